@@ -0,0 +1,239 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Represents the body of an HTTP message.
+#[derive(Default)]
+pub enum Body {
+  /// No body.
+  #[default]
+  Empty,
+  /// Body held in memory.
+  Bytes(Vec<u8>),
+  /// Body held in memory and shared, e.g. with a cache, so it is not copied.
+  Shared(Arc<[u8]>),
+  /// Body read from a file when it is sent.
+  File {
+    /// Opened file.
+    file: File,
+    /// Number of bytes to send from the file.
+    length: u64,
+  },
+  /// Body produced by a reader when it is sent.
+  Stream {
+    /// Reader of the body contents.
+    reader: Box<dyn Read + Send>,
+    /// Number of bytes the reader produces, if known in advance.
+    length: Option<u64>,
+  },
+}
+
+impl Body {
+  /// Opens the given file as a body.
+  ///
+  /// # Arguments
+  ///
+  /// * `path`: Path of the file.
+  pub fn from_file(path: impl AsRef<Path>) -> io::Result<Body> {
+    let file: File = File::open(path)?;
+    let metadata: std::fs::Metadata = file.metadata()?;
+    if !metadata.is_file() {
+      return Err(io::Error::new(io::ErrorKind::Other, "not a regular file"));
+    }
+
+    Ok(Body::File {
+      file,
+      length: metadata.len(),
+    })
+  }
+
+  /// Creates a body from a reader.
+  ///
+  /// # Arguments
+  ///
+  /// * `reader`: Reader of the body contents.
+  /// * `length`: Number of bytes the reader produces, if known.
+  pub fn from_reader(
+    reader: impl Read + Send + 'static,
+    length: Option<u64>,
+  ) -> Body {
+    Body::Stream {
+      reader: Box::new(reader),
+      length,
+    }
+  }
+
+  /// Gets the number of bytes of the body, if known without reading it.
+  pub fn len(&self) -> Option<u64> {
+    match self {
+      Body::Empty => Some(0),
+      Body::Bytes(bytes) => Some(bytes.len() as u64),
+      Body::Shared(bytes) => Some(bytes.len() as u64),
+      Body::File { length, .. } => Some(*length),
+      Body::Stream { length, .. } => *length,
+    }
+  }
+
+  /// Checks whether the body is known to be empty.
+  pub fn is_empty(&self) -> bool {
+    self.len() == Some(0)
+  }
+
+  /// Reads the whole body into memory.
+  pub fn into_bytes(self) -> io::Result<Vec<u8>> {
+    let mut bytes: Vec<u8> = Vec::new();
+    self.write_to(&mut bytes)?;
+    Ok(bytes)
+  }
+
+  /// Writes the body to the given stream and returns the number of bytes written.
+  ///
+  /// # Arguments
+  ///
+  /// * `write_stream`: Byte stream writer.
+  pub fn write_to(
+    self,
+    write_stream: &mut impl Write,
+  ) -> io::Result<u64> {
+    match self {
+      Body::Empty => Ok(0),
+      Body::Bytes(bytes) => {
+        write_stream.write_all(&bytes)?;
+        Ok(bytes.len() as u64)
+      }
+      Body::Shared(bytes) => {
+        write_stream.write_all(&bytes)?;
+        Ok(bytes.len() as u64)
+      }
+      Body::File { file, length } => io::copy(&mut file.take(length), write_stream),
+      Body::Stream {
+        reader,
+        length: Some(length),
+      } => io::copy(&mut reader.take(length), write_stream),
+      // Parts of a body of unknown length may be produced over time, so each one is
+      // passed on as soon as it is read
+      Body::Stream {
+        mut reader,
+        length: None,
+      } => {
+        let mut buffer: [u8; 8192] = [0; 8192];
+        let mut written: u64 = 0;
+        loop {
+          let read: usize = match reader.read(&mut buffer) {
+            Ok(0) => return Ok(written),
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+          };
+          write_stream.write_all(&buffer[..read])?;
+          write_stream.flush()?;
+          written += read as u64;
+        }
+      }
+    }
+  }
+}
+
+impl fmt::Debug for Body {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    match self {
+      Body::Empty => write!(f, "Empty"),
+      Body::Bytes(bytes) => write!(f, "Bytes({:?})", String::from_utf8_lossy(bytes)),
+      Body::Shared(bytes) => write!(f, "Shared({:?})", String::from_utf8_lossy(bytes)),
+      Body::File { length, .. } => write!(f, "File({} bytes)", length),
+      Body::Stream { length, .. } => write!(f, "Stream({:?} bytes)", length),
+    }
+  }
+}
+
+impl PartialEq for Body {
+  /// Compares bodies held in memory. File and stream bodies are never equal because
+  /// their contents are unknown until they are read.
+  fn eq(
+    &self,
+    other: &Self,
+  ) -> bool {
+    match (self, other) {
+      (Body::Empty, Body::Empty) => true,
+      (Body::Bytes(a), Body::Bytes(b)) => a == b,
+      (Body::Shared(a), Body::Shared(b)) => a == b,
+      _ => false,
+    }
+  }
+}
+
+impl From<Vec<u8>> for Body {
+  fn from(value: Vec<u8>) -> Self {
+    Body::Bytes(value)
+  }
+}
+
+impl From<Arc<[u8]>> for Body {
+  fn from(value: Arc<[u8]>) -> Self {
+    Body::Shared(value)
+  }
+}
+
+impl From<String> for Body {
+  fn from(value: String) -> Self {
+    Body::Bytes(value.into_bytes())
+  }
+}
+
+impl From<&str> for Body {
+  fn from(value: &str) -> Self {
+    Body::Bytes(value.as_bytes().to_vec())
+  }
+}
+
+impl<T: Into<Body>> From<Option<T>> for Body {
+  fn from(value: Option<T>) -> Self {
+    value.map_or(Body::Empty, Into::into)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_body_lengths() {
+    assert_eq!(Body::Empty.len(), Some(0));
+    assert_eq!(Body::from("héllo").len(), Some(6));
+    assert_eq!(Body::from(vec![0xff, 0x00]).len(), Some(2));
+    assert_eq!(Body::from(Arc::<[u8]>::from(&b"abc"[..])).len(), Some(3));
+    assert_eq!(Body::from(None::<String>), Body::Empty);
+    assert_eq!(Body::from_reader(io::empty(), None).len(), None);
+  }
+
+  #[test]
+  fn test_body_from_file() {
+    let path = std::env::temp_dir().join(format!("body-test-{}.bin", std::process::id()));
+    std::fs::write(&path, [0x89, b'P', b'N', b'G', 0xff]).unwrap();
+
+    let body = Body::from_file(&path).unwrap();
+    assert_eq!(body.len(), Some(5));
+    assert_eq!(
+      body.into_bytes().unwrap(),
+      vec![0x89, b'P', b'N', b'G', 0xff]
+    );
+
+    std::fs::remove_file(&path).unwrap();
+    assert!(Body::from_file(&path).is_err());
+    assert!(Body::from_file(std::env::temp_dir()).is_err());
+  }
+
+  #[test]
+  fn test_body_stream_with_length() {
+    let body = Body::from_reader(&b"0123456789"[..], Some(4));
+
+    let mut written: Vec<u8> = Vec::new();
+    assert_eq!(body.write_to(&mut written).unwrap(), 4);
+    assert_eq!(written, b"0123");
+  }
+}
@@ -0,0 +1,485 @@
+use std::io::{self, BufRead, Read, Write};
+
+use crate::header_map::HeaderMap;
+use crate::http_request::is_token_char;
+
+/// Maximum number of hexadecimal digits of a chunk size, so it fits in 64 bits.
+const MAX_CHUNK_SIZE_DIGITS: usize = 16;
+/// Maximum length of a chunk size line or a trailer line read by a [`ChunkedReader`].
+const MAX_LINE_LENGTH: u64 = 8 * 1024;
+
+/// Represents the errors found while decoding a chunked body.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DecodeError {
+  /// The bytes end before the body is complete. Holds the number of body bytes
+  /// announced so far, including the chunk being received.
+  Incomplete(usize),
+  /// A chunk size line, the end of a chunk or a trailer line is malformed.
+  Invalid,
+}
+
+/// Represents a chunked body decoded from the bytes of a message.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DecodedBody {
+  /// Contents of the body, without the chunk framing.
+  pub data: Vec<u8>,
+  /// Fields of the trailer section, sent after the last chunk.
+  pub trailers: HeaderMap,
+  /// Number of bytes taken by the encoded body, including the trailer section.
+  pub length: usize,
+}
+
+/// Decodes a body with the chunked transfer coding (RFC 9112, section 7.1) from the
+/// beginning of the given bytes. Chunk extensions are ignored.
+///
+/// # Arguments
+///
+/// * `bytes`: Bytes following the headers of the message.
+pub fn decode(bytes: &[u8]) -> Result<DecodedBody, DecodeError> {
+  let mut data: Vec<u8> = Vec::new();
+  let mut position: usize = 0;
+
+  loop {
+    let line: &[u8] =
+      next_line(bytes, &mut position).ok_or(DecodeError::Incomplete(data.len()))?;
+    let size: usize = parse_chunk_size(line)?;
+    if size == 0 {
+      break;
+    }
+
+    let end: usize = position.checked_add(size).ok_or(DecodeError::Invalid)?;
+    let incomplete: DecodeError =
+      DecodeError::Incomplete(data.len().saturating_add(size));
+    if bytes.len() < end {
+      return Err(incomplete);
+    }
+    data.extend_from_slice(&bytes[position..end]);
+    position = end;
+
+    // The chunk data is followed by a line terminator
+    match next_line(bytes, &mut position) {
+      Some([]) => {}
+      Some(_) => return Err(DecodeError::Invalid),
+      None => return Err(incomplete),
+    }
+  }
+
+  // The trailer section ends with an empty line
+  let mut trailers: HeaderMap = HeaderMap::new();
+  loop {
+    let line: &[u8] =
+      next_line(bytes, &mut position).ok_or(DecodeError::Incomplete(data.len()))?;
+    if line.is_empty() {
+      break;
+    }
+    let (name, value) = parse_trailer(line)?;
+    trailers.append(name, value);
+  }
+
+  Ok(DecodedBody {
+    data,
+    trailers,
+    length: position,
+  })
+} // end fn decode()
+
+/// Represents the progress of checking the framing of a chunked body received in parts,
+/// so each part is looked at only once however many times the check runs. The body is
+/// then decoded once with [`decode`].
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct ChunkedScanner {
+  /// Position of the next chunk size line or trailer line.
+  position: usize,
+  /// Number of body bytes in the chunks checked so far.
+  data_length: usize,
+  /// Whether the last chunk was checked, so only the trailer section is left.
+  in_trailers: bool,
+}
+
+impl ChunkedScanner {
+  /// Creates a new [`ChunkedScanner`] object for a body not received yet.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Checks the bytes received so far, from where the previous call stopped, and
+  /// returns the number of bytes taken by the encoded body once it is complete. The
+  /// bytes must start with the body, and only grow between calls.
+  ///
+  /// # Arguments
+  ///
+  /// * `bytes`: Bytes following the headers of the message.
+  pub fn scan(
+    &mut self,
+    bytes: &[u8],
+  ) -> Result<usize, DecodeError> {
+    loop {
+      let mut position: usize = self.position;
+      let Some(line) = next_line(bytes, &mut position) else {
+        // A line must end before it grows too long
+        if (bytes.len() - self.position) as u64 > MAX_LINE_LENGTH {
+          return Err(DecodeError::Invalid);
+        }
+        return Err(DecodeError::Incomplete(self.data_length));
+      };
+
+      if self.in_trailers {
+        // The trailer section ends with an empty line
+        if line.is_empty() {
+          self.position = position;
+          return Ok(position);
+        }
+        parse_trailer(line)?;
+        self.position = position;
+        continue;
+      }
+
+      let size: usize = parse_chunk_size(line)?;
+      if size == 0 {
+        self.in_trailers = true;
+        self.position = position;
+        continue;
+      }
+
+      // The chunk data is followed by a line terminator
+      let end: usize = position.checked_add(size).ok_or(DecodeError::Invalid)?;
+      let incomplete: DecodeError =
+        DecodeError::Incomplete(self.data_length.saturating_add(size));
+      if bytes.len() < end {
+        return Err(incomplete);
+      }
+      let mut position: usize = end;
+      match next_line(bytes, &mut position) {
+        Some([]) => {}
+        Some(_) => return Err(DecodeError::Invalid),
+        None => return Err(incomplete),
+      }
+      self.data_length += size;
+      self.position = position;
+    }
+  } // end fn scan()
+}
+
+/// Parses a trailer line into its field name and value.
+///
+/// # Arguments
+///
+/// * `line`: Trailer line, without the line terminator.
+fn parse_trailer(line: &[u8]) -> Result<(&str, &str), DecodeError> {
+  let line: &str = std::str::from_utf8(line).map_err(|_| DecodeError::Invalid)?;
+  let (name, value) = line.split_once(':').ok_or(DecodeError::Invalid)?;
+  if name.is_empty() || !name.bytes().all(is_token_char) {
+    return Err(DecodeError::Invalid);
+  }
+  Ok((name, value.trim_matches([' ', '\t'])))
+}
+
+/// Returns the next line of the given bytes, without its line terminator (CRLF or a
+/// bare LF), and moves the position to the beginning of the following line.
+///
+/// # Arguments
+///
+/// * `bytes`: Bytes to read.
+/// * `position`: Position where the line starts.
+fn next_line<'b>(
+  bytes: &'b [u8],
+  position: &mut usize,
+) -> Option<&'b [u8]> {
+  let start: usize = *position;
+  let end: usize = start + bytes[start..].iter().position(|b| *b == b'\n')?;
+  *position = end + 1;
+
+  let line: &[u8] = &bytes[start..end];
+  Some(line.strip_suffix(b"\r").unwrap_or(line))
+}
+
+/// Parses the size of a chunk from its size line, ignoring any extensions.
+///
+/// # Arguments
+///
+/// * `line`: Chunk size line, without the line terminator.
+fn parse_chunk_size(line: &[u8]) -> Result<usize, DecodeError> {
+  let digits: &[u8] = line.split(|b| *b == b';').next().unwrap_or_default();
+  let digits: &[u8] = match digits.iter().rposition(|b| !matches!(b, b' ' | b'\t')) {
+    Some(last) => &digits[..=last],
+    None => &[],
+  };
+
+  if digits.is_empty()
+    || digits.len() > MAX_CHUNK_SIZE_DIGITS
+    || !digits.iter().all(u8::is_ascii_hexdigit)
+  {
+    return Err(DecodeError::Invalid);
+  }
+  let digits: &str = std::str::from_utf8(digits).map_err(|_| DecodeError::Invalid)?;
+  let size: u64 = u64::from_str_radix(digits, 16).map_err(|_| DecodeError::Invalid)?;
+
+  usize::try_from(size).map_err(|_| DecodeError::Invalid)
+}
+
+/// Represents a writer that encodes everything written to it with the chunked transfer
+/// coding. Each write becomes one chunk, so the data reaches the client as soon as it is
+/// produced.
+pub struct ChunkedWriter<W: Write> {
+  /// Stream receiving the encoded data.
+  inner: W,
+}
+
+impl<W: Write> ChunkedWriter<W> {
+  /// Creates a new [`ChunkedWriter`] object.
+  ///
+  /// # Arguments
+  ///
+  /// * `inner`: Stream receiving the encoded data.
+  pub fn new(inner: W) -> Self {
+    Self { inner }
+  }
+
+  /// Writes the last chunk and an empty trailer section, and returns the stream.
+  pub fn finish(self) -> io::Result<W> {
+    self.finish_with_trailers(&HeaderMap::new())
+  }
+
+  /// Writes the last chunk followed by the given trailer fields, and returns the
+  /// stream.
+  ///
+  /// # Arguments
+  ///
+  /// * `trailers`: Fields of the trailer section.
+  pub fn finish_with_trailers(
+    mut self,
+    trailers: &HeaderMap,
+  ) -> io::Result<W> {
+    let mut end: String = "0\r\n".to_string();
+    for (name, value) in trailers.iter() {
+      end = format!("{}{}:{}\r\n", end, name, value);
+    }
+    end.push_str("\r\n");
+
+    self.inner.write_all(end.as_bytes())?;
+    self.inner.flush()?;
+    Ok(self.inner)
+  }
+}
+
+impl<W: Write> Write for ChunkedWriter<W> {
+  fn write(
+    &mut self,
+    buf: &[u8],
+  ) -> io::Result<usize> {
+    // An empty chunk would end the body
+    if buf.is_empty() {
+      return Ok(0);
+    }
+
+    self
+      .inner
+      .write_all(format!("{:x}\r\n", buf.len()).as_bytes())?;
+    self.inner.write_all(buf)?;
+    self.inner.write_all(b"\r\n")?;
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.inner.flush()
+  }
+}
+
+/// Represents a reader that decodes a body with the chunked transfer coding as it is
+/// read, so the data can be passed on before the whole body is received. The trailer
+/// section is read and discarded.
+pub struct ChunkedReader<R: BufRead> {
+  /// Stream with the encoded data.
+  inner: R,
+  /// Number of bytes left in the current chunk.
+  remaining: u64,
+  /// Whether the last chunk and the trailer section were read.
+  done: bool,
+}
+
+impl<R: BufRead> ChunkedReader<R> {
+  /// Creates a new [`ChunkedReader`] object.
+  ///
+  /// # Arguments
+  ///
+  /// * `inner`: Stream with the encoded data, positioned after the headers.
+  pub fn new(inner: R) -> Self {
+    Self {
+      inner,
+      remaining: 0,
+      done: false,
+    }
+  }
+
+  /// Reads the next line, without its line terminator (CRLF or a bare LF).
+  fn read_line(&mut self) -> io::Result<Vec<u8>> {
+    let mut line: Vec<u8> = Vec::new();
+    (&mut self.inner)
+      .take(MAX_LINE_LENGTH)
+      .read_until(b'\n', &mut line)?;
+    if line.pop() != Some(b'\n') {
+      return Err(io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "chunked body ends early",
+      ));
+    }
+    if line.last() == Some(&b'\r') {
+      line.pop();
+    }
+    Ok(line)
+  }
+}
+
+impl<R: BufRead> Read for ChunkedReader<R> {
+  fn read(
+    &mut self,
+    buf: &mut [u8],
+  ) -> io::Result<usize> {
+    if self.done || buf.is_empty() {
+      return Ok(0);
+    }
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid chunked body");
+
+    if self.remaining == 0 {
+      let size: usize = parse_chunk_size(&self.read_line()?).map_err(|_| invalid())?;
+      if size == 0 {
+        // The trailer section ends with an empty line
+        while !self.read_line()?.is_empty() {}
+        self.done = true;
+        return Ok(0);
+      }
+      self.remaining = size as u64;
+    }
+
+    let length: usize = buf.len().min(self.remaining as usize);
+    let read: usize = self.inner.read(&mut buf[..length])?;
+    if read == 0 {
+      return Err(io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "chunked body ends early",
+      ));
+    }
+    self.remaining -= read as u64;
+
+    // The chunk data is followed by a line terminator
+    if self.remaining == 0 && !self.read_line()?.is_empty() {
+      return Err(invalid());
+    }
+    Ok(read)
+  } // end fn read()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_decode() {
+    let bytes =
+      b"5;name=value\r\nHello\r\n7\r\n, World\r\n0\r\nExpires: never\r\n\r\nNEXT";
+    let body = decode(bytes).unwrap();
+
+    assert_eq!(body.data, b"Hello, World");
+    assert_eq!(body.trailers.get("expires"), Some("never"));
+    assert_eq!(body.length, bytes.len() - 4);
+
+    let body = decode(b"A\nabcdefghij\n0\n\n").unwrap();
+    assert_eq!(body.data, b"abcdefghij");
+    assert!(body.trailers.is_empty());
+  }
+
+  #[test]
+  fn test_decode_incomplete() {
+    assert_eq!(decode(b""), Err(DecodeError::Incomplete(0)));
+    assert_eq!(decode(b"5\r\nHel"), Err(DecodeError::Incomplete(5)));
+    assert_eq!(
+      decode(b"5\r\nHello\r\n10\r\n"),
+      Err(DecodeError::Incomplete(21))
+    );
+    assert_eq!(
+      decode(b"5\r\nHello\r\n0\r\nA: b\r\n"),
+      Err(DecodeError::Incomplete(5))
+    );
+  }
+
+  #[test]
+  fn test_decode_invalid() {
+    let invalid: [&[u8]; 6] = [
+      b"x\r\n",
+      b"-1\r\n",
+      b"\r\n",
+      b"11111111111111111\r\n",
+      b"2\r\nabc\r\n0\r\n\r\n",
+      b"0\r\nno colon\r\n\r\n",
+    ];
+
+    for bytes in invalid {
+      assert_eq!(decode(bytes), Err(DecodeError::Invalid));
+    }
+  }
+
+  #[test]
+  fn test_scanner() {
+    let bytes =
+      b"5;name=value\r\nHello\r\n7\r\n, World\r\n0\r\nExpires: never\r\n\r\nNEXT";
+    let length: usize = bytes.len() - 4;
+
+    // Fed a byte at a time, the scanner agrees with the decoder
+    let mut scanner = ChunkedScanner::new();
+    for end in 0..length {
+      let expected = decode(&bytes[..end]).unwrap_err();
+      assert_eq!(scanner.scan(&bytes[..end]), Err(expected));
+    }
+    assert_eq!(scanner.scan(&bytes[..length]), Ok(length));
+    assert_eq!(decode(bytes).unwrap().length, length);
+
+    for invalid in [&b"2\r\nabc\r\n"[..], b"0\r\nno colon\r\n", b"x\r\n"] {
+      assert_eq!(
+        ChunkedScanner::new().scan(invalid),
+        Err(DecodeError::Invalid)
+      );
+    }
+    let endless_line = vec![b'1'; MAX_LINE_LENGTH as usize + 1];
+    assert_eq!(
+      ChunkedScanner::new().scan(&endless_line),
+      Err(DecodeError::Invalid)
+    );
+  }
+
+  #[test]
+  fn test_chunked_reader() {
+    let encoded: &[u8] = b"4\r\nWiki\r\n6;ext=1\r\npedia \r\nE\r\nin \r\n\r\nchunks.\r\n0\r\nExpires: never\r\n\r\nnext";
+    let mut reader = ChunkedReader::new(io::BufReader::with_capacity(3, encoded));
+    let mut data: Vec<u8> = Vec::new();
+    reader.read_to_end(&mut data).unwrap();
+    assert_eq!(data, b"Wikipedia in \r\n\r\nchunks.");
+
+    // The rest of the stream is left unread
+    let mut rest: Vec<u8> = Vec::new();
+    reader.inner.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, b"next");
+
+    let mut truncated = ChunkedReader::new(&b"a\r\nshort"[..]);
+    let error = truncated.read_to_end(&mut Vec::new()).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    let mut invalid = ChunkedReader::new(&b"zz\r\n"[..]);
+    let error = invalid.read_to_end(&mut Vec::new()).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+  }
+
+  #[test]
+  fn test_chunked_writer_round_trip() {
+    let mut writer = ChunkedWriter::new(Vec::new());
+    writer.write_all(b"Hello").unwrap();
+    writer.write_all(b"").unwrap();
+    writer.write_all(&[b'x'; 20]).unwrap();
+    let trailers = HeaderMap::from([("Checksum", "abc")]);
+    let encoded = writer.finish_with_trailers(&trailers).unwrap();
+
+    assert!(encoded.starts_with(b"5\r\nHello\r\n14\r\nxxxx"));
+    let body = decode(&encoded).unwrap();
+    assert_eq!(body.data, [b"Hello".as_slice(), &[b'x'; 20]].concat());
+    assert_eq!(body.trailers, trailers);
+    assert_eq!(body.length, encoded.len());
+  }
+}
@@ -0,0 +1,905 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::body::Body;
+use crate::chunked::{self, DecodeError};
+use crate::header_map::HeaderMap;
+use crate::http_request::{HttpRequest, Method};
+use crate::http_response::HttpResponse;
+use crate::status_code::{self, StatusCode};
+
+/// Default number of redirects followed before giving up.
+const DEFAULT_MAX_REDIRECTS: usize = 10;
+/// Default time allowed to connect, and for each read or write.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Maximum size of the status line and headers of a response.
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+/// Number of bytes read from the connection at a time.
+const READ_BUFFER_SIZE: usize = 8192;
+
+/// Represents the errors of a request made by the [`Client`].
+#[derive(Debug)]
+pub enum ClientError {
+  /// The URL is malformed.
+  InvalidUrl(String),
+  /// The URL uses a scheme other than `http`.
+  UnsupportedScheme(String),
+  /// The request could not be built from the given parts.
+  InvalidRequest,
+  /// Connecting, reading or writing failed.
+  Io(io::Error),
+  /// The server did not answer in time.
+  Timeout,
+  /// The server closed the connection before sending a response.
+  ConnectionClosed,
+  /// The response is malformed.
+  InvalidResponse(&'static str),
+  /// The server redirected more times than allowed.
+  TooManyRedirects(usize),
+}
+
+impl fmt::Display for ClientError {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    match self {
+      ClientError::InvalidUrl(url) => write!(f, "invalid URL: {}", url),
+      ClientError::UnsupportedScheme(scheme) => {
+        write!(f, "unsupported scheme: {}", scheme)
+      }
+      ClientError::InvalidRequest => write!(f, "invalid request"),
+      ClientError::Io(e) => write!(f, "{}", e),
+      ClientError::Timeout => write!(f, "operation timed out"),
+      ClientError::ConnectionClosed => write!(f, "connection closed by the server"),
+      ClientError::InvalidResponse(reason) => write!(f, "invalid response: {}", reason),
+      ClientError::TooManyRedirects(count) => {
+        write!(f, "stopped after {} redirects", count)
+      }
+    }
+  }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<io::Error> for ClientError {
+  fn from(value: io::Error) -> Self {
+    match value.kind() {
+      io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => ClientError::Timeout,
+      _ => ClientError::Io(value),
+    }
+  }
+}
+
+/// Represents an `http` URL split into the parts needed to send a request.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Url {
+  /// Host name or address. IPv6 addresses keep their brackets.
+  pub host: String,
+  /// TCP port, 80 unless given.
+  pub port: u16,
+  /// Request target: the path and the query, without the fragment.
+  pub target: String,
+}
+
+impl Url {
+  /// Parses an absolute `http` URL, e.g. `http://localhost:3000/api?x=1`.
+  ///
+  /// # Arguments
+  ///
+  /// * `text`: Text of the URL.
+  pub fn parse(text: &str) -> Result<Url, ClientError> {
+    let invalid = || ClientError::InvalidUrl(text.to_string());
+
+    let (scheme, rest) = text.split_once("://").ok_or_else(invalid)?;
+    if !scheme.eq_ignore_ascii_case("http") {
+      return Err(ClientError::UnsupportedScheme(scheme.to_string()));
+    }
+
+    let rest: &str = rest.split('#').next().unwrap_or_default();
+    let (authority, target) = match rest.find(['/', '?']) {
+      Some(i) => rest.split_at(i),
+      None => (rest, ""),
+    };
+    let target: String = match target {
+      "" => "/".to_string(),
+      t if t.starts_with('?') => format!("/{}", t),
+      t => t.to_string(),
+    };
+
+    // User information is not supported
+    if authority.contains('@') {
+      return Err(invalid());
+    }
+    let (host, port) = match authority.rfind(':') {
+      // The colon of an IPv6 address is not a port separator
+      Some(i) if !authority[i..].contains(']') => (&authority[..i], &authority[i + 1..]),
+      _ => (authority, ""),
+    };
+    let port: u16 = match port {
+      "" => 80,
+      port => port.parse().map_err(|_| invalid())?,
+    };
+    if host.is_empty() || host.contains(char::is_whitespace) {
+      return Err(invalid());
+    }
+
+    Ok(Url {
+      host: host.to_ascii_lowercase(),
+      port,
+      target,
+    })
+  } // end fn parse()
+
+  /// Gets the host and the port as sent in the `Host` header; the default port is left
+  /// out.
+  pub fn authority(&self) -> String {
+    match self.port {
+      80 => self.host.clone(),
+      port => format!("{}:{}", self.host, port),
+    }
+  }
+
+  /// Resolves a reference found in a response, such as a `Location` header, against
+  /// this URL.
+  ///
+  /// # Arguments
+  ///
+  /// * `reference`: Absolute URL, network-path (`//host/path`), absolute path or
+  ///   relative path.
+  pub fn join(
+    &self,
+    reference: &str,
+  ) -> Result<Url, ClientError> {
+    let reference: &str = reference.trim();
+
+    if reference.contains("://") {
+      Url::parse(reference)
+    } else if reference.starts_with("//") {
+      Url::parse(&format!("http:{}", reference))
+    } else if reference.starts_with('/') {
+      Url::parse(&format!("http://{}{}", self.authority(), reference))
+    } else {
+      // Relative to the directory of the current path
+      let path: &str = self.target.split('?').next().unwrap_or_default();
+      let directory: &str = &path[..path.rfind('/').map_or(0, |i| i + 1)];
+      Url::parse(&format!(
+        "http://{}{}{}",
+        self.authority(),
+        directory,
+        reference
+      ))
+    }
+  } // end fn join()
+}
+
+impl fmt::Display for Url {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    write!(f, "http://{}{}", self.authority(), self.target)
+  }
+}
+
+/// Represents an open connection to a server, with the bytes received but not used yet.
+struct Connection {
+  /// Stream of the connection.
+  stream: TcpStream,
+  /// Bytes received past the last response.
+  buffer: Vec<u8>,
+}
+
+/// Represents a response parsed from the bytes received on a connection.
+struct ParsedResponse {
+  /// Parsed response, with the whole body in memory.
+  response: HttpResponse,
+  /// Number of bytes the response takes.
+  length: usize,
+  /// Whether the connection can be used for another request.
+  reusable: bool,
+}
+
+/// Represents a blocking HTTP/1.1 client. Connections are kept open after a response and
+/// reused by the next request to the same host.
+pub struct Client {
+  /// Time allowed to connect, and for each read or write.
+  timeout: Duration,
+  /// Number of redirects followed before giving up; `0` returns the redirect response.
+  max_redirects: usize,
+  /// Value of the `User-Agent` header.
+  user_agent: String,
+  /// Idle connections by host and port.
+  connections: Mutex<HashMap<String, Connection>>,
+}
+
+impl Default for Client {
+  fn default() -> Self {
+    Self {
+      timeout: DEFAULT_TIMEOUT,
+      max_redirects: DEFAULT_MAX_REDIRECTS,
+      user_agent: format!("http/{}", env!("CARGO_PKG_VERSION")),
+      connections: Mutex::new(HashMap::new()),
+    }
+  }
+}
+
+impl Client {
+  /// Creates a new [`Client`] object with the default timeout and redirect limit.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Sets the time allowed to connect, and for each read or write.
+  ///
+  /// # Arguments
+  ///
+  /// * `timeout`: Time allowed for each operation.
+  pub fn with_timeout(
+    mut self,
+    timeout: Duration,
+  ) -> Self {
+    self.timeout = timeout;
+    self
+  }
+
+  /// Sets the number of redirects followed before giving up. With `0`, redirects are not
+  /// followed and the redirect response is returned.
+  ///
+  /// # Arguments
+  ///
+  /// * `max_redirects`: Maximum number of redirects.
+  pub fn with_max_redirects(
+    mut self,
+    max_redirects: usize,
+  ) -> Self {
+    self.max_redirects = max_redirects;
+    self
+  }
+
+  /// Sets the value of the `User-Agent` header.
+  ///
+  /// # Arguments
+  ///
+  /// * `user_agent`: Name of the client.
+  pub fn with_user_agent(
+    mut self,
+    user_agent: &str,
+  ) -> Self {
+    self.user_agent = user_agent.to_string();
+    self
+  }
+
+  /// Starts a GET request.
+  ///
+  /// # Arguments
+  ///
+  /// * `url`: Absolute `http` URL.
+  pub fn get(
+    &self,
+    url: &str,
+  ) -> RequestBuilder<'_> {
+    self.request(Method::GET, url)
+  }
+
+  /// Starts a request with the given method.
+  ///
+  /// # Arguments
+  ///
+  /// * `method`: HTTP method of the request.
+  /// * `url`: Absolute `http` URL.
+  pub fn request(
+    &self,
+    method: Method,
+    url: &str,
+  ) -> RequestBuilder<'_> {
+    RequestBuilder {
+      client: self,
+      method,
+      url: url.to_string(),
+      headers: HeaderMap::new(),
+      body: "".to_string(),
+    }
+  }
+
+  /// Sends a request and follows the redirects of the responses, up to the limit.
+  ///
+  /// # Arguments
+  ///
+  /// * `method`: HTTP method of the request.
+  /// * `url`: URL of the request.
+  /// * `headers`: Headers given by the caller.
+  /// * `body`: Body of the request.
+  pub fn execute(
+    &self,
+    method: Method,
+    url: Url,
+    headers: HeaderMap,
+    body: String,
+  ) -> Result<HttpResponse, ClientError> {
+    let (mut method, mut url, mut headers, mut body) = (method, url, headers, body);
+    let mut redirects: usize = 0;
+
+    loop {
+      let response: HttpResponse = self.send(&method, &url, &headers, &body)?;
+      let location: Option<&str> = response.header("Location");
+      let code: u16 = response.status_code().as_u16();
+      let (true, Some(location)) = (self.max_redirects > 0, location) else {
+        return Ok(response);
+      };
+      if !matches!(code, 301 | 302 | 303 | 307 | 308) {
+        return Ok(response);
+      }
+
+      redirects += 1;
+      if redirects > self.max_redirects {
+        return Err(ClientError::TooManyRedirects(self.max_redirects));
+      }
+
+      let next: Url = url.join(location)?;
+      // 303 always turns into a GET; 301 and 302 do so for POST, as browsers do
+      let becomes_get: bool = match code {
+        303 => method != Method::HEAD,
+        301 | 302 => method == Method::POST,
+        _ => false,
+      };
+      if becomes_get {
+        method = Method::GET;
+        body.clear();
+        headers.remove("Content-Type");
+      }
+      // Credentials are only sent to the host they were given for
+      if next.host != url.host || next.port != url.port {
+        headers.remove("Authorization");
+        headers.remove("Cookie");
+      }
+      url = next;
+    }
+  } // end fn execute()
+
+  /// Sends one request, reusing an idle connection to the host if there is one. A request
+  /// with an idempotent method is retried once on a new connection if the idle one turns
+  /// out to be closed.
+  ///
+  /// # Arguments
+  ///
+  /// * `method`: HTTP method of the request.
+  /// * `url`: URL of the request.
+  /// * `headers`: Headers given by the caller.
+  /// * `body`: Body of the request.
+  fn send(
+    &self,
+    method: &Method,
+    url: &Url,
+    headers: &HeaderMap,
+    body: &str,
+  ) -> Result<HttpResponse, ClientError> {
+    let mut request: HttpRequest = HttpRequest::new(method.clone(), &url.target)
+      .map_err(|_| ClientError::InvalidRequest)?;
+    request.headers.insert("Host", &url.authority());
+    request.headers.insert("User-Agent", &self.user_agent);
+    request.headers.insert("Accept", "*/*");
+    for (name, _) in headers.iter() {
+      request.headers.remove(name);
+    }
+    for (name, value) in headers.iter() {
+      request.headers.append(name, value);
+    }
+    request.message_body = body.as_bytes().to_vec();
+
+    let mut bytes: Vec<u8> = Vec::new();
+    request.write_to(&mut bytes)?;
+    let head_only: bool = *method == Method::HEAD;
+
+    let key: String = format!("{}:{}", url.host, url.port);
+    let idle: Option<Connection> = self.connections.lock().unwrap().remove(&key);
+    let reused: bool = idle.is_some();
+    let mut connection: Connection = match idle {
+      Some(connection) => connection,
+      None => self.connect(url)?,
+    };
+
+    let parsed: ParsedResponse = match Self::exchange(&mut connection, &bytes, head_only)
+    {
+      Err(e) if reused && Self::is_stale(&e) && Self::is_idempotent(method) => {
+        connection = self.connect(url)?;
+        Self::exchange(&mut connection, &bytes, head_only)?
+      }
+      result => result?,
+    };
+
+    let closes: bool = request.headers.connection().iter().any(|o| o == "close");
+    if parsed.reusable && !closes {
+      self.connections.lock().unwrap().insert(key, connection);
+    }
+    Ok(parsed.response)
+  } // end fn send()
+
+  /// Opens a connection to the host of a URL, trying each of its addresses.
+  ///
+  /// # Arguments
+  ///
+  /// * `url`: URL of the request.
+  fn connect(
+    &self,
+    url: &Url,
+  ) -> Result<Connection, ClientError> {
+    let host: &str = url.host.trim_start_matches('[').trim_end_matches(']');
+    let addresses: Vec<SocketAddr> = (host, url.port).to_socket_addrs()?.collect();
+
+    let mut last_error: ClientError = ClientError::InvalidUrl(url.to_string());
+    for address in addresses {
+      match TcpStream::connect_timeout(&address, self.timeout) {
+        Ok(stream) => {
+          stream.set_read_timeout(Some(self.timeout))?;
+          stream.set_write_timeout(Some(self.timeout))?;
+          stream.set_nodelay(true)?;
+          return Ok(Connection {
+            stream,
+            buffer: Vec::new(),
+          });
+        }
+        Err(e) => last_error = e.into(),
+      }
+    }
+    Err(last_error)
+  }
+
+  /// Writes a request on a connection and reads the response, skipping interim
+  /// (informational) responses.
+  ///
+  /// # Arguments
+  ///
+  /// * `connection`: Connection to the server.
+  /// * `request`: Bytes of the request.
+  /// * `head_only`: Whether the request is a HEAD request, whose response has no body.
+  fn exchange(
+    connection: &mut Connection,
+    request: &[u8],
+    head_only: bool,
+  ) -> Result<ParsedResponse, ClientError> {
+    std::io::Write::write_all(&mut connection.stream, request)?;
+
+    let mut chunk: [u8; READ_BUFFER_SIZE] = [0; READ_BUFFER_SIZE];
+    let mut received: bool = false;
+    loop {
+      if let Some(parsed) = parse_response(&connection.buffer, head_only, false)? {
+        connection.buffer.drain(..parsed.length);
+        let status: &StatusCode = parsed.response.status_code();
+        if status.is_informational() && status != &StatusCode::SWITCHING_PROTOCOLS {
+          continue;
+        }
+        return Ok(parsed);
+      }
+
+      let read: usize = connection.stream.read(&mut chunk)?;
+      if read == 0 {
+        if !received && connection.buffer.is_empty() {
+          return Err(ClientError::ConnectionClosed);
+        }
+        let parsed: ParsedResponse = parse_response(&connection.buffer, head_only, true)?
+          .ok_or(ClientError::InvalidResponse("incomplete response"))?;
+        connection.buffer.clear();
+        return Ok(parsed);
+      }
+      received = true;
+      connection.buffer.extend_from_slice(&chunk[..read]);
+    }
+  } // end fn exchange()
+
+  /// Checks whether an error shows that a reused connection had been closed by the
+  /// server while idle.
+  ///
+  /// # Arguments
+  ///
+  /// * `error`: Error of the exchange.
+  fn is_stale(error: &ClientError) -> bool {
+    match error {
+      ClientError::ConnectionClosed => true,
+      ClientError::Io(e) => matches!(
+        e.kind(),
+        io::ErrorKind::BrokenPipe
+          | io::ErrorKind::ConnectionReset
+          | io::ErrorKind::ConnectionAborted
+      ),
+      _ => false,
+    }
+  }
+
+  /// Checks whether a request with the given method can be sent again safely.
+  ///
+  /// # Arguments
+  ///
+  /// * `method`: HTTP method of the request.
+  fn is_idempotent(method: &Method) -> bool {
+    matches!(
+      method,
+      Method::GET
+        | Method::HEAD
+        | Method::PUT
+        | Method::DELETE
+        | Method::OPTIONS
+        | Method::TRACE
+    )
+  }
+}
+
+/// Represents a request being built, sent with [`RequestBuilder::send`].
+pub struct RequestBuilder<'c> {
+  /// Client sending the request.
+  client: &'c Client,
+  /// HTTP method of the request.
+  method: Method,
+  /// URL of the request, parsed when it is sent.
+  url: String,
+  /// Headers of the request.
+  headers: HeaderMap,
+  /// Body of the request.
+  body: String,
+}
+
+impl RequestBuilder<'_> {
+  /// Adds a header to the request, after any others with the same name.
+  ///
+  /// # Arguments
+  ///
+  /// * `name`: Name of the header.
+  /// * `value`: Value of the header.
+  pub fn with_header(
+    mut self,
+    name: &str,
+    value: &str,
+  ) -> Self {
+    self.headers.append(name, value);
+    self
+  }
+
+  /// Sets the body of the request.
+  ///
+  /// # Arguments
+  ///
+  /// * `body`: Contents of the body.
+  pub fn with_body(
+    mut self,
+    body: impl Into<String>,
+  ) -> Self {
+    self.body = body.into();
+    self
+  }
+
+  /// Sends the request and returns the final response, with the whole body in memory.
+  pub fn send(self) -> Result<HttpResponse, ClientError> {
+    let url: Url = Url::parse(&self.url)?;
+    self
+      .client
+      .execute(self.method, url, self.headers, self.body)
+  }
+}
+
+/// Parses a response from the beginning of the given bytes. Returns `None` if more bytes
+/// are needed.
+///
+/// # Arguments
+///
+/// * `bytes`: Bytes received on the connection.
+/// * `head_only`: Whether the request was a HEAD request, whose response has no body.
+/// * `eof`: Whether the server closed the connection, which ends a body without framing.
+fn parse_response(
+  bytes: &[u8],
+  head_only: bool,
+  eof: bool,
+) -> Result<Option<ParsedResponse>, ClientError> {
+  let Some((head, head_length)) = split_head(bytes) else {
+    if bytes.len() > MAX_HEAD_SIZE {
+      return Err(ClientError::InvalidResponse("response head too large"));
+    }
+    return Ok(None);
+  };
+  let head: &str = std::str::from_utf8(head)
+    .map_err(|_| ClientError::InvalidResponse("response head is not valid UTF-8"))?;
+  let mut lines = head.lines();
+
+  // Status line: "HTTP/1.1 200 OK"; the reason phrase may be empty
+  let status_line: &str = lines.next().unwrap_or_default();
+  let mut parts = status_line.splitn(3, ' ');
+  let version: &str = parts.next().unwrap_or_default();
+  let code: &str = parts.next().unwrap_or_default();
+  let reason: &str = parts.next().unwrap_or_default().trim();
+  if !version.starts_with("HTTP/1.") || code.len() != 3 {
+    return Err(ClientError::InvalidResponse("invalid status line"));
+  }
+  let code: u16 = code
+    .parse()
+    .map_err(|_| ClientError::InvalidResponse("invalid status code"))?;
+  let status: StatusCode = match status_code::canonical_reason(code) {
+    Some(canonical) if canonical == reason || reason.is_empty() => {
+      StatusCode::from_u16(code)
+    }
+    _ => StatusCode::with_reason(code, reason),
+  }
+  .ok_or(ClientError::InvalidResponse("invalid status code"))?;
+
+  let mut headers: HeaderMap = HeaderMap::new();
+  for line in lines {
+    let (name, value) = line
+      .split_once(':')
+      .ok_or(ClientError::InvalidResponse("invalid header line"))?;
+    headers.append(name.trim(), value.trim());
+  }
+
+  let options: Vec<String> = headers.connection();
+  let mut reusable: bool = if version == "HTTP/1.0" {
+    options.iter().any(|o| o == "keep-alive")
+  } else {
+    !options.iter().any(|o| o == "close")
+  };
+
+  // Framing of the body (RFC 9112, section 6.3)
+  let rest: &[u8] = &bytes[head_length..];
+  let bodiless: bool = head_only
+    || status.is_informational()
+    || status == StatusCode::NO_CONTENT
+    || status == StatusCode::NOT_MODIFIED;
+  let chunked: bool = headers
+    .get_all("Transfer-Encoding")
+    .iter()
+    .flat_map(|v| v.split(','))
+    .last()
+    .is_some_and(|c| c.trim().eq_ignore_ascii_case("chunked"));
+  let (body, body_length): (Vec<u8>, usize) = if bodiless {
+    (Vec::new(), 0)
+  } else if chunked {
+    match chunked::decode(rest) {
+      Ok(decoded) => {
+        for (name, value) in decoded.trailers.iter() {
+          headers.append(name, value);
+        }
+        (decoded.data, decoded.length)
+      }
+      Err(DecodeError::Incomplete(_)) if !eof => return Ok(None),
+      Err(DecodeError::Incomplete(_)) => {
+        return Err(ClientError::InvalidResponse("incomplete chunked body"))
+      }
+      Err(DecodeError::Invalid) => {
+        return Err(ClientError::InvalidResponse("invalid chunked body"))
+      }
+    }
+  } else if headers.contains("Transfer-Encoding") || !headers.contains("Content-Length") {
+    // The body ends when the server closes the connection
+    if !eof {
+      return Ok(None);
+    }
+    reusable = false;
+    (rest.to_vec(), rest.len())
+  } else {
+    let length: usize = headers
+      .content_length()
+      .ok_or(ClientError::InvalidResponse(
+        "invalid Content-Length header",
+      ))?;
+    if rest.len() < length {
+      return match eof {
+        true => Err(ClientError::InvalidResponse("incomplete body")),
+        false => Ok(None),
+      };
+    }
+    (rest[..length].to_vec(), length)
+  };
+
+  Ok(Some(ParsedResponse {
+    response: HttpResponse::new(status, Some(headers), Body::Bytes(body)),
+    length: head_length + body_length,
+    reusable,
+  }))
+} // end fn parse_response()
+
+/// Splits the status line and headers from the given bytes. Returns them without the
+/// terminating empty line, along with the length of the head including it, or `None` if
+/// the empty line has not been received yet.
+///
+/// # Arguments
+///
+/// * `bytes`: Bytes received on the connection.
+fn split_head(bytes: &[u8]) -> Option<(&[u8], usize)> {
+  let mut position: usize = 0;
+  while let Some(end) = bytes[position..].iter().position(|b| *b == b'\n') {
+    let line: &[u8] = &bytes[position..position + end];
+    let line: &[u8] = line.strip_suffix(b"\r").unwrap_or(line);
+    if line.is_empty() && position > 0 {
+      return Some((&bytes[..position], position + end + 1));
+    }
+    position += end + 1;
+  }
+  None
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Write;
+  use std::net::TcpListener;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+  use std::sync::Arc;
+  use std::thread;
+
+  use super::*;
+
+  /// Starts a server answering each request with the response the given function builds,
+  /// and returns its port and the number of connections accepted so far.
+  fn serve(respond: fn(&HttpRequest) -> HttpResponse) -> (u16, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let accepted = Arc::new(AtomicUsize::new(0));
+    let counter = accepted.clone();
+
+    thread::spawn(move || {
+      for stream in listener.incoming() {
+        counter.fetch_add(1, Ordering::SeqCst);
+        let mut stream = stream.unwrap();
+        thread::spawn(move || {
+          let mut buffer: Vec<u8> = Vec::new();
+          let mut chunk = [0; 1024];
+          loop {
+            match HttpRequest::parse(&buffer) {
+              Ok((request, length)) => {
+                buffer.drain(..length);
+                respond(&request).send_response(&mut stream).unwrap();
+              }
+              Err(_) => match stream.read(&mut chunk) {
+                Ok(0) | Err(_) => return,
+                Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+              },
+            }
+          }
+        });
+      }
+    });
+    (port, accepted)
+  }
+
+  #[test]
+  fn test_url_parse_and_join() {
+    let url = Url::parse("http://LocalHost:3000/api/orders?id=1#top").unwrap();
+    assert_eq!(url.host, "localhost");
+    assert_eq!(url.port, 3000);
+    assert_eq!(url.target, "/api/orders?id=1");
+    assert_eq!(url.to_string(), "http://localhost:3000/api/orders?id=1");
+
+    assert_eq!(Url::parse("http://[::1]").unwrap().authority(), "[::1]");
+    assert_eq!(Url::parse("http://host?x").unwrap().target, "/?x");
+    assert!(matches!(
+      Url::parse("https://host/"),
+      Err(ClientError::UnsupportedScheme(_))
+    ));
+    for invalid in [
+      "localhost/",
+      "http://",
+      "http://host:port/",
+      "http://u@host/",
+    ] {
+      assert!(matches!(
+        Url::parse(invalid),
+        Err(ClientError::InvalidUrl(_))
+      ));
+    }
+
+    let join = |reference: &str| url.join(reference).unwrap().to_string();
+    assert_eq!(join("/index.html"), "http://localhost:3000/index.html");
+    assert_eq!(
+      join("items?page=2"),
+      "http://localhost:3000/api/items?page=2"
+    );
+    assert_eq!(join("//example.com/a"), "http://example.com/a");
+    assert_eq!(join("http://other:8080"), "http://other:8080/");
+  }
+
+  #[test]
+  fn test_parse_response_framing() {
+    let bytes = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nHelloHTTP/1.1";
+    let parsed = parse_response(bytes, false, false).unwrap().unwrap();
+    assert_eq!(parsed.response.body().len(), Some(5));
+    assert_eq!(parsed.length, bytes.len() - 8);
+    assert!(parsed.reusable);
+    assert!(parse_response(&bytes[..40], false, false)
+      .unwrap()
+      .is_none());
+
+    let bytes = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nHi\r\n0\r\nX-Sum: 1\r\n\r\n";
+    let parsed = parse_response(bytes, false, false).unwrap().unwrap();
+    assert_eq!(parsed.response.body().len(), Some(2));
+    assert_eq!(parsed.response.header("X-Sum"), Some("1"));
+    assert_eq!(parsed.length, bytes.len());
+
+    // Without framing, the body lasts until the connection closes
+    let bytes = b"HTTP/1.0 299 Custom Reason\nConnection: keep-alive\n\nabc";
+    assert!(parse_response(bytes, false, false).unwrap().is_none());
+    let parsed = parse_response(bytes, false, true).unwrap().unwrap();
+    assert_eq!(
+      parsed.response.status_code().to_string(),
+      "299 Custom Reason"
+    );
+    assert_eq!(parsed.response.body().len(), Some(3));
+    assert!(!parsed.reusable);
+
+    // HEAD responses and 304 have no body despite their headers
+    let bytes = b"HTTP/1.1 304 Not Modified\r\nContent-Length: 10\r\n\r\n";
+    let parsed = parse_response(bytes, true, false).unwrap().unwrap();
+    assert_eq!(parsed.length, bytes.len());
+
+    for invalid in [&b"SPDY 200 OK\r\n\r\n"[..], b"HTTP/1.1 2000 OK\r\n\r\n"] {
+      assert!(parse_response(invalid, false, false).is_err());
+    }
+  }
+
+  #[test]
+  fn test_redirects_and_reuse() {
+    let (port, accepted) = serve(|request| match request.resource.path.as_str() {
+      "/old" => HttpResponse::new(
+        StatusCode::FOUND,
+        Some(HeaderMap::from([("Location", "new")])),
+        Body::Empty,
+      ),
+      "/loop" => HttpResponse::new(
+        StatusCode::TEMPORARY_REDIRECT,
+        Some(HeaderMap::from([("Location", "/loop")])),
+        Body::Empty,
+      ),
+      _ => HttpResponse::new(
+        StatusCode::OK,
+        None,
+        format!(
+          "{} {}",
+          request.method,
+          String::from_utf8_lossy(&request.message_body)
+        ),
+      ),
+    });
+    let client = Client::new().with_max_redirects(3);
+    let url = format!("http://127.0.0.1:{}", port);
+
+    // The POST becomes a GET after "302 Found"
+    let response = client
+      .request(Method::POST, &format!("{}/old", url))
+      .with_body("data")
+      .send()
+      .unwrap();
+    assert_eq!(response.status_code(), &StatusCode::OK);
+    assert_eq!(response.body().len(), Some("GET ".len() as u64));
+
+    // Both requests and the redirect used the same connection
+    let response = client.get(&format!("{}/new", url)).send().unwrap();
+    assert_eq!(response.status_code(), &StatusCode::OK);
+    assert_eq!(accepted.load(Ordering::SeqCst), 1);
+
+    let result = client.get(&format!("{}/loop", url)).send();
+    assert!(matches!(result, Err(ClientError::TooManyRedirects(3))));
+    let response = Client::new()
+      .with_max_redirects(0)
+      .get(&format!("{}/loop", url))
+      .send()
+      .unwrap();
+    assert_eq!(response.status_code(), &StatusCode::TEMPORARY_REDIRECT);
+  }
+
+  #[test]
+  fn test_timeout() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+      // Accept the connection and never answer
+      let (mut stream, _) = listener.accept().unwrap();
+      thread::sleep(Duration::from_millis(500));
+      let _ = stream.write_all(b"");
+    });
+
+    let result = Client::new()
+      .with_timeout(Duration::from_millis(100))
+      .get(&format!("http://127.0.0.1:{}/", port))
+      .send();
+    assert!(matches!(result, Err(ClientError::Timeout)));
+    server.join().unwrap();
+  }
+}
@@ -0,0 +1,232 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Abbreviated names of the months, starting with January.
+const MONTHS: [&str; 12] = [
+  "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Abbreviated names of the days of the week, starting with Sunday.
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+/// Represents a date and time in UTC, with a precision of seconds.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct DateTime {
+  /// Year (e.g. 2024).
+  pub year: i64,
+  /// Month, from 1 (January) to 12.
+  pub month: u32,
+  /// Day of the month, from 1.
+  pub day: u32,
+  /// Hour, from 0 to 23.
+  pub hour: u32,
+  /// Minute, from 0 to 59.
+  pub minute: u32,
+  /// Second, from 0 to 59.
+  pub second: u32,
+}
+
+impl DateTime {
+  /// Creates a [`DateTime`] object from the number of seconds since the Unix epoch.
+  ///
+  /// # Arguments
+  ///
+  /// * `seconds`: Seconds since 1970-01-01T00:00:00Z.
+  pub fn from_unix(seconds: i64) -> DateTime {
+    let days: i64 = seconds.div_euclid(86_400);
+    let time: i64 = seconds.rem_euclid(86_400);
+
+    // Convert the days to a civil date (proleptic Gregorian calendar), counting eras of
+    // 400 years from 0000-03-01
+    let z: i64 = days + 719_468;
+    let era: i64 = z.div_euclid(146_097);
+    let day_of_era: i64 = z.rem_euclid(146_097);
+    let year_of_era: i64 =
+      (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year: i64 =
+      day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index: i64 = (5 * day_of_year + 2) / 153;
+    let day: i64 = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month: i64 = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year: i64 = year_of_era + era * 400 + i64::from(month <= 2);
+
+    DateTime {
+      year,
+      month: month as u32,
+      day: day as u32,
+      hour: (time / 3600) as u32,
+      minute: (time % 3600 / 60) as u32,
+      second: (time % 60) as u32,
+    }
+  }
+
+  /// Creates a [`DateTime`] object from a system time, ignoring fractions of a second.
+  ///
+  /// # Arguments
+  ///
+  /// * `time`: System time to convert.
+  pub fn from_system_time(time: SystemTime) -> DateTime {
+    let seconds: i64 = match time.duration_since(UNIX_EPOCH) {
+      Ok(elapsed) => elapsed.as_secs() as i64,
+      Err(e) => -(e.duration().as_secs() as i64),
+    };
+
+    DateTime::from_unix(seconds)
+  }
+
+  /// Parses an HTTP date in the preferred IMF-fixdate format, e.g. `Sun, 06 Nov 1994
+  /// 08:49:37 GMT` (RFC 9110).
+  ///
+  /// # Arguments
+  ///
+  /// * `text`: Text of the date.
+  pub fn parse_http_date(text: &str) -> Option<DateTime> {
+    let (weekday, rest) = text.trim().split_once(", ")?;
+    let parts: Vec<&str> = rest.split(' ').collect();
+    let [day, month, year, time, "GMT"] = parts.as_slice() else {
+      return None;
+    };
+    let time: Vec<&str> = time.split(':').collect();
+    let [hour, minute, second] = time.as_slice() else {
+      return None;
+    };
+    let number = |text: &str, digits: usize| -> Option<u32> {
+      (text.len() == digits && text.bytes().all(|b| b.is_ascii_digit()))
+        .then(|| text.parse().ok())
+        .flatten()
+    };
+
+    let date: DateTime = DateTime {
+      year: i64::from(number(year, 4)?),
+      month: MONTHS.iter().position(|m| m == month)? as u32 + 1,
+      day: number(day, 2)?,
+      hour: number(hour, 2)?,
+      minute: number(minute, 2)?,
+      second: number(second, 2)?,
+    };
+
+    // Reject impossible dates, which do not survive the round trip
+    let valid: bool = date.hour < 24
+      && date.minute < 60
+      && date.second < 60
+      && DateTime::from_unix(date.to_unix()) == date
+      && date.weekday() == weekday;
+    valid.then_some(date)
+  }
+
+  /// Gets the number of seconds since the Unix epoch.
+  pub fn to_unix(&self) -> i64 {
+    // Convert the civil date to days, counting eras of 400 years from 0000-03-01
+    let year: i64 = self.year - i64::from(self.month <= 2);
+    let era: i64 = year.div_euclid(400);
+    let year_of_era: i64 = year.rem_euclid(400);
+    let month: i64 = i64::from(self.month);
+    let month_index: i64 = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year: i64 = (153 * month_index + 2) / 5 + i64::from(self.day) - 1;
+    let day_of_era: i64 =
+      year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days: i64 = era * 146_097 + day_of_era - 719_468;
+
+    days * 86_400
+      + i64::from(self.hour) * 3600
+      + i64::from(self.minute) * 60
+      + i64::from(self.second)
+  }
+
+  /// Gets the abbreviated name of the day of the week.
+  pub fn weekday(&self) -> &'static str {
+    // 1970-01-01 was a Thursday
+    WEEKDAYS[(self.to_unix().div_euclid(86_400) + 4).rem_euclid(7) as usize]
+  }
+
+  /// Gets the current date and time.
+  pub fn now() -> DateTime {
+    DateTime::from_system_time(SystemTime::now())
+  }
+
+  /// Formats the date as an HTTP date, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+  pub fn to_http_date(&self) -> String {
+    format!(
+      "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+      self.weekday(),
+      self.day,
+      MONTHS[self.month as usize - 1],
+      self.year,
+      self.hour,
+      self.minute,
+      self.second
+    )
+  }
+
+  /// Formats the date as in the Common Log Format, e.g. `10/Oct/2000:13:55:36 +0000`.
+  pub fn to_clf(&self) -> String {
+    format!(
+      "{:02}/{}/{:04}:{:02}:{:02}:{:02} +0000",
+      self.day,
+      MONTHS[self.month as usize - 1],
+      self.year,
+      self.hour,
+      self.minute,
+      self.second
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_from_unix() {
+    assert_eq!(
+      DateTime::from_unix(784_111_777),
+      DateTime {
+        year: 1994,
+        month: 11,
+        day: 6,
+        hour: 8,
+        minute: 49,
+        second: 37,
+      }
+    );
+    assert_eq!(
+      DateTime::from_unix(0).to_clf(),
+      "01/Jan/1970:00:00:00 +0000"
+    );
+    assert_eq!(
+      DateTime::from_unix(951_782_400).to_clf(),
+      "29/Feb/2000:00:00:00 +0000"
+    );
+    assert_eq!(
+      DateTime::from_unix(-1).to_clf(),
+      "31/Dec/1969:23:59:59 +0000"
+    );
+  }
+
+  #[test]
+  fn test_http_dates() {
+    let date = DateTime::from_unix(784_111_777);
+
+    assert_eq!(date.to_http_date(), "Sun, 06 Nov 1994 08:49:37 GMT");
+    assert_eq!(date.to_unix(), 784_111_777);
+    assert_eq!(
+      DateTime::parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"),
+      Some(date)
+    );
+    assert_eq!(DateTime::from_unix(-1).to_unix(), -1);
+
+    // Wrong weekday, impossible date, obsolete format and missing zone
+    assert_eq!(
+      DateTime::parse_http_date("Mon, 06 Nov 1994 08:49:37 GMT"),
+      None
+    );
+    assert_eq!(
+      DateTime::parse_http_date("Thu, 31 Feb 1994 08:49:37 GMT"),
+      None
+    );
+    assert_eq!(
+      DateTime::parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"),
+      None
+    );
+    assert_eq!(DateTime::parse_http_date("Sun, 06 Nov 1994 08:49:37"), None);
+  }
+}
@@ -0,0 +1,302 @@
+use std::io::{self, Write};
+
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+
+use crate::header_map::HeaderMap;
+
+/// Media types, besides the `text/*` ones, worth compressing.
+const COMPRESSIBLE_TYPES: [&str; 8] = [
+  "application/javascript",
+  "application/json",
+  "application/manifest+json",
+  "application/wasm",
+  "application/xhtml+xml",
+  "application/xml",
+  "image/svg+xml",
+  "image/x-icon",
+];
+
+/// Represents a content coding of an HTTP body.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ContentCoding {
+  /// No coding.
+  Identity,
+  /// The gzip format (RFC 1952).
+  Gzip,
+  /// The zlib format (RFC 1950) with the deflate algorithm.
+  Deflate,
+  /// The Brotli format (RFC 7932).
+  Brotli,
+}
+
+impl ContentCoding {
+  /// Gets the name of the coding, as used in the `Content-Encoding` header.
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      ContentCoding::Identity => "identity",
+      ContentCoding::Gzip => "gzip",
+      ContentCoding::Deflate => "deflate",
+      ContentCoding::Brotli => "br",
+    }
+  }
+
+  /// Encodes data with the coding.
+  ///
+  /// # Arguments
+  ///
+  /// * `data`: Data to encode.
+  pub fn encode(
+    &self,
+    data: &[u8],
+  ) -> io::Result<Vec<u8>> {
+    match self {
+      ContentCoding::Identity => Ok(data.to_vec()),
+      ContentCoding::Gzip => {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data)?;
+        encoder.finish()
+      }
+      ContentCoding::Deflate => {
+        // The "deflate" coding is the zlib format, despite the name
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data)?;
+        encoder.finish()
+      }
+      ContentCoding::Brotli => {
+        let mut encoded: Vec<u8> = Vec::new();
+        {
+          let mut encoder = brotli::CompressorWriter::new(&mut encoded, 4096, 5, 22);
+          encoder.write_all(data)?;
+        }
+        Ok(encoded)
+      }
+    }
+  }
+}
+
+/// Represents the codings a client accepts, as sent in the `Accept-Encoding` header.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct AcceptEncoding {
+  /// Name of each coding in lowercase, with its quality in thousandths.
+  codings: Vec<(String, u16)>,
+}
+
+impl AcceptEncoding {
+  /// Parses the value of an `Accept-Encoding` header, e.g. `gzip;q=0.8, br, *;q=0`.
+  /// Entries with invalid qualities are skipped.
+  ///
+  /// # Arguments
+  ///
+  /// * `value`: Value of the header.
+  pub fn parse(value: &str) -> AcceptEncoding {
+    let codings: Vec<(String, u16)> = value
+      .split(',')
+      .filter_map(|entry| {
+        let mut parts = entry.split(';').map(str::trim);
+        let name: &str = parts.next().filter(|n| !n.is_empty())?;
+        let mut quality: u16 = 1000;
+        for parameter in parts {
+          let (key, value) = parameter.split_once('=')?;
+          if key.trim().eq_ignore_ascii_case("q") {
+            quality = Self::parse_quality(value.trim())?;
+          }
+        }
+        Some((name.to_ascii_lowercase(), quality))
+      })
+      .collect();
+
+    AcceptEncoding { codings }
+  }
+
+  /// Gets the codings accepted by a request, from every `Accept-Encoding` header.
+  ///
+  /// # Arguments
+  ///
+  /// * `headers`: Headers of the request.
+  pub fn from_headers(headers: &HeaderMap) -> AcceptEncoding {
+    AcceptEncoding::parse(&headers.get_all("Accept-Encoding").join(","))
+  }
+
+  /// Parses a quality value, from `0` to `1` with up to three decimals, into thousandths.
+  ///
+  /// # Arguments
+  ///
+  /// * `text`: Text of the quality value.
+  fn parse_quality(text: &str) -> Option<u16> {
+    let (integer, decimals) = text.split_once('.').unwrap_or((text, ""));
+    if decimals.len() > 3 || !decimals.bytes().all(|b| b.is_ascii_digit()) {
+      return None;
+    }
+    let thousandths: u16 = format!("{:0<3}", decimals).parse().ok()?;
+
+    match integer {
+      "0" => Some(thousandths),
+      "1" if thousandths == 0 => Some(1000),
+      _ => None,
+    }
+  }
+
+  /// Gets the quality, in thousandths, the client gives to a coding. Codings not listed
+  /// take the quality of `*` if present; otherwise only `identity` is acceptable.
+  ///
+  /// # Arguments
+  ///
+  /// * `coding`: Coding to check.
+  pub fn quality(
+    &self,
+    coding: ContentCoding,
+  ) -> u16 {
+    let find = |name: &str| {
+      self
+        .codings
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, q)| *q)
+    };
+
+    find(coding.as_str())
+      .or_else(|| find("*"))
+      .unwrap_or(if coding == ContentCoding::Identity { 1000 } else { 0 })
+  }
+
+  /// Chooses the coding with the highest quality among the offered ones, preferring the
+  /// earlier ones on ties. Returns `None` if the client accepts none of them.
+  ///
+  /// # Arguments
+  ///
+  /// * `offered`: Codings the server can apply, from the most to the least preferred.
+  pub fn preferred(
+    &self,
+    offered: &[ContentCoding],
+  ) -> Option<ContentCoding> {
+    let mut best: Option<(ContentCoding, u16)> = None;
+    for coding in offered {
+      let quality: u16 = self.quality(*coding);
+      if quality > 0 && best.map_or(true, |(_, q)| quality > q) {
+        best = Some((*coding, quality));
+      }
+    }
+    best.map(|(coding, _)| coding)
+  }
+}
+
+/// Checks whether bodies of a media type are worth compressing.
+///
+/// # Arguments
+///
+/// * `content_type`: Value of the `Content-Type` header.
+pub fn is_compressible(content_type: &str) -> bool {
+  let media_type: String = content_type
+    .split(';')
+    .next()
+    .unwrap_or_default()
+    .trim()
+    .to_ascii_lowercase();
+
+  media_type.starts_with("text/") || COMPRESSIBLE_TYPES.contains(&media_type.as_str())
+}
+
+/// Adds a header name to the `Vary` header of a response, unless it is already listed.
+///
+/// # Arguments
+///
+/// * `headers`: Headers of the response.
+/// * `name`: Name of the request header the response depends on.
+pub fn add_vary(
+  headers: &mut HeaderMap,
+  name: &str,
+) {
+  let listed: bool = headers
+    .get_all("Vary")
+    .iter()
+    .flat_map(|v| v.split(','))
+    .any(|n| n.trim() == "*" || n.trim().eq_ignore_ascii_case(name));
+
+  if !listed {
+    let vary: String = match headers.get("Vary") {
+      Some(vary) => format!("{}, {}", vary, name),
+      None => name.to_string(),
+    };
+    headers.insert("Vary", &vary);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Read;
+
+  use super::*;
+
+  #[test]
+  fn test_accept_encoding() {
+    let accept = AcceptEncoding::parse("gzip;q=0.8, BR, deflate;q=0.800, identity;q=0.1");
+    assert_eq!(accept.quality(ContentCoding::Gzip), 800);
+    assert_eq!(accept.quality(ContentCoding::Brotli), 1000);
+    assert_eq!(accept.quality(ContentCoding::Identity), 100);
+
+    let offered = [
+      ContentCoding::Brotli,
+      ContentCoding::Gzip,
+      ContentCoding::Deflate,
+      ContentCoding::Identity,
+    ];
+    assert_eq!(accept.preferred(&offered), Some(ContentCoding::Brotli));
+    // Ties are broken by the order of the offered codings
+    assert_eq!(accept.preferred(&offered[1..]), Some(ContentCoding::Gzip));
+
+    // Missing header: only identity
+    let accept = AcceptEncoding::parse("");
+    assert_eq!(accept.preferred(&offered), Some(ContentCoding::Identity));
+
+    // Wildcards, exclusions and invalid qualities
+    let accept = AcceptEncoding::parse("br;q=0, *;q=0.5, gzip;q=2");
+    assert_eq!(accept.quality(ContentCoding::Brotli), 0);
+    assert_eq!(accept.quality(ContentCoding::Gzip), 500);
+    assert_eq!(accept.preferred(&offered), Some(ContentCoding::Gzip));
+    let accept = AcceptEncoding::parse("*;q=0");
+    assert_eq!(accept.preferred(&offered), None);
+  }
+
+  #[test]
+  fn test_encode_round_trip() {
+    let data: Vec<u8> = "compress me, ".repeat(100).into_bytes();
+
+    let mut decoded: Vec<u8> = Vec::new();
+    let gzip = ContentCoding::Gzip.encode(&data).unwrap();
+    flate2::read::GzDecoder::new(gzip.as_slice())
+      .read_to_end(&mut decoded)
+      .unwrap();
+    assert_eq!(decoded, data);
+    assert!(gzip.len() < data.len());
+
+    let mut decoded: Vec<u8> = Vec::new();
+    let deflate = ContentCoding::Deflate.encode(&data).unwrap();
+    flate2::read::ZlibDecoder::new(deflate.as_slice())
+      .read_to_end(&mut decoded)
+      .unwrap();
+    assert_eq!(decoded, data);
+
+    let mut decoded: Vec<u8> = Vec::new();
+    let brotli = ContentCoding::Brotli.encode(&data).unwrap();
+    brotli::Decompressor::new(brotli.as_slice(), 4096)
+      .read_to_end(&mut decoded)
+      .unwrap();
+    assert_eq!(decoded, data);
+  }
+
+  #[test]
+  fn test_compressible_types_and_vary() {
+    assert!(is_compressible("text/html;charset=UTF-8"));
+    assert!(is_compressible("Application/JSON"));
+    assert!(!is_compressible("image/png"));
+
+    let mut headers = HeaderMap::new();
+    add_vary(&mut headers, "Accept-Encoding");
+    add_vary(&mut headers, "accept-encoding");
+    assert_eq!(headers.get("Vary"), Some("Accept-Encoding"));
+    let mut headers = HeaderMap::from([("Vary", "Origin")]);
+    add_vary(&mut headers, "Accept-Encoding");
+    assert_eq!(headers.get("Vary"), Some("Origin, Accept-Encoding"));
+  }
+}
@@ -0,0 +1,238 @@
+/// Represents a set of HTTP headers.
+///
+/// Names are compared case-insensitively but keep the case they were given with, and a
+/// name can have several values. Headers keep the order they were added in, so they are
+/// serialized deterministically.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct HeaderMap {
+  /// Name and value of each header, in insertion order.
+  entries: Vec<(String, String)>,
+}
+
+impl HeaderMap {
+  /// Creates an empty [`HeaderMap`] object.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Sets the value of a header, replacing any previous values of the same name.
+  ///
+  /// # Arguments
+  ///
+  /// * `name`: Name of the header.
+  /// * `value`: Value of the header.
+  pub fn insert(
+    &mut self,
+    name: &str,
+    value: &str,
+  ) {
+    match self.position(name) {
+      Some(index) => {
+        self.entries[index].1 = value.to_string();
+        // Drop the later values of the same name
+        let rest: Vec<(String, String)> = self.entries.split_off(index + 1);
+        self.entries.extend(
+          rest
+            .into_iter()
+            .filter(|(n, _)| !n.eq_ignore_ascii_case(name)),
+        );
+      }
+      None => self.append(name, value),
+    }
+  }
+
+  /// Adds a value to a header, keeping any previous values of the same name.
+  ///
+  /// # Arguments
+  ///
+  /// * `name`: Name of the header.
+  /// * `value`: Value of the header.
+  pub fn append(
+    &mut self,
+    name: &str,
+    value: &str,
+  ) {
+    self.entries.push((name.to_string(), value.to_string()));
+  }
+
+  /// Gets the first value of a header.
+  ///
+  /// # Arguments
+  ///
+  /// * `name`: Name of the header.
+  pub fn get(
+    &self,
+    name: &str,
+  ) -> Option<&str> {
+    self.position(name).map(|i| self.entries[i].1.as_str())
+  }
+
+  /// Gets every value of a header, in the order they were added.
+  ///
+  /// # Arguments
+  ///
+  /// * `name`: Name of the header.
+  pub fn get_all(
+    &self,
+    name: &str,
+  ) -> Vec<&str> {
+    self
+      .entries
+      .iter()
+      .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+      .map(|(_, v)| v.as_str())
+      .collect()
+  }
+
+  /// Checks whether a header is present.
+  ///
+  /// # Arguments
+  ///
+  /// * `name`: Name of the header.
+  pub fn contains(
+    &self,
+    name: &str,
+  ) -> bool {
+    self.position(name).is_some()
+  }
+
+  /// Removes every value of a header and returns whether it was present.
+  ///
+  /// # Arguments
+  ///
+  /// * `name`: Name of the header.
+  pub fn remove(
+    &mut self,
+    name: &str,
+  ) -> bool {
+    let length: usize = self.entries.len();
+    self.entries.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+    self.entries.len() != length
+  }
+
+  /// Gets the number of header values.
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  /// Checks whether there are no headers.
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+
+  /// Iterates over the name and value of each header, in insertion order.
+  pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+    self.entries.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+  }
+
+  /// Gets the `Content-Type` header.
+  pub fn content_type(&self) -> Option<&str> {
+    self.get("Content-Type")
+  }
+
+  /// Gets the `Content-Length` header as a number, if it is valid.
+  pub fn content_length(&self) -> Option<usize> {
+    self.get("Content-Length")?.trim().parse().ok()
+  }
+
+  /// Gets the `Host` header.
+  pub fn host(&self) -> Option<&str> {
+    self.get("Host")
+  }
+
+  /// Gets the lowercase options of every `Connection` header (e.g. `close`).
+  pub fn connection(&self) -> Vec<String> {
+    self
+      .get_all("Connection")
+      .iter()
+      .flat_map(|v| v.split(','))
+      .map(|o| o.trim().to_ascii_lowercase())
+      .filter(|o| !o.is_empty())
+      .collect()
+  }
+
+  /// Returns the index of the first header with the given name.
+  ///
+  /// # Arguments
+  ///
+  /// * `name`: Name of the header.
+  fn position(
+    &self,
+    name: &str,
+  ) -> Option<usize> {
+    self
+      .entries
+      .iter()
+      .position(|(n, _)| n.eq_ignore_ascii_case(name))
+  }
+}
+
+impl<'h> FromIterator<(&'h str, &'h str)> for HeaderMap {
+  fn from_iter<T: IntoIterator<Item = (&'h str, &'h str)>>(iter: T) -> Self {
+    let mut headers: HeaderMap = HeaderMap::new();
+    for (name, value) in iter {
+      headers.append(name, value);
+    }
+    headers
+  }
+}
+
+impl<'h, const N: usize> From<[(&'h str, &'h str); N]> for HeaderMap {
+  fn from(value: [(&'h str, &'h str); N]) -> Self {
+    value.into_iter().collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_case_insensitive_lookup() {
+    let headers = HeaderMap::from([("content-type", "text/css"), ("HOST", "localhost")]);
+
+    assert_eq!(headers.get("Content-Type"), Some("text/css"));
+    assert_eq!(headers.content_type(), Some("text/css"));
+    assert_eq!(headers.host(), Some("localhost"));
+    assert!(headers.contains("Host"));
+    assert!(!headers.contains("Accept"));
+  }
+
+  #[test]
+  fn test_multiple_values() {
+    let mut headers = HeaderMap::new();
+    headers.append("Set-Cookie", "a=1");
+    headers.append("Content-Type", "text/html");
+    headers.append("set-cookie", "b=2");
+
+    assert_eq!(headers.get_all("Set-Cookie"), vec!["a=1", "b=2"]);
+    assert_eq!(headers.len(), 3);
+
+    // Inserting replaces every previous value in the position of the first one
+    headers.insert("SET-COOKIE", "c=3");
+    assert_eq!(
+      headers.iter().collect::<Vec<_>>(),
+      vec![("Set-Cookie", "c=3"), ("Content-Type", "text/html")]
+    );
+
+    assert!(headers.remove("set-cookie"));
+    assert!(!headers.remove("set-cookie"));
+    assert_eq!(headers.len(), 1);
+  }
+
+  #[test]
+  fn test_typed_accessors() {
+    let headers = HeaderMap::from([
+      ("Content-Length", "42"),
+      ("Connection", "Keep-Alive, Upgrade"),
+      ("Connection", "close"),
+    ]);
+
+    assert_eq!(headers.content_length(), Some(42));
+    assert_eq!(headers.connection(), vec!["keep-alive", "upgrade", "close"]);
+
+    let headers = HeaderMap::from([("Content-Length", "abc")]);
+    assert_eq!(headers.content_length(), None);
+    assert!(headers.connection().is_empty());
+  }
+}
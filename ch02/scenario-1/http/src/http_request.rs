@@ -1,29 +1,92 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+
+use crate::chunked::{self, ChunkedScanner, DecodeError, DecodedBody};
+use crate::header_map::HeaderMap;
+pub use crate::resource::Resource;
 
 /// Represents an HTTP method.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
 pub enum Method {
   /// The HTTP GET method.
   GET,
+  /// The HTTP HEAD method.
+  HEAD,
   /// The HTTP POST method.
   POST,
+  /// The HTTP PUT method.
+  PUT,
+  /// The HTTP DELETE method.
+  DELETE,
+  /// The HTTP CONNECT method.
+  CONNECT,
+  /// The HTTP OPTIONS method.
+  OPTIONS,
+  /// The HTTP TRACE method.
+  TRACE,
+  /// The HTTP PATCH method.
+  PATCH,
+  /// Any other method with a valid token name.
+  Extension(String),
   /// Unknown HTTP method.
   UNINITIALIZED,
 }
 
+impl Method {
+  /// Gets the name of the method as sent on the request line.
+  pub fn as_str(&self) -> &str {
+    match self {
+      Method::GET => "GET",
+      Method::HEAD => "HEAD",
+      Method::POST => "POST",
+      Method::PUT => "PUT",
+      Method::DELETE => "DELETE",
+      Method::CONNECT => "CONNECT",
+      Method::OPTIONS => "OPTIONS",
+      Method::TRACE => "TRACE",
+      Method::PATCH => "PATCH",
+      Method::Extension(name) => name.as_str(),
+      Method::UNINITIALIZED => "",
+    }
+  }
+}
+
+impl fmt::Display for Method {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    write!(f, "{}", self.as_str())
+  }
+}
+
 impl From<&str> for Method {
   fn from(value: &str) -> Self {
     match value {
       "GET" => Method::GET,
+      "HEAD" => Method::HEAD,
       "POST" => Method::POST,
+      "PUT" => Method::PUT,
+      "DELETE" => Method::DELETE,
+      "CONNECT" => Method::CONNECT,
+      "OPTIONS" => Method::OPTIONS,
+      "TRACE" => Method::TRACE,
+      "PATCH" => Method::PATCH,
+      // Method names are case-sensitive tokens
+      _ if !value.is_empty() && value.bytes().all(is_token_char) => {
+        Method::Extension(value.to_string())
+      }
       _ => Method::UNINITIALIZED,
     }
   }
 }
 
 /// Represents the version of the HTTP protocol.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Version {
+  /// HTTP/1.0 version.
+  V1_0,
   /// HTTP/1.1 version.
   V1_1,
   /// HTTP/2.0 version.
@@ -32,23 +95,30 @@ pub enum Version {
   UNINITIALIZED,
 }
 
+impl Version {
+  /// Gets the version as written in the request line.
+  pub fn as_str(&self) -> &str {
+    match self {
+      Version::V1_0 => "HTTP/1.0",
+      Version::V1_1 => "HTTP/1.1",
+      Version::V2_0 => "HTTP/2.0",
+      Version::UNINITIALIZED => "",
+    }
+  }
+}
+
 impl From<&str> for Version {
   fn from(value: &str) -> Self {
     match value {
+      "HTTP/1.0" => Version::V1_0,
       "HTTP/1.1" => Version::V1_1,
       _ => Version::UNINITIALIZED,
     }
   }
 }
 
-/// Represents the path to a REST resource.
-#[derive(Debug, PartialEq)]
-pub enum Resource {
-  Path(String),
-}
-
 /// Represents an HTTP request.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct HttpRequest {
   /// HTTP method of the request.
   pub method: Method,
@@ -57,88 +127,454 @@ pub struct HttpRequest {
   /// Path of the requested REST resource.
   pub resource: Resource,
   /// Set of headers of the HTTP request.
-  pub headers: HashMap<String, String>,
-  /// Body message of the request.
-  pub message_body: String,
+  pub headers: HeaderMap,
+  /// Body message of the request, as received; it is not necessarily text.
+  pub message_body: Vec<u8>,
+  /// Fields of the trailer section of a chunked request body.
+  pub trailers: HeaderMap,
+  /// Parameters extracted from the path by the route matching the request.
+  pub path_params: HashMap<String, String>,
 }
 
-impl From<String> for HttpRequest {
-  fn from(req: String) -> Self {
-    let mut parsed_method: Method = Method::UNINITIALIZED;
-    let mut parsed_version: Version = Version::UNINITIALIZED;
-    let mut parsed_resource: Resource = Resource::Path("".to_string());
-    let mut parsed_headers: HashMap<String, String> = HashMap::new();
-    let mut parsed_message_body: String = "".to_string();
-
-    // Process each line of the incoming HTTP request
-    for line in req.lines() {
-      // Process the request line
-      if line.contains("HTTP") {
-        let (method, resource, version) = process_req_line(line);
-        parsed_method = method;
-        parsed_version = version;
-        parsed_resource = resource;
-      } else if line.contains(":") {
-        let (key, value) = process_header_line(line);
-        parsed_headers.insert(key, value);
-      } else if line.len() == 0 {
-        // It is an emmpty line followed by the body
-      } else {
-        parsed_message_body = line.to_string();
+/// Represents the errors found while parsing an HTTP request.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ParseError {
+  /// The input ends before the request is complete, so more bytes are needed.
+  Incomplete {
+    /// Length of the request line and headers, once they are complete.
+    head_length: Option<usize>,
+    /// Length of the body announced by the headers, or by the chunks received so far
+    /// for a chunked body, once the headers are complete.
+    body_length: Option<usize>,
+  },
+  /// The request line does not have the form `method SP request-target SP version`.
+  InvalidRequestLine,
+  /// The request target is not a valid path or contains invalid escape sequences.
+  InvalidRequestTarget,
+  /// The HTTP version is not of the form `HTTP/x.y`.
+  InvalidVersion,
+  /// A header line is not a valid field line.
+  InvalidHeader,
+  /// The `Content-Length` header is not a valid length or is ambiguous.
+  InvalidContentLength,
+  /// The request uses a transfer coding that is not supported.
+  UnsupportedTransferEncoding,
+  /// The chunked body of the request is malformed.
+  InvalidChunkedBody,
+  /// The request line or a header contains bytes that are not valid UTF-8.
+  InvalidEncoding,
+}
+
+impl fmt::Display for ParseError {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    match self {
+      ParseError::Incomplete { .. } => write!(f, "incomplete request"),
+      ParseError::InvalidRequestLine => write!(f, "invalid request line"),
+      ParseError::InvalidRequestTarget => write!(f, "invalid request target"),
+      ParseError::InvalidVersion => write!(f, "invalid HTTP version"),
+      ParseError::InvalidHeader => write!(f, "invalid header line"),
+      ParseError::InvalidContentLength => write!(f, "invalid Content-Length header"),
+      ParseError::UnsupportedTransferEncoding => {
+        write!(f, "unsupported transfer encoding")
+      }
+      ParseError::InvalidChunkedBody => write!(f, "invalid chunked body"),
+      ParseError::InvalidEncoding => write!(f, "request is not valid UTF-8"),
+    }
+  }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Represents the section of the request the parser is processing.
+enum ParseState {
+  /// Waiting for the request line (leading empty lines are skipped).
+  RequestLine,
+  /// Reading header lines until the empty line.
+  Headers,
+  /// Reading the number of body bytes given by `Content-Length`.
+  Body {
+    /// Length of the request line and headers.
+    head_length: usize,
+    /// Length of the body.
+    body_length: usize,
+  },
+  /// Reading a body with the chunked transfer coding.
+  ChunkedBody {
+    /// Length of the request line and headers.
+    head_length: usize,
+  },
+}
+
+impl HttpRequest {
+  /// Creates an HTTP/1.1 request without headers or body.
+  ///
+  /// # Arguments
+  ///
+  /// * `method`: HTTP method of the request.
+  /// * `target`: Request target, e.g. `/api/shipping/orders?order_id=1`.
+  pub fn new(
+    method: Method,
+    target: &str,
+  ) -> Result<HttpRequest, ParseError> {
+    Ok(HttpRequest {
+      method,
+      version: Version::V1_1,
+      resource: Resource::parse(target)?,
+      headers: HeaderMap::new(),
+      message_body: Vec::new(),
+      trailers: HeaderMap::new(),
+      path_params: HashMap::new(),
+    })
+  }
+
+  /// Sends this request as a byte stream. The `Content-Length` header is computed from
+  /// the body, which is sent whole.
+  ///
+  /// # Arguments
+  ///
+  /// * `write_stream`: Byte stream writer. Recommended: a TCP stream
+  pub fn write_to(
+    &self,
+    write_stream: &mut impl Write,
+  ) -> io::Result<()> {
+    let mut head: String = format!(
+      "{} {} {}\r\n",
+      self.method,
+      self.resource.target,
+      self.version.as_str()
+    );
+    for (k, v) in self.headers.iter() {
+      if !k.eq_ignore_ascii_case("Content-Length")
+        && !k.eq_ignore_ascii_case("Transfer-Encoding")
+      {
+        head = format!("{}{}: {}\r\n", head, k, v);
       }
     }
+    // Methods that usually carry a body announce even an empty one
+    if !self.message_body.is_empty()
+      || matches!(self.method, Method::POST | Method::PUT | Method::PATCH)
+    {
+      head = format!("{}Content-Length: {}\r\n", head, self.message_body.len());
+    }
+    head.push_str("\r\n");
+
+    write_stream.write_all(head.as_bytes())?;
+    write_stream.write_all(&self.message_body)?;
+    write_stream.flush()
+  } // end fn write_to()
+
+  /// Gets the value of a parameter extracted from the path, e.g. `order_id` for the
+  /// route `/api/shipping/orders/{order_id}`.
+  ///
+  /// # Arguments
+  ///
+  /// * `name`: Name of the path parameter.
+  pub fn path_param(
+    &self,
+    name: &str,
+  ) -> Option<&str> {
+    self.path_params.get(name).map(String::as_str)
+  }
+
+  /// Parses a request from the beginning of the given bytes following the HTTP/1.1
+  /// message framing (RFC 9112).
+  ///
+  /// Returns the request and the number of bytes it takes, so any following bytes
+  /// belong to the next request. When the bytes end before the request is complete,
+  /// returns [`ParseError::Incomplete`].
+  ///
+  /// # Arguments
+  ///
+  /// * `bytes`: Bytes received from the client.
+  pub fn parse(bytes: &[u8]) -> Result<(HttpRequest, usize), ParseError> {
+    let (request, _, request_length) = Self::parse_framed(bytes)?;
+
+    Ok((request, request_length))
+  }
+
+  /// Parses a request like [`HttpRequest::parse`], also returning the length of the
+  /// request line and headers, so the request `(request, head_length, request_length)`
+  /// can be checked against size limits.
+  ///
+  /// A body is delimited by the `Content-Length` header or by the chunked transfer
+  /// coding, whose trailer fields are kept apart from the headers.
+  ///
+  /// # Arguments
+  ///
+  /// * `bytes`: Bytes received from the client.
+  pub fn parse_framed(bytes: &[u8]) -> Result<(HttpRequest, usize, usize), ParseError> {
+    Self::parse_framed_with(bytes, &mut ChunkedScanner::new())
+  }
+
+  /// Parses a request like [`HttpRequest::parse_framed`], resuming the check of a chunked
+  /// body where the previous call with the same scanner stopped. A body received in many
+  /// parts is then decoded once, when it is complete, instead of after every part. The
+  /// scanner must be a new one for every request.
+  ///
+  /// # Arguments
+  ///
+  /// * `bytes`: Bytes received from the client, starting with the request.
+  /// * `scanner`: Progress of the check of a chunked body.
+  pub fn parse_framed_with(
+    bytes: &[u8],
+    scanner: &mut ChunkedScanner,
+  ) -> Result<(HttpRequest, usize, usize), ParseError> {
+    let mut request: HttpRequest = HttpRequest {
+      method: Method::UNINITIALIZED,
+      version: Version::UNINITIALIZED,
+      resource: Resource::default(),
+      headers: HeaderMap::new(),
+      message_body: Vec::new(),
+      trailers: HeaderMap::new(),
+      path_params: HashMap::new(),
+    };
+    let mut content_length: Option<usize> = None;
+    let mut transfer_codings: Vec<String> = Vec::new();
+    let mut position: usize = 0;
+    let mut state: ParseState = ParseState::RequestLine;
+
+    loop {
+      state = match state {
+        ParseState::RequestLine => {
+          let line: &str = next_line(bytes, &mut position)?;
+          // Ignore empty lines received before the request line
+          if line.is_empty() {
+            ParseState::RequestLine
+          } else {
+            let (method, resource, version) = process_req_line(line)?;
+            request.method = method;
+            request.resource = resource;
+            request.version = version;
+            ParseState::Headers
+          }
+        }
+        ParseState::Headers => {
+          let line: &str = next_line(bytes, &mut position)?;
+          // The empty line ends the headers
+          if line.is_empty() {
+            body_state(position, content_length, &transfer_codings)?
+          } else {
+            let (key, value) = process_header_line(line)?;
+
+            if key.eq_ignore_ascii_case("Transfer-Encoding") {
+              transfer_codings.extend(
+                value
+                  .split(',')
+                  .map(|c| c.trim().to_ascii_lowercase())
+                  .filter(|c| !c.is_empty()),
+              );
+            }
+            if key.eq_ignore_ascii_case("Content-Length") {
+              let length: usize = parse_content_length(&value)?;
+              if matches!(content_length, Some(l) if l != length) {
+                return Err(ParseError::InvalidContentLength);
+              }
+              content_length = Some(length);
+            }
+
+            request.headers.append(&key, &value);
+            ParseState::Headers
+          }
+        }
+        ParseState::Body {
+          head_length,
+          body_length,
+        } => {
+          let request_length: usize = head_length + body_length;
+          if bytes.len() < request_length {
+            return Err(ParseError::Incomplete {
+              head_length: Some(head_length),
+              body_length: Some(body_length),
+            });
+          }
+
+          request.message_body = bytes[head_length..request_length].to_vec();
+
+          return Ok((request, head_length, request_length));
+        }
+        ParseState::ChunkedBody { head_length } => {
+          // Decode the body only once its framing is complete
+          match scanner.scan(&bytes[head_length..]) {
+            Ok(_) => {}
+            Err(DecodeError::Incomplete(body_length)) => {
+              return Err(ParseError::Incomplete {
+                head_length: Some(head_length),
+                body_length: Some(body_length),
+              });
+            }
+            Err(DecodeError::Invalid) => return Err(ParseError::InvalidChunkedBody),
+          }
+          let body: DecodedBody = chunked::decode(&bytes[head_length..])
+            .map_err(|_| ParseError::InvalidChunkedBody)?;
+
+          request.message_body = body.data;
+          request.trailers = body.trailers;
+
+          return Ok((request, head_length, head_length + body.length));
+        }
+      };
+    }
+  } // end fn parse_framed_with()
+}
 
-    // Create the request object
-    HttpRequest {
-      method: parsed_method,
-      version: parsed_version,
-      resource: parsed_resource,
-      headers: parsed_headers,
-      message_body: parsed_message_body.to_string(),
+impl From<String> for HttpRequest {
+  fn from(req: String) -> Self {
+    match HttpRequest::parse(req.as_bytes()) {
+      Ok((request, _)) => request,
+      // An invalid request keeps every component uninitialized
+      Err(_) => HttpRequest {
+        method: Method::UNINITIALIZED,
+        version: Version::UNINITIALIZED,
+        resource: Resource::default(),
+        headers: HeaderMap::new(),
+        message_body: Vec::new(),
+        trailers: HeaderMap::new(),
+        path_params: HashMap::new(),
+      },
     }
   }
 }
 
+/// Chooses how the body of a request is delimited once its headers are complete.
+///
+/// # Arguments
+///
+/// * `head_length`: Length of the request line and headers.
+/// * `content_length`: Value of the `Content-Length` header, if any.
+/// * `transfer_codings`: Codings listed by the `Transfer-Encoding` headers, in order.
+fn body_state(
+  head_length: usize,
+  content_length: Option<usize>,
+  transfer_codings: &[String],
+) -> Result<ParseState, ParseError> {
+  if transfer_codings.is_empty() {
+    return Ok(ParseState::Body {
+      head_length,
+      body_length: content_length.unwrap_or(0),
+    });
+  }
+
+  // A message with both headers could be framed differently by each side
+  if content_length.is_some() {
+    return Err(ParseError::InvalidContentLength);
+  }
+  // Only the chunked coding is decoded, and it must be applied exactly once
+  if transfer_codings != ["chunked"] {
+    return Err(ParseError::UnsupportedTransferEncoding);
+  }
+  Ok(ParseState::ChunkedBody { head_length })
+}
+
+/// Returns the next line of the given bytes, without its line terminator, and moves the
+/// position to the beginning of the following line.
+///
+/// Both CRLF and a bare LF are accepted as line terminators.
+///
+/// # Arguments
+///
+/// * `bytes`: Bytes received from the client.
+/// * `position`: Position where the line starts.
+fn next_line<'b>(
+  bytes: &'b [u8],
+  position: &mut usize,
+) -> Result<&'b str, ParseError> {
+  let start: usize = *position;
+  let Some(offset) = bytes[start..].iter().position(|b| *b == b'\n') else {
+    return Err(ParseError::Incomplete {
+      head_length: None,
+      body_length: None,
+    });
+  };
+
+  let end: usize = start + offset;
+  *position = end + 1;
+
+  let line: &[u8] = &bytes[start..end];
+  let line: &[u8] = line.strip_suffix(b"\r").unwrap_or(line);
+
+  std::str::from_utf8(line).map_err(|_| ParseError::InvalidEncoding)
+}
+
 /// Returns the method, resource and version components of the given request line.
 ///
 /// # Arguments
 ///
 /// * `line`: HTTP request line.
-///  
-fn process_req_line(line: &str) -> (Method, Resource, Version) {
-  // Split the request line by whitespaces into a iterator
-  let mut words: std::str::SplitWhitespace = line.split_whitespace();
+fn process_req_line(line: &str) -> Result<(Method, Resource, Version), ParseError> {
+  // Split the request line by single spaces
+  let words: Vec<&str> = line.split(' ').collect();
+  if words.len() != 3 || words.iter().any(|w| w.is_empty()) {
+    return Err(ParseError::InvalidRequestLine);
+  }
 
-  // Extract the HTTP method
-  let method: &str = words.next().unwrap();
+  let (method, resource_path, version) = (words[0], words[1], words[2]);
 
-  // Extract the path of the resource
-  let resource_path: String = words.next().unwrap().to_string();
+  // The method is a token
+  if !method.bytes().all(is_token_char) {
+    return Err(ParseError::InvalidRequestLine);
+  }
 
-  // Extract the HTTP version
-  let version: &str = words.next().unwrap();
+  // The version has the form HTTP/x.y
+  let digits: &[u8] = version
+    .strip_prefix("HTTP/")
+    .ok_or(ParseError::InvalidVersion)?
+    .as_bytes();
+  if digits.len() != 3
+    || !digits[0].is_ascii_digit()
+    || digits[1] != b'.'
+    || !digits[2].is_ascii_digit()
+  {
+    return Err(ParseError::InvalidVersion);
+  }
 
-  (method.into(), Resource::Path(resource_path), version.into())
+  Ok((
+    method.into(),
+    Resource::parse(resource_path)?,
+    version.into(),
+  ))
 }
 
-/// Returns the set of HTTP headers from the given string line.
+/// Returns the name and the value of the HTTP header in the given line.
 ///
 /// # Arguments
 ///
-/// * `line`: String line containing the HTTP headers.
-fn process_header_line(line: &str) -> (String, String) {
-  let mut key = "".to_string();
-  let mut value = "".to_string();
-
+/// * `line`: String line containing the HTTP header.
+fn process_header_line(line: &str) -> Result<(String, String), ParseError> {
   // Split the key and the value parts only by the first colon (:)
-  let header_items: Vec<&str> = line.splitn(2, ":").collect::<Vec<&str>>();
-  // Asign the key and the value only if both parts are present
-  if header_items.len() == 2 {
-    key = header_items[0].trim().to_string();
-    value = header_items[1].trim().to_string();
+  let (key, value) = line.split_once(':').ok_or(ParseError::InvalidHeader)?;
+
+  // The name is a non-empty token without surrounding whitespace, which also rejects
+  // obsolete line folding
+  if key.is_empty() || !key.bytes().all(is_token_char) {
+    return Err(ParseError::InvalidHeader);
+  }
+
+  Ok((key.to_string(), value.trim_matches([' ', '\t']).to_string()))
+}
+
+/// Returns the length given in a `Content-Length` header value.
+///
+/// # Arguments
+///
+/// * `value`: Value of the header.
+fn parse_content_length(value: &str) -> Result<usize, ParseError> {
+  // Only digits are allowed, so signs and whitespace are rejected
+  if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+    return Err(ParseError::InvalidContentLength);
   }
+  value.parse().map_err(|_| ParseError::InvalidContentLength)
+}
 
-  (key, value)
+/// Checks whether the given byte is allowed in a token (RFC 9110, section 5.6.2).
+///
+/// # Arguments
+///
+/// * `byte`: Byte to check.
+pub(crate) fn is_token_char(byte: u8) -> bool {
+  byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte)
 }
 // ----------------------------------------------------------- //
 
@@ -154,11 +590,22 @@ mod tests {
     let method_post: Method = "POST".into();
     assert_eq!(method_post, Method::POST);
 
+    let method_patch: Method = "PATCH".into();
+    assert_eq!(method_patch, Method::PATCH);
+
     let method_uninitialized: Method = "".into();
     assert_eq!(method_uninitialized, Method::UNINITIALIZED);
 
-    let method_uninitialized: Method = "Other".into();
+    let method_uninitialized: Method = "GE T".into();
     assert_eq!(method_uninitialized, Method::UNINITIALIZED);
+
+    let method_extension: Method = "Other".into();
+    assert_eq!(method_extension, Method::Extension("Other".to_string()));
+    assert_eq!(method_extension.to_string(), "Other");
+
+    // Method names are case-sensitive
+    let method_extension: Method = "get".into();
+    assert_eq!(method_extension, Method::Extension("get".to_string()));
   }
 
   #[test]
@@ -181,21 +628,178 @@ mod tests {
 
   #[test]
   fn test_read_http() {
-    let s: String = String::from("GET /greeting HTTP/1.1\r\nHost: localhost:3000\r\nUser-Agent: curl/7.81.0\r\nAccept: */*\r\n\r\nHello World!\r\n");
+    let s: String = String::from("GET /greeting HTTP/1.1\r\nHost: localhost:3000\r\nUser-Agent: curl/7.81.0\r\nAccept: */*\r\nContent-Length: 12\r\n\r\nHello World!\r\n");
 
     let req: HttpRequest = s.into();
 
     assert_eq!(req.method, Method::GET);
-    assert_eq!(req.resource, Resource::Path("/greeting".to_string()));
+    assert_eq!(req.resource, Resource::parse("/greeting").unwrap());
     assert_eq!(req.version, Version::V1_1);
 
-    let mut headers_expected: HashMap<String, String> = HashMap::new();
-    headers_expected.insert("Host".into(), "localhost:3000".into());
-    headers_expected.insert("User-Agent".into(), "curl/7.81.0".into());
-    headers_expected.insert("Accept".into(), "*/*".into());
+    let mut headers_expected: HeaderMap = HeaderMap::new();
+    headers_expected.insert("Host", "localhost:3000");
+    headers_expected.insert("User-Agent", "curl/7.81.0");
+    headers_expected.insert("Accept", "*/*");
+    headers_expected.insert("Content-Length", "12");
 
     assert_eq!(req.headers, headers_expected);
 
-    assert_eq!("Hello World!".to_string(), req.message_body);
+    assert_eq!(req.message_body, b"Hello World!");
+  }
+
+  #[test]
+  fn test_parse_multi_line_body() {
+    let bytes = b"POST /api HTTP/1.1\r\nContent-Length: 11\r\n\r\nline1\nline2GET";
+
+    let (req, length) = HttpRequest::parse(bytes).unwrap();
+
+    assert_eq!(req.method, Method::POST);
+    assert_eq!(req.message_body, b"line1\nline2");
+    assert_eq!(length, bytes.len() - 3);
+  }
+
+  #[test]
+  fn test_parse_binary_body() {
+    let bytes = b"POST /upload HTTP/1.1\r\nContent-Length: 3\r\n\r\n\xff\x00\xfe";
+
+    let (req, length) = HttpRequest::parse(bytes).unwrap();
+
+    assert_eq!(req.message_body, [0xff, 0x00, 0xfe]);
+    assert_eq!(length, bytes.len());
+
+    let bytes = b"POST /upload HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n2\r\n\xff\xfe\r\n0\r\n\r\n";
+
+    let (req, _) = HttpRequest::parse(bytes).unwrap();
+
+    assert_eq!(req.message_body, [0xff, 0xfe]);
+  }
+
+  #[test]
+  fn test_parse_chunked_body() {
+    let bytes = b"POST /api HTTP/1.1\r\nTransfer-Encoding: Chunked\r\n\r\n6\r\nline1\n\r\n5;ext\r\nline2\r\n0\r\nDigest: x\r\n\r\nGET";
+
+    let (req, head_length, length) = HttpRequest::parse_framed(bytes).unwrap();
+
+    assert_eq!(req.message_body, b"line1\nline2");
+    assert_eq!(req.trailers.get("Digest"), Some("x"));
+    assert!(!req.headers.contains("Digest"));
+    assert_eq!(head_length, 50);
+    assert_eq!(length, bytes.len() - 3);
+
+    assert_eq!(
+      HttpRequest::parse(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n4\r\nab"),
+      Err(ParseError::Incomplete {
+        head_length: Some(47),
+        body_length: Some(4)
+      })
+    );
+  }
+
+  #[test]
+  fn test_write_round_trip() {
+    let mut req = HttpRequest::new(Method::POST, "/api/shipping/orders?x=1").unwrap();
+    req.headers.insert("Host", "localhost");
+    req.headers.insert("Content-Length", "99");
+    req.message_body = b"{}".to_vec();
+
+    let mut bytes: Vec<u8> = Vec::new();
+    req.write_to(&mut bytes).unwrap();
+    assert_eq!(
+      String::from_utf8(bytes.clone()).unwrap(),
+      "POST /api/shipping/orders?x=1 HTTP/1.1\r\nHost: localhost\r\nContent-Length: 2\r\n\r\n{}"
+    );
+
+    let (parsed, length) = HttpRequest::parse(&bytes).unwrap();
+    assert_eq!(length, bytes.len());
+    assert_eq!(parsed.resource, req.resource);
+    assert_eq!(parsed.message_body, b"{}");
+  }
+
+  #[test]
+  fn test_parse_incomplete() {
+    assert_eq!(
+      HttpRequest::parse(b"GET / HTTP/1.1\r\nHost: local"),
+      Err(ParseError::Incomplete {
+        head_length: None,
+        body_length: None
+      })
+    );
+
+    assert_eq!(
+      HttpRequest::parse(b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nab"),
+      Err(ParseError::Incomplete {
+        head_length: Some(38),
+        body_length: Some(5)
+      })
+    );
+  }
+
+  #[test]
+  fn test_parse_leading_empty_lines_and_bare_lf() {
+    let (req, length) = HttpRequest::parse(b"\r\nGET /a HTTP/1.0\nHost: x\n\n").unwrap();
+
+    assert_eq!(req.resource.path, "/a");
+    assert_eq!(req.version, Version::V1_0);
+    assert_eq!(req.headers.host(), Some("x"));
+    assert_eq!(length, 27);
+  }
+
+  #[test]
+  fn test_parse_repeated_headers() {
+    let (req, _) =
+      HttpRequest::parse(b"GET / HTTP/1.1\r\nAccept: a\r\nAccept: b\r\n\r\n").unwrap();
+
+    assert_eq!(req.headers.get_all("accept"), vec!["a", "b"]);
+  }
+
+  #[test]
+  fn test_parse_errors() {
+    let cases: [(&[u8], ParseError); 12] = [
+      (b"GET /\r\n\r\n", ParseError::InvalidRequestLine),
+      (b"GET  / HTTP/1.1\r\n\r\n", ParseError::InvalidRequestLine),
+      (b"G(T / HTTP/1.1\r\n\r\n", ParseError::InvalidRequestLine),
+      (
+        b"GET a%zz HTTP/1.1\r\n\r\n",
+        ParseError::InvalidRequestTarget,
+      ),
+      (b"GET / HTTP/1\r\n\r\n", ParseError::InvalidVersion),
+      (
+        b"GET / HTTP/1.1\r\nHost : x\r\n\r\n",
+        ParseError::InvalidHeader,
+      ),
+      (
+        b"GET / HTTP/1.1\r\nA: b\r\n c\r\n\r\n",
+        ParseError::InvalidHeader,
+      ),
+      (
+        b"GET / HTTP/1.1\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\n",
+        ParseError::InvalidContentLength,
+      ),
+      (
+        b"GET / HTTP/1.1\r\nTransfer-Encoding: gzip, chunked\r\n\r\n",
+        ParseError::UnsupportedTransferEncoding,
+      ),
+      (
+        b"GET / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 1\r\n\r\n",
+        ParseError::InvalidContentLength,
+      ),
+      (
+        b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n",
+        ParseError::InvalidChunkedBody,
+      ),
+      (b"GET /\xff HTTP/1.1\r\n\r\n", ParseError::InvalidEncoding),
+    ];
+
+    for (bytes, expected) in cases {
+      assert_eq!(HttpRequest::parse(bytes), Err(expected));
+    }
+  }
+
+  #[test]
+  fn test_from_invalid_string() {
+    let req: HttpRequest = String::from("GET HTTP/1.1\r\n\r\n").into();
+
+    assert_eq!(req.method, Method::UNINITIALIZED);
+    assert_eq!(req.version, Version::UNINITIALIZED);
   }
 }
@@ -1,87 +1,67 @@
-use std::collections::HashMap;
 use std::io::{Result, Write};
 
+use crate::body::Body;
+use crate::chunked::ChunkedWriter;
+use crate::header_map::HeaderMap;
+use crate::status_code::StatusCode;
+
 /// Represents an HTTP response to a request.
-#[derive(Debug, PartialEq, Clone)]
-pub struct HttpResponse<'a> {
+#[derive(Debug, PartialEq)]
+pub struct HttpResponse {
   /// HTTP protocol version.
-  version: &'a str,
-  /// HTTP status numerical code.
-  status_code: &'a str,
-  // HTTP status text.
-  status_text: &'a str,
+  version: &'static str,
+  /// HTTP status code and reason phrase.
+  status_code: StatusCode,
   /// Headers of the HTTP response.
-  headers: Option<HashMap<&'a str, &'a str>>,
+  headers: HeaderMap,
   /// Body of the HTTP response.
-  body: Option<String>,
+  body: Body,
 }
 
-impl<'a> Default for HttpResponse<'a> {
+impl Default for HttpResponse {
   fn default() -> Self {
     Self {
       version: "HTTP/1.1",
-      status_code: "200",
-      status_text: "OK",
-      headers: None,
-      body: None,
+      status_code: StatusCode::OK,
+      headers: HeaderMap::new(),
+      body: Body::Empty,
     }
   }
 }
 
-impl<'a> From<HttpResponse<'a>> for String {
-  fn from(value: HttpResponse<'a>) -> String {
-    let res = value.clone();
-    format!(
-      "{} {} {}\r\n{}Content-Length: {}\r\n\r\n{}",
-      &res.version(),
-      &res.status_code(),
-      &res.status_text(),
-      &res.headers(),
-      if res.body.is_some() { res.body().len() } else { 0 },
-      &res.body()
-    )
+impl From<HttpResponse> for String {
+  fn from(value: HttpResponse) -> String {
+    let mut bytes: Vec<u8> = Vec::new();
+    let _ = value.send_response(&mut bytes);
+    String::from_utf8_lossy(&bytes).to_string()
   }
 }
 
-impl<'a> HttpResponse<'a> {
+impl HttpResponse {
   /// Creates an new [`HttpResponse`] object with default values and the given parameters.
   ///
   /// # Arguments
   ///
-  /// * `status_code`: HTTP status numerical code for the response.
-  /// * `headers`: Set of HTTP headers for the response.
+  /// * `status_code`: HTTP status code for the response.
+  /// * `headers`: Set of HTTP headers for the response. Defaults to an HTML content type.
   /// * `body`: Contents of the HTTP body for the response.
   pub fn new(
-    status_code: &'a str,
-    headers: Option<HashMap<&'a str, &'a str>>,
-    body: Option<String>,
-  ) -> HttpResponse<'a> {
-    let mut response: HttpResponse<'a> = HttpResponse::default();
-
-    if status_code != "200" {
-      response.status_code = status_code.into();
-    }
-
-    response.headers = match &headers {
-      Some(_h) => headers,
-      None => {
-        let mut h: HashMap<&str, &str> = HashMap::new();
-        h.insert("Content-Type", "text/html");
-        Some(h)
-      }
-    };
+    status_code: StatusCode,
+    headers: Option<HeaderMap>,
+    body: impl Into<Body>,
+  ) -> HttpResponse {
+    let headers: HeaderMap = headers.unwrap_or_else(|| {
+      let mut h: HeaderMap = HeaderMap::new();
+      h.insert("Content-Type", "text/html");
+      h
+    });
 
-    response.status_text = match response.status_code {
-      "200" => "OK",
-      "400" => "Bad Request",
-      "404" => "Not Found",
-      "500" => "Internal Server Error",
-      _ => "Not Found",
-    };
-
-    response.body = body;
-
-    response
+    HttpResponse {
+      status_code,
+      headers,
+      body: body.into(),
+      ..HttpResponse::default()
+    }
   } // end fn new()
 
   /// Gets the HTTP version.
@@ -89,47 +69,146 @@ impl<'a> HttpResponse<'a> {
     self.version
   }
 
-  /// Gets the HTTP status numerical code.
-  fn status_code(&self) -> &str {
-    self.status_code
+  /// Gets the HTTP status code.
+  pub fn status_code(&self) -> &StatusCode {
+    &self.status_code
+  }
+
+  /// Gets the HTTP headers.
+  pub fn headers(&self) -> &HeaderMap {
+    &self.headers
   }
 
-  /// Gets the HTTP status text.
-  fn status_text(&self) -> &str {
-    self.status_text
+  /// Gets the HTTP headers for modification.
+  pub fn headers_mut(&mut self) -> &mut HeaderMap {
+    &mut self.headers
   }
 
-  /// Gets the HTTP headers as a single text string.
-  fn headers(&self) -> String {
+  /// Gets the first value of the given header.
+  ///
+  /// # Arguments
+  ///
+  /// * `name`: Name of the header (case-insensitive).
+  pub fn header(
+    &self,
+    name: &str,
+  ) -> Option<&str> {
+    self.headers.get(name)
+  }
+
+  /// Gets the HTTP body.
+  pub fn body(&self) -> &Body {
+    &self.body
+  }
+
+  /// Replaces the HTTP body.
+  ///
+  /// # Arguments
+  ///
+  /// * `body`: New contents of the HTTP body.
+  pub fn set_body(
+    &mut self,
+    body: impl Into<Body>,
+  ) {
+    self.body = body.into();
+  }
+
+  /// Takes the HTTP body out of the response, leaving it empty.
+  pub fn take_body(&mut self) -> Body {
+    std::mem::take(&mut self.body)
+  }
+
+  /// Gets the HTTP headers as a single text string, in the order they were added. The
+  /// `Content-Length` and `Transfer-Encoding` headers are left out because they are
+  /// computed from the body.
+  fn header_lines(&self) -> String {
     let mut header_string: String = "".to_string();
 
-    for (k, v) in self.headers.as_ref().unwrap() {
-      header_string = format!("{}{}:{}\r\n", header_string, k, v);
+    for (k, v) in self.headers.iter() {
+      if !k.eq_ignore_ascii_case("Content-Length")
+        && !k.eq_ignore_ascii_case("Transfer-Encoding")
+      {
+        header_string = format!("{}{}:{}\r\n", header_string, k, v);
+      }
     }
     header_string
   }
 
-  /// Gets the HTTP body.
-  pub fn body(&self) -> &str {
-    match &self.body {
-      Some(b) => b.as_str(),
-      None => "",
-    }
+  /// Checks whether the status code of the response forbids a body: informational,
+  /// "204 No Content" and "304 Not Modified" responses.
+  fn is_bodiless(&self) -> bool {
+    self.status_code.is_informational()
+      || self.status_code == StatusCode::NO_CONTENT
+      || self.status_code == StatusCode::NOT_MODIFIED
+  }
+
+  /// Gets the status line and the headers, including the terminating empty line. A body
+  /// of unknown length is announced with the chunked transfer coding.
+  fn head(&self) -> String {
+    let content_length: String = match self.body.len() {
+      _ if self.is_bodiless() => "".to_string(),
+      Some(length) => format!("Content-Length: {}\r\n", length),
+      None => "Transfer-Encoding: chunked\r\n".to_string(),
+    };
+
+    format!(
+      "{} {}\r\n{}{}\r\n",
+      self.version(),
+      self.status_code(),
+      self.header_lines(),
+      content_length
+    )
   }
 
-  /// Sends this response as a byte stream.
+  /// Sends this response as a byte stream. A body of unknown length is streamed with
+  /// the chunked transfer coding as it is produced.
   ///
   /// # Arguments
   ///
   /// * `write_stream`: Byte stream writer. Recommended: a TCP stream
   pub fn send_response(
-    &self,
+    self,
     write_stream: &mut impl Write,
   ) -> Result<()> {
-    let response = self.clone();
-    let _ = write!(write_stream, "{}", String::from(response));
-    Ok(())
+    write_stream.write_all(self.head().as_bytes())?;
+
+    if self.is_bodiless() {
+      // Nothing is sent
+    } else if self.body.len().is_none() {
+      let mut chunked: ChunkedWriter<&mut _> = ChunkedWriter::new(&mut *write_stream);
+      self.body.write_to(&mut chunked)?;
+      chunked.finish()?;
+    } else {
+      self.body.write_to(write_stream)?;
+    }
+    write_stream.flush()
   } // end fn send_response()
+
+  /// Reads a body of unknown length into memory, so it is sent with a `Content-Length`
+  /// header instead of the chunked transfer coding, which HTTP/1.0 clients do not
+  /// understand.
+  pub fn buffer_body(&mut self) -> Result<()> {
+    if self.body.len().is_none() {
+      let body: Body = self.take_body();
+      self.body = Body::Bytes(body.into_bytes()?);
+    }
+    Ok(())
+  }
+
+  /// Sends only the status line and the headers of this response as a byte stream, as
+  /// required to answer a HEAD request. The `Content-Length` header still describes the
+  /// body that a GET request would receive.
+  ///
+  /// # Arguments
+  ///
+  /// * `write_stream`: Byte stream writer. Recommended: a TCP stream
+  pub fn send_head(
+    &self,
+    write_stream: &mut impl Write,
+  ) -> Result<()> {
+    write_stream.write_all(self.head().as_bytes())?;
+    write_stream.flush()
+  } // end fn send_head()
 }
 
 #[cfg(test)]
@@ -139,21 +218,16 @@ mod tests {
   #[test]
   fn test_response_struct_creation_200() {
     let response_actual = HttpResponse::new(
-      "200",
+      StatusCode::OK,
       None,
       Some("Item was shipped on 21st Dec 2020".to_string()),
     );
 
     let response_expected = HttpResponse {
       version: "HTTP/1.1",
-      status_code: "200",
-      status_text: "OK",
-      headers: {
-        let mut h: HashMap<&str, &str> = HashMap::new();
-        h.insert("Content-Type", "text/html");
-        Some(h)
-      },
-      body: Some("Item was shipped on 21st Dec 2020".to_string()),
+      status_code: StatusCode::OK,
+      headers: HeaderMap::from([("Content-Type", "text/html")]),
+      body: Body::from("Item was shipped on 21st Dec 2020"),
     };
 
     assert_eq!(response_actual, response_expected);
@@ -162,21 +236,16 @@ mod tests {
   #[test]
   fn test_response_struct_creation_400() {
     let response_actual = HttpResponse::new(
-      "404",
+      StatusCode::NOT_FOUND,
       None,
       Some("Item was shipped on 21st Dec 2020".to_string()),
     );
 
     let response_expected = HttpResponse {
       version: "HTTP/1.1",
-      status_code: "404",
-      status_text: "Not Found",
-      headers: {
-        let mut h: HashMap<&str, &str> = HashMap::new();
-        h.insert("Content-Type", "text/html");
-        Some(h)
-      },
-      body: Some("Item was shipped on 21st Dec 2020".to_string()),
+      status_code: StatusCode::NOT_FOUND,
+      headers: HeaderMap::from([("Content-Type", "text/html")]),
+      body: Body::from("Item was shipped on 21st Dec 2020"),
     };
 
     assert_eq!(response_actual, response_expected);
@@ -186,14 +255,9 @@ mod tests {
   fn test_http_response_creation() {
     let response_actual = HttpResponse {
       version: "HTTP/1.1",
-      status_code: "404",
-      status_text: "Not Found",
-      headers: {
-        let mut h: HashMap<&str, &str> = HashMap::new();
-        h.insert("Content-Type", "text/html");
-        Some(h)
-      },
-      body: Some("Item was shipped on 21st Dec 2020".to_string()),
+      status_code: StatusCode::NOT_FOUND,
+      headers: HeaderMap::from([("Content-Type", "text/html")]),
+      body: Body::from("Item was shipped on 21st Dec 2020"),
     };
 
     let http_actual: String = response_actual.into();
@@ -202,18 +266,28 @@ mod tests {
     assert_eq!(http_actual, http_expected);
   }
 
+  #[test]
+  fn test_http_response_buffered_body() {
+    let mut response = HttpResponse::new(
+      StatusCode::OK,
+      None,
+      Body::from_reader(&b"streamed"[..], None),
+    );
+    response.buffer_body().unwrap();
+
+    let http_actual: String = response.into();
+    let http_expected =
+      "HTTP/1.1 200 OK\r\nContent-Type:text/html\r\nContent-Length: 8\r\n\r\nstreamed";
+    assert_eq!(http_actual, http_expected);
+  }
+
   #[test]
   fn test_http_response_empty_body() {
     let response_actual = HttpResponse {
       version: "HTTP/1.1",
-      status_code: "404",
-      status_text: "Not Found",
-      headers: {
-        let mut h: HashMap<&str, &str> = HashMap::new();
-        h.insert("Content-Type", "text/html");
-        Some(h)
-      },
-      body: None,
+      status_code: StatusCode::NOT_FOUND,
+      headers: HeaderMap::from([("Content-Type", "text/html")]),
+      body: Body::Empty,
     };
 
     let http_actual: String = String::from(response_actual);
@@ -222,4 +296,85 @@ mod tests {
 
     assert_eq!(http_actual, http_expected);
   }
+
+  #[test]
+  fn test_http_response_send_head() {
+    let response = HttpResponse::new(StatusCode::OK, None, Some("Hello".to_string()));
+
+    let mut stream: Vec<u8> = Vec::new();
+    response.send_head(&mut stream).unwrap();
+
+    assert_eq!(
+      String::from_utf8(stream).unwrap(),
+      "HTTP/1.1 200 OK\r\nContent-Type:text/html\r\nContent-Length: 5\r\n\r\n"
+    );
+  }
+
+  #[test]
+  fn test_http_response_header_order() {
+    let mut headers = HeaderMap::new();
+    headers.append("Set-Cookie", "a=1");
+    headers.append("Content-Type", "text/plain");
+    headers.append("Set-Cookie", "b=2");
+    headers.append("content-length", "99");
+
+    let response =
+      HttpResponse::new(StatusCode::OK, Some(headers), Some("Hi".to_string()));
+    assert_eq!(response.header("content-type"), Some("text/plain"));
+
+    let http_actual: String = response.into();
+    let http_expected = "HTTP/1.1 200 OK\r\nSet-Cookie:a=1\r\nContent-Type:text/plain\r\nSet-Cookie:b=2\r\nContent-Length: 2\r\n\r\nHi";
+
+    assert_eq!(http_actual, http_expected);
+  }
+
+  #[test]
+  fn test_http_response_status_line() {
+    let response_actual = HttpResponse::new(StatusCode::CREATED, None, Body::Empty);
+    let http_actual: String = response_actual.into();
+    assert!(http_actual.starts_with("HTTP/1.1 201 Created\r\n"));
+
+    let response_actual = HttpResponse::new(
+      StatusCode::with_reason(299, "Custom").unwrap(),
+      None,
+      Body::Empty,
+    );
+    let http_actual: String = response_actual.into();
+    assert!(http_actual.starts_with("HTTP/1.1 299 Custom\r\n"));
+  }
+
+  #[test]
+  fn test_http_response_binary_body() {
+    let body: Vec<u8> = vec![0x89, b'P', b'N', b'G', 0x00, 0xff];
+    let response = HttpResponse::new(
+      StatusCode::OK,
+      Some(HeaderMap::from([("Content-Type", "image/png")])),
+      body.clone(),
+    );
+
+    let mut stream: Vec<u8> = Vec::new();
+    response.send_response(&mut stream).unwrap();
+
+    let mut expected: Vec<u8> =
+      b"HTTP/1.1 200 OK\r\nContent-Type:image/png\r\nContent-Length: 6\r\n\r\n".to_vec();
+    expected.extend_from_slice(&body);
+    assert_eq!(stream, expected);
+  }
+
+  #[test]
+  fn test_http_response_stream_body() {
+    let response = HttpResponse::new(
+      StatusCode::OK,
+      None,
+      Body::from_reader(&b"streamed"[..], None),
+    );
+
+    let mut stream: Vec<u8> = Vec::new();
+    response.send_response(&mut stream).unwrap();
+
+    assert_eq!(
+      String::from_utf8(stream).unwrap(),
+      "HTTP/1.1 200 OK\r\nContent-Type:text/html\r\nTransfer-Encoding: chunked\r\n\r\n8\r\nstreamed\r\n0\r\n\r\n"
+    );
+  }
 }
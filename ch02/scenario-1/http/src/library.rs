@@ -1,2 +1,12 @@
+pub mod body;
+pub mod chunked;
+pub mod client;
+pub mod date;
+pub mod encoding;
+pub mod header_map;
 pub mod http_request;
 pub mod http_response;
+pub mod resource;
+pub mod sse;
+pub mod status_code;
+pub mod websocket;
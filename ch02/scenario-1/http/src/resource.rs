@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+
+use crate::http_request::ParseError;
+
+/// Represents the parsed query string of a request target, where each parameter name can
+/// have several values.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Query {
+  /// Decoded values of each parameter, in the order they were given.
+  params: HashMap<String, Vec<String>>,
+}
+
+impl Query {
+  /// Parses a query string (without the leading `?`) in the
+  /// `application/x-www-form-urlencoded` format.
+  ///
+  /// # Arguments
+  ///
+  /// * `query`: Raw query string.
+  pub fn parse(query: &str) -> Result<Query, ParseError> {
+    let mut params: HashMap<String, Vec<String>> = HashMap::new();
+
+    for pair in query.split('&').filter(|p| !p.is_empty()) {
+      let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+      let name: String = percent_decode(&name.replace('+', " "))?;
+      let value: String = percent_decode(&value.replace('+', " "))?;
+      params.entry(name).or_default().push(value);
+    }
+
+    Ok(Query { params })
+  }
+
+  /// Gets the first value of the given parameter.
+  ///
+  /// # Arguments
+  ///
+  /// * `name`: Name of the parameter.
+  pub fn get(
+    &self,
+    name: &str,
+  ) -> Option<&str> {
+    self.get_all(name).first().map(String::as_str)
+  }
+
+  /// Gets every value of the given parameter.
+  ///
+  /// # Arguments
+  ///
+  /// * `name`: Name of the parameter.
+  pub fn get_all(
+    &self,
+    name: &str,
+  ) -> &[String] {
+    self.params.get(name).map(Vec::as_slice).unwrap_or_default()
+  }
+
+  /// Checks whether the query has no parameters.
+  pub fn is_empty(&self) -> bool {
+    self.params.is_empty()
+  }
+}
+
+/// Represents the target of a request: a REST resource path with its query.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Resource {
+  /// Request target exactly as received.
+  pub target: String,
+  /// Decoded path of the resource, without the query and the fragment.
+  pub path: String,
+  /// Decoded non-empty segments of the path.
+  pub segments: Vec<String>,
+  /// Parameters of the query string.
+  pub query: Query,
+}
+
+impl Resource {
+  /// Parses a request target in origin-form (`/path?query`) or absolute-form
+  /// (`http://host/path?query`). Any fragment is stripped.
+  ///
+  /// # Arguments
+  ///
+  /// * `target`: Request target from the request line.
+  pub fn parse(target: &str) -> Result<Resource, ParseError> {
+    // Clients should not send fragments, but strip them anyway
+    let without_fragment: &str = target.split('#').next().unwrap_or_default();
+
+    // Keep only the path of an absolute-form target
+    let relative: &str = match without_fragment.split_once("://") {
+      Some((_scheme, rest)) => rest.find('/').map(|i| &rest[i..]).unwrap_or("/"),
+      None => without_fragment,
+    };
+
+    let (raw_path, raw_query) = relative.split_once('?').unwrap_or((relative, ""));
+    if !raw_path.starts_with('/') && raw_path != "*" {
+      return Err(ParseError::InvalidRequestTarget);
+    }
+
+    // Decode each segment on its own, so an encoded slash does not split a segment
+    let segments: Vec<String> = raw_path
+      .split('/')
+      .filter(|s| !s.is_empty())
+      .map(percent_decode)
+      .collect::<Result<Vec<String>, ParseError>>()?;
+
+    Ok(Resource {
+      target: target.to_string(),
+      path: percent_decode(raw_path)?,
+      segments,
+      query: Query::parse(raw_query)?,
+    })
+  }
+
+  /// Gets the path segments as string slices, which is convenient for matching.
+  pub fn segments(&self) -> Vec<&str> {
+    self.segments.iter().map(String::as_str).collect()
+  }
+}
+
+/// Decodes the `%XX` escape sequences of the given text.
+///
+/// # Arguments
+///
+/// * `text`: Percent-encoded text.
+pub fn percent_decode(text: &str) -> Result<String, ParseError> {
+  let bytes: &[u8] = text.as_bytes();
+  let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+  let mut i: usize = 0;
+
+  while i < bytes.len() {
+    if bytes[i] == b'%' {
+      let hex: &str = text
+        .get(i + 1..i + 3)
+        .ok_or(ParseError::InvalidRequestTarget)?;
+      if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(ParseError::InvalidRequestTarget);
+      }
+      decoded
+        .push(u8::from_str_radix(hex, 16).map_err(|_| ParseError::InvalidRequestTarget)?);
+      i += 3;
+    } else {
+      decoded.push(bytes[i]);
+      i += 1;
+    }
+  }
+
+  String::from_utf8(decoded).map_err(|_| ParseError::InvalidRequestTarget)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_resource_parse() {
+    let resource = Resource::parse("/api/shipping/orders?status=Pending#top").unwrap();
+
+    assert_eq!(resource.target, "/api/shipping/orders?status=Pending#top");
+    assert_eq!(resource.path, "/api/shipping/orders");
+    assert_eq!(resource.segments(), vec!["api", "shipping", "orders"]);
+    assert_eq!(resource.query.get("status"), Some("Pending"));
+  }
+
+  #[test]
+  fn test_resource_parse_decoding() {
+    let resource = Resource::parse("/docs/a%20b/c%2Fd/").unwrap();
+
+    assert_eq!(resource.path, "/docs/a b/c/d/");
+    assert_eq!(resource.segments(), vec!["docs", "a b", "c/d"]);
+
+    let resource = Resource::parse("http://localhost:3000/health?x=1").unwrap();
+    assert_eq!(resource.path, "/health");
+
+    let resource = Resource::parse("/").unwrap();
+    assert!(resource.segments.is_empty());
+    assert!(resource.query.is_empty());
+  }
+
+  #[test]
+  fn test_resource_parse_errors() {
+    for target in ["health", "/a%2", "/a%zz", "/a%ff", "/?q=%"] {
+      assert_eq!(
+        Resource::parse(target),
+        Err(ParseError::InvalidRequestTarget)
+      );
+    }
+  }
+
+  #[test]
+  fn test_query_multi_valued() {
+    let query = Query::parse("id=1&id=2&name=J%C3%BAlio+Verne&flag&&").unwrap();
+
+    assert_eq!(query.get_all("id"), ["1", "2"]);
+    assert_eq!(query.get("id"), Some("1"));
+    assert_eq!(query.get("name"), Some("Júlio Verne"));
+    assert_eq!(query.get("flag"), Some(""));
+    assert_eq!(query.get("other"), None);
+    assert!(query.get_all("other").is_empty());
+  }
+}
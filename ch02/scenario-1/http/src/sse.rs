@@ -0,0 +1,161 @@
+use std::io::Read;
+
+use crate::body::Body;
+use crate::header_map::HeaderMap;
+use crate::http_request::HttpRequest;
+use crate::http_response::HttpResponse;
+use crate::status_code::StatusCode;
+
+/// Media type of a stream of server-sent events.
+pub const CONTENT_TYPE: &str = "text/event-stream";
+
+/// Represents an event sent to a client of an event stream (HTML Living Standard,
+/// section 9.2).
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Event {
+  /// ID of the event, sent back by the client in `Last-Event-ID` when it reconnects.
+  pub id: Option<String>,
+  /// Type of the event; clients dispatch `message` when there is none.
+  pub event: Option<String>,
+  /// Data of the event, possibly on several lines.
+  pub data: String,
+}
+
+impl Event {
+  /// Creates a new [`Event`] object without ID or type.
+  ///
+  /// # Arguments
+  ///
+  /// * `data`: Data of the event.
+  pub fn new(data: impl Into<String>) -> Self {
+    Self {
+      id: None,
+      event: None,
+      data: data.into(),
+    }
+  }
+
+  /// Sets the ID of the event.
+  ///
+  /// # Arguments
+  ///
+  /// * `id`: ID of the event, without line breaks.
+  pub fn with_id(
+    mut self,
+    id: impl Into<String>,
+  ) -> Self {
+    self.id = Some(id.into());
+    self
+  }
+
+  /// Sets the type of the event.
+  ///
+  /// # Arguments
+  ///
+  /// * `event`: Type of the event, without line breaks.
+  pub fn with_event(
+    mut self,
+    event: impl Into<String>,
+  ) -> Self {
+    self.event = Some(event.into());
+    self
+  }
+
+  /// Encodes the event as it is sent on the stream. Every line of the data becomes a
+  /// `data` field.
+  pub fn encode(&self) -> String {
+    let mut encoded: String = String::new();
+
+    if let Some(event) = &self.event {
+      encoded.push_str(&format!("event: {}\n", event));
+    }
+    if let Some(id) = &self.id {
+      encoded.push_str(&format!("id: {}\n", id));
+    }
+    for line in self.data.split('\n') {
+      encoded.push_str(&format!(
+        "data: {}\n",
+        line.strip_suffix('\r').unwrap_or(line)
+      ));
+    }
+    encoded.push('\n');
+    encoded
+  }
+}
+
+/// Encodes a comment, ignored by clients, e.g. to keep an idle connection open.
+///
+/// # Arguments
+///
+/// * `text`: Text of the comment, without line breaks.
+pub fn comment(text: &str) -> String {
+  format!(": {}\n\n", text)
+}
+
+/// Encodes the field telling clients how long to wait before reconnecting.
+///
+/// # Arguments
+///
+/// * `milliseconds`: Reconnection time in milliseconds.
+pub fn retry(milliseconds: u64) -> String {
+  format!("retry: {}\n\n", milliseconds)
+}
+
+/// Gets the ID of the last event received by a reconnecting client.
+///
+/// # Arguments
+///
+/// * `request`: HTTP request opening the stream.
+pub fn last_event_id(request: &HttpRequest) -> Option<&str> {
+  request.headers.get("Last-Event-ID").map(str::trim)
+}
+
+/// Creates the response streaming the events produced by a reader, which must return
+/// the encoded events as they happen and end when the stream is over. The body has
+/// no known length, so it is sent with the chunked transfer coding.
+///
+/// # Arguments
+///
+/// * `reader`: Reader of the encoded events.
+pub fn response(reader: impl Read + Send + 'static) -> HttpResponse {
+  let mut headers: HeaderMap = HeaderMap::new();
+  headers.insert("Content-Type", CONTENT_TYPE);
+  headers.insert("Cache-Control", "no-cache");
+
+  HttpResponse::new(
+    StatusCode::OK,
+    Some(headers),
+    Body::from_reader(reader, None),
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_event_encoding() {
+    assert_eq!(Event::new("hello").encode(), "data: hello\n\n");
+    assert_eq!(
+      Event::new("first\nsecond\r\nthird")
+        .with_event("order-updated")
+        .with_id("42")
+        .encode(),
+      "event: order-updated\nid: 42\ndata: first\ndata: second\ndata: third\n\n"
+    );
+    assert_eq!(comment("keep-alive"), ": keep-alive\n\n");
+    assert_eq!(retry(3000), "retry: 3000\n\n");
+  }
+
+  #[test]
+  fn test_response() {
+    let request = HttpRequest::from(String::from(
+      "GET /events HTTP/1.1\r\nLast-Event-ID: 7 \r\n\r\n",
+    ));
+    assert_eq!(last_event_id(&request), Some("7"));
+
+    let response = response(&b"data: hello\n\n"[..]);
+    assert_eq!(response.headers().content_type(), Some(CONTENT_TYPE));
+    assert_eq!(response.body().len(), None);
+  }
+}
@@ -0,0 +1,239 @@
+use std::fmt;
+
+/// Represents the status code of an HTTP response with its reason phrase.
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct StatusCode {
+  /// Three-digit numerical code.
+  code: u16,
+  /// Reason phrase replacing the canonical one, if any.
+  reason: Option<String>,
+}
+
+/// Defines a constant for each registered status code and the table of canonical reason
+/// phrases from the same list.
+macro_rules! status_codes {
+  ($(($code:literal, $name:ident, $reason:literal),)+) => {
+    impl StatusCode {
+      $(
+        #[doc = concat!("`", $code, " ", $reason, "`")]
+        pub const $name: StatusCode = StatusCode {
+          code: $code,
+          reason: None,
+        };
+      )+
+    }
+
+    /// Gets the canonical reason phrase of a registered status code.
+    ///
+    /// # Arguments
+    ///
+    /// * `code`: Numerical status code.
+    pub fn canonical_reason(code: u16) -> Option<&'static str> {
+      match code {
+        $($code => Some($reason),)+
+        _ => None,
+      }
+    }
+  };
+}
+
+// IANA HTTP Status Code Registry
+status_codes! {
+  (100, CONTINUE, "Continue"),
+  (101, SWITCHING_PROTOCOLS, "Switching Protocols"),
+  (102, PROCESSING, "Processing"),
+  (103, EARLY_HINTS, "Early Hints"),
+  (200, OK, "OK"),
+  (201, CREATED, "Created"),
+  (202, ACCEPTED, "Accepted"),
+  (203, NON_AUTHORITATIVE_INFORMATION, "Non-Authoritative Information"),
+  (204, NO_CONTENT, "No Content"),
+  (205, RESET_CONTENT, "Reset Content"),
+  (206, PARTIAL_CONTENT, "Partial Content"),
+  (207, MULTI_STATUS, "Multi-Status"),
+  (208, ALREADY_REPORTED, "Already Reported"),
+  (226, IM_USED, "IM Used"),
+  (300, MULTIPLE_CHOICES, "Multiple Choices"),
+  (301, MOVED_PERMANENTLY, "Moved Permanently"),
+  (302, FOUND, "Found"),
+  (303, SEE_OTHER, "See Other"),
+  (304, NOT_MODIFIED, "Not Modified"),
+  (305, USE_PROXY, "Use Proxy"),
+  (307, TEMPORARY_REDIRECT, "Temporary Redirect"),
+  (308, PERMANENT_REDIRECT, "Permanent Redirect"),
+  (400, BAD_REQUEST, "Bad Request"),
+  (401, UNAUTHORIZED, "Unauthorized"),
+  (402, PAYMENT_REQUIRED, "Payment Required"),
+  (403, FORBIDDEN, "Forbidden"),
+  (404, NOT_FOUND, "Not Found"),
+  (405, METHOD_NOT_ALLOWED, "Method Not Allowed"),
+  (406, NOT_ACCEPTABLE, "Not Acceptable"),
+  (407, PROXY_AUTHENTICATION_REQUIRED, "Proxy Authentication Required"),
+  (408, REQUEST_TIMEOUT, "Request Timeout"),
+  (409, CONFLICT, "Conflict"),
+  (410, GONE, "Gone"),
+  (411, LENGTH_REQUIRED, "Length Required"),
+  (412, PRECONDITION_FAILED, "Precondition Failed"),
+  (413, CONTENT_TOO_LARGE, "Content Too Large"),
+  (414, URI_TOO_LONG, "URI Too Long"),
+  (415, UNSUPPORTED_MEDIA_TYPE, "Unsupported Media Type"),
+  (416, RANGE_NOT_SATISFIABLE, "Range Not Satisfiable"),
+  (417, EXPECTATION_FAILED, "Expectation Failed"),
+  (421, MISDIRECTED_REQUEST, "Misdirected Request"),
+  (422, UNPROCESSABLE_CONTENT, "Unprocessable Content"),
+  (423, LOCKED, "Locked"),
+  (424, FAILED_DEPENDENCY, "Failed Dependency"),
+  (425, TOO_EARLY, "Too Early"),
+  (426, UPGRADE_REQUIRED, "Upgrade Required"),
+  (428, PRECONDITION_REQUIRED, "Precondition Required"),
+  (429, TOO_MANY_REQUESTS, "Too Many Requests"),
+  (431, REQUEST_HEADER_FIELDS_TOO_LARGE, "Request Header Fields Too Large"),
+  (451, UNAVAILABLE_FOR_LEGAL_REASONS, "Unavailable For Legal Reasons"),
+  (500, INTERNAL_SERVER_ERROR, "Internal Server Error"),
+  (501, NOT_IMPLEMENTED, "Not Implemented"),
+  (502, BAD_GATEWAY, "Bad Gateway"),
+  (503, SERVICE_UNAVAILABLE, "Service Unavailable"),
+  (504, GATEWAY_TIMEOUT, "Gateway Timeout"),
+  (505, HTTP_VERSION_NOT_SUPPORTED, "HTTP Version Not Supported"),
+  (506, VARIANT_ALSO_NEGOTIATES, "Variant Also Negotiates"),
+  (507, INSUFFICIENT_STORAGE, "Insufficient Storage"),
+  (508, LOOP_DETECTED, "Loop Detected"),
+  (510, NOT_EXTENDED, "Not Extended"),
+  (511, NETWORK_AUTHENTICATION_REQUIRED, "Network Authentication Required"),
+}
+
+impl StatusCode {
+  /// Creates a [`StatusCode`] object from a three-digit code (100 to 999), which may be
+  /// unregistered.
+  ///
+  /// # Arguments
+  ///
+  /// * `code`: Numerical status code.
+  pub fn from_u16(code: u16) -> Option<StatusCode> {
+    (100..1000)
+      .contains(&code)
+      .then_some(StatusCode { code, reason: None })
+  }
+
+  /// Creates a [`StatusCode`] object with a custom reason phrase.
+  ///
+  /// # Arguments
+  ///
+  /// * `code`: Numerical status code (100 to 999).
+  /// * `reason`: Reason phrase to send instead of the canonical one.
+  pub fn with_reason(
+    code: u16,
+    reason: &str,
+  ) -> Option<StatusCode> {
+    let mut status: StatusCode = StatusCode::from_u16(code)?;
+    status.reason = Some(reason.to_string());
+    Some(status)
+  }
+
+  /// Gets the numerical code.
+  pub fn as_u16(&self) -> u16 {
+    self.code
+  }
+
+  /// Gets the reason phrase: the custom one if given, otherwise the canonical one, or an
+  /// empty text for unregistered codes.
+  pub fn reason(&self) -> &str {
+    match &self.reason {
+      Some(reason) => reason.as_str(),
+      None => canonical_reason(self.code).unwrap_or_default(),
+    }
+  }
+
+  /// Checks whether the code is informational (1xx).
+  pub fn is_informational(&self) -> bool {
+    (100..200).contains(&self.code)
+  }
+
+  /// Checks whether the code is successful (2xx).
+  pub fn is_success(&self) -> bool {
+    (200..300).contains(&self.code)
+  }
+
+  /// Checks whether the code is a redirection (3xx).
+  pub fn is_redirection(&self) -> bool {
+    (300..400).contains(&self.code)
+  }
+
+  /// Checks whether the code is a client error (4xx).
+  pub fn is_client_error(&self) -> bool {
+    (400..500).contains(&self.code)
+  }
+
+  /// Checks whether the code is a server error (5xx).
+  pub fn is_server_error(&self) -> bool {
+    (500..600).contains(&self.code)
+  }
+}
+
+impl Default for StatusCode {
+  fn default() -> Self {
+    StatusCode::OK
+  }
+}
+
+impl fmt::Display for StatusCode {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    write!(f, "{} {}", self.code, self.reason())
+  }
+}
+
+impl PartialEq<u16> for StatusCode {
+  fn eq(
+    &self,
+    other: &u16,
+  ) -> bool {
+    self.code == *other
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_canonical_reasons() {
+    assert_eq!(StatusCode::OK.to_string(), "200 OK");
+    assert_eq!(StatusCode::CREATED.to_string(), "201 Created");
+    assert_eq!(StatusCode::NOT_FOUND.reason(), "Not Found");
+    assert_eq!(
+      StatusCode::from_u16(431).unwrap().reason(),
+      "Request Header Fields Too Large"
+    );
+    assert_eq!(StatusCode::from_u16(299).unwrap().to_string(), "299 ");
+  }
+
+  #[test]
+  fn test_custom_reason() {
+    let status = StatusCode::with_reason(200, "Fine").unwrap();
+
+    assert_eq!(status.to_string(), "200 Fine");
+    assert_eq!(status, 200);
+    assert_ne!(status, StatusCode::OK);
+  }
+
+  #[test]
+  fn test_invalid_codes() {
+    assert_eq!(StatusCode::from_u16(99), None);
+    assert_eq!(StatusCode::from_u16(1000), None);
+    assert_eq!(StatusCode::with_reason(0, "Zero"), None);
+  }
+
+  #[test]
+  fn test_classes() {
+    assert!(StatusCode::CONTINUE.is_informational());
+    assert!(StatusCode::NO_CONTENT.is_success());
+    assert!(StatusCode::NOT_MODIFIED.is_redirection());
+    assert!(StatusCode::CONFLICT.is_client_error());
+    assert!(StatusCode::BAD_GATEWAY.is_server_error());
+    assert!(!StatusCode::NOT_FOUND.is_success());
+    assert!(!StatusCode::from_u16(600).unwrap().is_server_error());
+  }
+}
@@ -0,0 +1,1159 @@
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+use crate::body::Body;
+use crate::header_map::HeaderMap;
+use crate::http_request::{HttpRequest, Method, Version};
+use crate::http_response::HttpResponse;
+use crate::status_code::StatusCode;
+
+/// GUID appended to the key of the client to compute the accept key (RFC 6455, section
+/// 1.3).
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// Only version of the protocol supported.
+const VERSION: &str = "13";
+/// Maximum payload length of a control frame.
+const MAX_CONTROL_PAYLOAD: usize = 125;
+/// Maximum number of bytes of the handshake response read by a client.
+const MAX_HANDSHAKE_SIZE: usize = 16 * 1024;
+
+/// Default maximum number of bytes of a message, once its fragments are joined.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+/// Default maximum number of bytes of the payload of a frame sent.
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 64 * 1024;
+
+/// Close code of a normal closure.
+pub const CLOSE_NORMAL: u16 = 1000;
+/// Close code of an endpoint going away, e.g. a server shutting down.
+pub const CLOSE_GOING_AWAY: u16 = 1001;
+/// Close code of a protocol error.
+pub const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+/// Close code of a text message that is not valid UTF-8.
+pub const CLOSE_INVALID_DATA: u16 = 1007;
+/// Close code of a message too big to process.
+pub const CLOSE_TOO_BIG: u16 = 1009;
+
+/// Represents a stream a WebSocket connection runs on, so connections over different
+/// streams (e.g. plaintext and TLS) can be served by the same code.
+pub trait Stream: Read + Write {}
+
+impl<T: Read + Write + ?Sized> Stream for T {}
+
+/// Represents the errors of a WebSocket connection.
+#[derive(Debug)]
+pub enum WebSocketError {
+  /// The stream failed or closed without a close frame.
+  Io(io::Error),
+  /// The opening handshake was refused.
+  Handshake(String),
+  /// The peer broke the protocol.
+  Protocol(&'static str),
+  /// A text message is not valid UTF-8.
+  InvalidUtf8,
+  /// A message is larger than allowed.
+  MessageTooBig,
+  /// The peer stopped answering pings.
+  Timeout,
+  /// The connection is closed.
+  Closed,
+}
+
+impl WebSocketError {
+  /// Gets the close code telling the peer about this error, if it broke the protocol.
+  pub fn close_code(&self) -> Option<u16> {
+    match self {
+      WebSocketError::Protocol(_) => Some(CLOSE_PROTOCOL_ERROR),
+      WebSocketError::InvalidUtf8 => Some(CLOSE_INVALID_DATA),
+      WebSocketError::MessageTooBig => Some(CLOSE_TOO_BIG),
+      _ => None,
+    }
+  }
+}
+
+impl fmt::Display for WebSocketError {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    match self {
+      WebSocketError::Io(e) => write!(f, "{}", e),
+      WebSocketError::Handshake(message) => write!(f, "handshake failed: {}", message),
+      WebSocketError::Protocol(message) => write!(f, "protocol error: {}", message),
+      WebSocketError::InvalidUtf8 => write!(f, "text message is not valid UTF-8"),
+      WebSocketError::MessageTooBig => write!(f, "message too big"),
+      WebSocketError::Timeout => write!(f, "peer stopped answering"),
+      WebSocketError::Closed => write!(f, "connection closed"),
+    }
+  }
+}
+
+impl std::error::Error for WebSocketError {}
+
+impl From<io::Error> for WebSocketError {
+  fn from(value: io::Error) -> Self {
+    WebSocketError::Io(value)
+  }
+}
+
+/// Represents the reasons a request cannot be upgraded to a WebSocket connection.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum HandshakeError {
+  /// The request is not a GET request over HTTP/1.1.
+  InvalidRequest,
+  /// The request does not ask for the upgrade.
+  NotUpgrade,
+  /// The client speaks another version of the protocol.
+  UnsupportedVersion,
+  /// The `Sec-WebSocket-Key` header is missing or is not 16 bytes in base64.
+  InvalidKey,
+}
+
+impl HandshakeError {
+  /// Creates the response refusing the upgrade: "426 Upgrade Required" when the client
+  /// must upgrade or switch versions, and "400 Bad Request" otherwise.
+  pub fn response(&self) -> HttpResponse {
+    let mut headers: HeaderMap = HeaderMap::new();
+    let status_code: StatusCode = match self {
+      HandshakeError::NotUpgrade | HandshakeError::UnsupportedVersion => {
+        headers.insert("Upgrade", "websocket");
+        headers.insert("Sec-WebSocket-Version", VERSION);
+        StatusCode::UPGRADE_REQUIRED
+      }
+      HandshakeError::InvalidRequest | HandshakeError::InvalidKey => {
+        StatusCode::BAD_REQUEST
+      }
+    };
+    HttpResponse::new(status_code, Some(headers), Body::Empty)
+  }
+}
+
+/// Computes the `Sec-WebSocket-Accept` value proving the server read the key of the
+/// client.
+///
+/// # Arguments
+///
+/// * `key`: Value of the `Sec-WebSocket-Key` header.
+pub fn accept_key(key: &str) -> String {
+  let mut sha1: sha1_smol::Sha1 = sha1_smol::Sha1::new();
+  sha1.update(key.trim().as_bytes());
+  sha1.update(GUID.as_bytes());
+  BASE64.encode(sha1.digest().bytes())
+}
+
+/// Checks whether a request asks to upgrade the connection to the WebSocket protocol.
+///
+/// # Arguments
+///
+/// * `request`: HTTP request received.
+pub fn is_upgrade(request: &HttpRequest) -> bool {
+  let websocket: bool = request
+    .headers
+    .get_all("Upgrade")
+    .iter()
+    .flat_map(|value| value.split(','))
+    .any(|protocol| protocol.trim().eq_ignore_ascii_case("websocket"));
+
+  websocket && request.headers.connection().iter().any(|o| o == "upgrade")
+}
+
+/// Validates the opening handshake of a client (RFC 6455, section 4.2) and creates the
+/// "101 Switching Protocols" response accepting it.
+///
+/// # Arguments
+///
+/// * `request`: HTTP request received.
+pub fn handshake(request: &HttpRequest) -> Result<HttpResponse, HandshakeError> {
+  if request.method != Method::GET || request.version != Version::V1_1 {
+    return Err(HandshakeError::InvalidRequest);
+  }
+  if !is_upgrade(request) {
+    return Err(HandshakeError::NotUpgrade);
+  }
+  if request.headers.get("Sec-WebSocket-Version").map(str::trim) != Some(VERSION) {
+    return Err(HandshakeError::UnsupportedVersion);
+  }
+  let key: &str = request
+    .headers
+    .get("Sec-WebSocket-Key")
+    .map(str::trim)
+    .ok_or(HandshakeError::InvalidKey)?;
+  if !matches!(BASE64.decode(key), Ok(nonce) if nonce.len() == 16) {
+    return Err(HandshakeError::InvalidKey);
+  }
+
+  let mut headers: HeaderMap = HeaderMap::new();
+  headers.insert("Upgrade", "websocket");
+  headers.insert("Connection", "Upgrade");
+  headers.insert("Sec-WebSocket-Accept", &accept_key(key));
+  Ok(HttpResponse::new(
+    StatusCode::SWITCHING_PROTOCOLS,
+    Some(headers),
+    Body::Empty,
+  ))
+} // end fn handshake()
+
+/// Represents the kind of a frame.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Opcode {
+  /// Next fragment of a message.
+  Continuation,
+  /// First fragment of a text message.
+  Text,
+  /// First fragment of a binary message.
+  Binary,
+  /// Closing of the connection.
+  Close,
+  /// Request for a pong.
+  Ping,
+  /// Answer to a ping.
+  Pong,
+}
+
+impl Opcode {
+  /// Gets the opcode of the given 4-bit value, if defined.
+  ///
+  /// # Arguments
+  ///
+  /// * `value`: Value of the opcode field.
+  pub fn from_u8(value: u8) -> Option<Opcode> {
+    match value {
+      0x0 => Some(Opcode::Continuation),
+      0x1 => Some(Opcode::Text),
+      0x2 => Some(Opcode::Binary),
+      0x8 => Some(Opcode::Close),
+      0x9 => Some(Opcode::Ping),
+      0xA => Some(Opcode::Pong),
+      _ => None,
+    }
+  }
+
+  /// Gets the 4-bit value of the opcode.
+  pub fn as_u8(&self) -> u8 {
+    match self {
+      Opcode::Continuation => 0x0,
+      Opcode::Text => 0x1,
+      Opcode::Binary => 0x2,
+      Opcode::Close => 0x8,
+      Opcode::Ping => 0x9,
+      Opcode::Pong => 0xA,
+    }
+  }
+
+  /// Checks whether frames of this kind control the connection instead of carrying
+  /// messages.
+  pub fn is_control(&self) -> bool {
+    matches!(self, Opcode::Close | Opcode::Ping | Opcode::Pong)
+  }
+}
+
+/// Represents a frame of the WebSocket protocol (RFC 6455, section 5.2).
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Frame {
+  /// Whether this is the last fragment of its message.
+  pub fin: bool,
+  /// Kind of the frame.
+  pub opcode: Opcode,
+  /// Payload, unmasked.
+  pub payload: Vec<u8>,
+}
+
+impl Frame {
+  /// Creates a [`Frame`] object that is the only fragment of its message.
+  ///
+  /// # Arguments
+  ///
+  /// * `opcode`: Kind of the frame.
+  /// * `payload`: Payload of the frame.
+  pub fn new(
+    opcode: Opcode,
+    payload: impl Into<Vec<u8>>,
+  ) -> Self {
+    Self {
+      fin: true,
+      opcode,
+      payload: payload.into(),
+    }
+  }
+
+  /// Parses a frame from the beginning of the given bytes, and returns it along with the
+  /// number of bytes it takes, or `None` if more bytes are needed.
+  ///
+  /// # Arguments
+  ///
+  /// * `bytes`: Bytes received.
+  /// * `masked`: Whether the frame must be masked, as the ones sent by clients are.
+  /// * `max_payload`: Maximum length of the payload.
+  pub fn parse(
+    bytes: &[u8],
+    masked: bool,
+    max_payload: usize,
+  ) -> Result<Option<(Frame, usize)>, WebSocketError> {
+    if bytes.len() < 2 {
+      return Ok(None);
+    }
+    let fin: bool = bytes[0] & 0x80 != 0;
+    if bytes[0] & 0x70 != 0 {
+      return Err(WebSocketError::Protocol("reserved bits are set"));
+    }
+    let opcode: Opcode = Opcode::from_u8(bytes[0] & 0x0F)
+      .ok_or(WebSocketError::Protocol("unknown opcode"))?;
+    if (bytes[1] & 0x80 != 0) != masked {
+      return Err(WebSocketError::Protocol(match masked {
+        true => "frames from the client must be masked",
+        false => "frames from the server must not be masked",
+      }));
+    }
+
+    // The length takes 7 bits, or the next 2 or 8 bytes
+    let (length, mut position): (u64, usize) = match bytes[1] & 0x7F {
+      126 if bytes.len() >= 4 => (u64::from(u16::from_be_bytes([bytes[2], bytes[3]])), 4),
+      127 if bytes.len() >= 10 => {
+        let mut length: [u8; 8] = [0; 8];
+        length.copy_from_slice(&bytes[2..10]);
+        (u64::from_be_bytes(length), 10)
+      }
+      126 | 127 => return Ok(None),
+      length => (u64::from(length), 2),
+    };
+    if opcode.is_control() && (!fin || length > MAX_CONTROL_PAYLOAD as u64) {
+      return Err(WebSocketError::Protocol("invalid control frame"));
+    }
+    if length > max_payload as u64 {
+      return Err(WebSocketError::MessageTooBig);
+    }
+    let length: usize = length as usize;
+
+    let mask: Option<[u8; 4]> = match masked {
+      true if bytes.len() < position + 4 => return Ok(None),
+      true => {
+        let mask: [u8; 4] = [
+          bytes[position],
+          bytes[position + 1],
+          bytes[position + 2],
+          bytes[position + 3],
+        ];
+        position += 4;
+        Some(mask)
+      }
+      false => None,
+    };
+    if bytes.len() < position + length {
+      return Ok(None);
+    }
+
+    let mut payload: Vec<u8> = bytes[position..position + length].to_vec();
+    if let Some(mask) = mask {
+      apply_mask(&mut payload, mask);
+    }
+    Ok(Some((
+      Frame {
+        fin,
+        opcode,
+        payload,
+      },
+      position + length,
+    )))
+  } // end fn parse()
+
+  /// Encodes the frame, masking its payload with the given key as clients must.
+  ///
+  /// # Arguments
+  ///
+  /// * `mask`: Masking key, for the frames sent by clients.
+  pub fn encode(
+    &self,
+    mask: Option<[u8; 4]>,
+  ) -> Vec<u8> {
+    let mut bytes: Vec<u8> = Vec::with_capacity(self.payload.len() + 14);
+    bytes.push(if self.fin { 0x80 } else { 0 } | self.opcode.as_u8());
+
+    let mask_bit: u8 = if mask.is_some() { 0x80 } else { 0 };
+    match self.payload.len() {
+      length if length < 126 => bytes.push(mask_bit | length as u8),
+      length if length <= usize::from(u16::MAX) => {
+        bytes.push(mask_bit | 126);
+        bytes.extend_from_slice(&(length as u16).to_be_bytes());
+      }
+      length => {
+        bytes.push(mask_bit | 127);
+        bytes.extend_from_slice(&(length as u64).to_be_bytes());
+      }
+    }
+
+    let start: usize = bytes.len() + if mask.is_some() { 4 } else { 0 };
+    if let Some(mask) = mask {
+      bytes.extend_from_slice(&mask);
+    }
+    bytes.extend_from_slice(&self.payload);
+    if let Some(mask) = mask {
+      apply_mask(&mut bytes[start..], mask);
+    }
+    bytes
+  } // end fn encode()
+}
+
+/// Represents the code and reason of a close frame.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CloseFrame {
+  /// Close code, e.g. [`CLOSE_NORMAL`].
+  pub code: u16,
+  /// Reason, for debugging.
+  pub reason: String,
+}
+
+impl CloseFrame {
+  /// Parses the payload of a close frame, which may be empty.
+  ///
+  /// # Arguments
+  ///
+  /// * `payload`: Payload of the frame.
+  fn parse(payload: &[u8]) -> Result<Option<CloseFrame>, WebSocketError> {
+    match payload {
+      [] => Ok(None),
+      [_] => Err(WebSocketError::Protocol("invalid close frame")),
+      [high, low, reason @ ..] => {
+        let code: u16 = u16::from_be_bytes([*high, *low]);
+        // Codes below 1000, reserved for local use or not defined (RFC 6455, 7.4)
+        if !matches!(code, 1000..=1003 | 1007..=1014 | 3000..=4999) {
+          return Err(WebSocketError::Protocol("invalid close code"));
+        }
+        let reason: String =
+          String::from_utf8(reason.to_vec()).map_err(|_| WebSocketError::InvalidUtf8)?;
+        Ok(Some(CloseFrame { code, reason }))
+      }
+    }
+  }
+}
+
+/// Represents a message of a WebSocket connection.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Message {
+  /// UTF-8 text.
+  Text(String),
+  /// Binary data.
+  Binary(Vec<u8>),
+  /// Ping, answered automatically when received.
+  Ping(Vec<u8>),
+  /// Pong, the answer to a ping.
+  Pong(Vec<u8>),
+  /// Closing of the connection, with an optional code and reason.
+  Close(Option<CloseFrame>),
+}
+
+/// Represents the side of a WebSocket connection.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Role {
+  /// The side that accepted the connection, receiving masked frames.
+  Server,
+  /// The side that opened the connection, masking the frames it sends.
+  Client,
+}
+
+/// Represents a WebSocket connection over a stream, once the opening handshake is done.
+///
+/// Pings are answered and close frames are echoed as they are received. When the
+/// stream has a read timeout, an idle peer is pinged once per timeout and the
+/// connection fails when it does not answer.
+pub struct WebSocket<S: Read + Write> {
+  /// Stream with the peer.
+  stream: S,
+  /// Side of the connection.
+  role: Role,
+  /// Bytes received and not parsed yet.
+  buffer: Vec<u8>,
+  /// Opcode and data of the fragmented message being received.
+  fragments: Option<(Opcode, Vec<u8>)>,
+  /// Maximum number of bytes of a message received.
+  max_message_size: usize,
+  /// Maximum number of bytes of the payload of a frame sent.
+  max_frame_size: usize,
+  /// Whether a ping was sent and nothing was received since.
+  awaiting_pong: bool,
+  /// Whether a close frame was sent.
+  close_sent: bool,
+  /// Whether a close frame was received or the connection failed.
+  closed: bool,
+  /// Flag set when the connection must close, checked while waiting for messages.
+  shutdown: Option<Arc<AtomicBool>>,
+}
+
+impl<S: Read + Write> WebSocket<S> {
+  /// Creates a new [`WebSocket`] object over a stream whose handshake is done.
+  ///
+  /// # Arguments
+  ///
+  /// * `stream`: Stream with the peer.
+  /// * `role`: Side of the connection.
+  pub fn new(
+    stream: S,
+    role: Role,
+  ) -> Self {
+    Self {
+      stream,
+      role,
+      buffer: Vec::new(),
+      fragments: None,
+      max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+      max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+      awaiting_pong: false,
+      close_sent: false,
+      closed: false,
+      shutdown: None,
+    }
+  }
+
+  /// Opens a connection as a client: sends the opening handshake over the stream and
+  /// checks the answer of the server.
+  ///
+  /// # Arguments
+  ///
+  /// * `stream`: Stream connected to the server.
+  /// * `host`: Value of the `Host` header, e.g. `localhost:3000`.
+  /// * `target`: Request target, e.g. `/ws/echo`.
+  pub fn connect(
+    mut stream: S,
+    host: &str,
+    target: &str,
+  ) -> Result<Self, WebSocketError> {
+    let key: String = BASE64.encode(random_bytes::<16>());
+    let mut request: HttpRequest = HttpRequest::new(Method::GET, target)
+      .map_err(|_| WebSocketError::Handshake(format!("invalid target: {}", target)))?;
+    request.headers.insert("Host", host);
+    request.headers.insert("Upgrade", "websocket");
+    request.headers.insert("Connection", "Upgrade");
+    request.headers.insert("Sec-WebSocket-Key", &key);
+    request.headers.insert("Sec-WebSocket-Version", VERSION);
+    request.write_to(&mut stream)?;
+
+    // Read the head of the response; any bytes after it are already frames
+    let mut buffer: Vec<u8> = Vec::new();
+    let head_length: usize = loop {
+      if let Some(end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+        break end + 4;
+      }
+      if buffer.len() > MAX_HANDSHAKE_SIZE {
+        return Err(WebSocketError::Handshake("response too large".to_string()));
+      }
+      let mut chunk: [u8; 1024] = [0; 1024];
+      match stream.read(&mut chunk)? {
+        0 => return Err(WebSocketError::Handshake("connection closed".to_string())),
+        read => buffer.extend_from_slice(&chunk[..read]),
+      }
+    };
+
+    let head: String = String::from_utf8_lossy(&buffer[..head_length]).to_string();
+    let mut lines = head.lines();
+    let status_line: &str = lines.next().unwrap_or_default();
+    if status_line.split(' ').nth(1) != Some("101") {
+      return Err(WebSocketError::Handshake(format!(
+        "unexpected response: {}",
+        status_line
+      )));
+    }
+    let accepted: bool = lines.filter_map(|line| line.split_once(':')).any(|(k, v)| {
+      k.trim().eq_ignore_ascii_case("Sec-WebSocket-Accept")
+        && v.trim() == accept_key(&key)
+    });
+    if !accepted {
+      return Err(WebSocketError::Handshake("invalid accept key".to_string()));
+    }
+
+    let mut socket: WebSocket<S> = WebSocket::new(stream, Role::Client);
+    socket.buffer = buffer.split_off(head_length);
+    Ok(socket)
+  } // end fn connect()
+
+  /// Sets the maximum number of bytes of a message received.
+  ///
+  /// # Arguments
+  ///
+  /// * `size`: Maximum number of bytes, once the fragments are joined.
+  pub fn with_max_message_size(
+    mut self,
+    size: usize,
+  ) -> Self {
+    self.max_message_size = size;
+    self
+  }
+
+  /// Sets the maximum number of bytes of the payload of a frame sent. Longer messages
+  /// are sent in fragments.
+  ///
+  /// # Arguments
+  ///
+  /// * `size`: Maximum number of bytes, at least one.
+  pub fn with_max_frame_size(
+    mut self,
+    size: usize,
+  ) -> Self {
+    self.max_frame_size = size.max(1);
+    self
+  }
+
+  /// Closes the connection with [`CLOSE_GOING_AWAY`] once the given flag is set, the
+  /// next time waiting for a message times out.
+  ///
+  /// # Arguments
+  ///
+  /// * `shutdown`: Flag set when the connection must close.
+  pub fn with_shutdown(
+    mut self,
+    shutdown: Arc<AtomicBool>,
+  ) -> Self {
+    self.shutdown = Some(shutdown);
+    self
+  }
+
+  /// Waits for the next text, binary or close message. Pings are answered and pongs
+  /// are skipped. A protocol error is reported to the peer with a close frame.
+  pub fn read_message(&mut self) -> Result<Message, WebSocketError> {
+    loop {
+      if self.closed {
+        return Err(WebSocketError::Closed);
+      }
+
+      let masked: bool = self.role == Role::Server;
+      match Frame::parse(&self.buffer, masked, self.max_message_size) {
+        Ok(Some((frame, length))) => {
+          self.buffer.drain(..length);
+          self.awaiting_pong = false;
+          match self.receive(frame) {
+            Ok(Some(message)) => return Ok(message),
+            Ok(None) => {}
+            Err(e) => return Err(self.fail(e)),
+          }
+        }
+        Ok(None) => self.fill()?,
+        Err(e) => return Err(self.fail(e)),
+      }
+    }
+  }
+
+  /// Sends a message, in fragments if it is longer than the maximum frame size.
+  ///
+  /// # Arguments
+  ///
+  /// * `message`: Message to send.
+  pub fn send(
+    &mut self,
+    message: Message,
+  ) -> Result<(), WebSocketError> {
+    if self.close_sent {
+      return Err(WebSocketError::Closed);
+    }
+
+    let (opcode, payload): (Opcode, Vec<u8>) = match message {
+      Message::Text(text) => (Opcode::Text, text.into_bytes()),
+      Message::Binary(data) => (Opcode::Binary, data),
+      Message::Ping(data) => (Opcode::Ping, data),
+      Message::Pong(data) => (Opcode::Pong, data),
+      Message::Close(close) => {
+        self.close_sent = true;
+        let payload: Vec<u8> = match close {
+          Some(close) => {
+            [&close.code.to_be_bytes()[..], close.reason.as_bytes()].concat()
+          }
+          None => Vec::new(),
+        };
+        (Opcode::Close, payload)
+      }
+    };
+    if opcode.is_control() {
+      if payload.len() > MAX_CONTROL_PAYLOAD {
+        return Err(WebSocketError::MessageTooBig);
+      }
+      return self.write_frame(&Frame::new(opcode, payload));
+    }
+
+    let mut chunks = payload.chunks(self.max_frame_size).peekable();
+    let mut frame_opcode: Opcode = opcode;
+    if chunks.peek().is_none() {
+      return self.write_frame(&Frame::new(opcode, Vec::new()));
+    }
+    while let Some(chunk) = chunks.next() {
+      let frame: Frame = Frame {
+        fin: chunks.peek().is_none(),
+        opcode: frame_opcode,
+        payload: chunk.to_vec(),
+      };
+      self.write_frame(&frame)?;
+      frame_opcode = Opcode::Continuation;
+    }
+    Ok(())
+  } // end fn send()
+
+  /// Starts the closing handshake and waits for the close frame of the peer, skipping
+  /// any message received meanwhile.
+  ///
+  /// # Arguments
+  ///
+  /// * `code`: Close code, e.g. [`CLOSE_NORMAL`].
+  /// * `reason`: Reason, for debugging.
+  pub fn close(
+    &mut self,
+    code: u16,
+    reason: &str,
+  ) -> Result<(), WebSocketError> {
+    if !self.close_sent {
+      self.send(Message::Close(Some(CloseFrame {
+        code,
+        reason: reason.to_string(),
+      })))?;
+    }
+    while !self.closed {
+      if let Message::Close(_) = self.read_message()? {
+        break;
+      }
+    }
+    Ok(())
+  }
+
+  /// Handles a frame received, and returns the message it completes, if any.
+  ///
+  /// # Arguments
+  ///
+  /// * `frame`: Frame received.
+  fn receive(
+    &mut self,
+    frame: Frame,
+  ) -> Result<Option<Message>, WebSocketError> {
+    match frame.opcode {
+      Opcode::Ping => {
+        if !self.close_sent {
+          self.write_frame(&Frame::new(Opcode::Pong, frame.payload))?;
+        }
+        Ok(None)
+      }
+      Opcode::Pong => Ok(None),
+      Opcode::Close => {
+        let close: Option<CloseFrame> = CloseFrame::parse(&frame.payload)?;
+        // Echo the close frame to complete the closing handshake
+        if !self.close_sent {
+          self.close_sent = true;
+          let code: Vec<u8> = frame.payload.get(..2).unwrap_or_default().to_vec();
+          self.write_frame(&Frame::new(Opcode::Close, code))?;
+        }
+        self.closed = true;
+        Ok(Some(Message::Close(close)))
+      }
+      Opcode::Text | Opcode::Binary if self.fragments.is_some() => Err(
+        WebSocketError::Protocol("new message before the previous one ended"),
+      ),
+      Opcode::Text | Opcode::Binary if frame.fin => {
+        Self::message(frame.opcode, frame.payload).map(Some)
+      }
+      Opcode::Text | Opcode::Binary => {
+        self.fragments = Some((frame.opcode, frame.payload));
+        Ok(None)
+      }
+      Opcode::Continuation => {
+        let Some((_, data)) = &mut self.fragments else {
+          return Err(WebSocketError::Protocol("continuation without a message"));
+        };
+        if data.len() + frame.payload.len() > self.max_message_size {
+          return Err(WebSocketError::MessageTooBig);
+        }
+        data.extend_from_slice(&frame.payload);
+
+        match (frame.fin, self.fragments.take()) {
+          (true, Some((opcode, data))) => Self::message(opcode, data).map(Some),
+          (_, fragments) => {
+            self.fragments = fragments;
+            Ok(None)
+          }
+        }
+      }
+    }
+  } // end fn receive()
+
+  /// Creates a complete data message.
+  ///
+  /// # Arguments
+  ///
+  /// * `opcode`: Opcode of the first fragment.
+  /// * `data`: Data of every fragment.
+  fn message(
+    opcode: Opcode,
+    data: Vec<u8>,
+  ) -> Result<Message, WebSocketError> {
+    match opcode {
+      Opcode::Text => String::from_utf8(data)
+        .map(Message::Text)
+        .map_err(|_| WebSocketError::InvalidUtf8),
+      _ => Ok(Message::Binary(data)),
+    }
+  }
+
+  /// Reads more bytes from the stream. When waiting times out, the peer is pinged, or
+  /// the connection closes if it is shutting down or the last ping got no answer.
+  fn fill(&mut self) -> Result<(), WebSocketError> {
+    let mut chunk: [u8; 4096] = [0; 4096];
+    match self.stream.read(&mut chunk) {
+      Ok(0) => {
+        self.closed = true;
+        Err(WebSocketError::Io(io::ErrorKind::UnexpectedEof.into()))
+      }
+      Ok(read) => {
+        self.buffer.extend_from_slice(&chunk[..read]);
+        Ok(())
+      }
+      Err(e)
+        if matches!(
+          e.kind(),
+          io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+        ) =>
+      {
+        if matches!(&self.shutdown, Some(flag) if flag.load(Ordering::SeqCst)) {
+          self.closed = true;
+          self.send(Message::Close(Some(CloseFrame {
+            code: CLOSE_GOING_AWAY,
+            reason: String::new(),
+          })))?;
+          return Err(WebSocketError::Closed);
+        }
+        if self.awaiting_pong {
+          self.closed = true;
+          return Err(WebSocketError::Timeout);
+        }
+        self.awaiting_pong = true;
+        self.write_frame(&Frame::new(Opcode::Ping, Vec::new()))
+      }
+      Err(e) if e.kind() == io::ErrorKind::Interrupted => Ok(()),
+      Err(e) => {
+        self.closed = true;
+        Err(WebSocketError::Io(e))
+      }
+    }
+  } // end fn fill()
+
+  /// Tells the peer about an error breaking the protocol, and marks the connection as
+  /// closed.
+  ///
+  /// # Arguments
+  ///
+  /// * `error`: Error found.
+  fn fail(
+    &mut self,
+    error: WebSocketError,
+  ) -> WebSocketError {
+    if let Some(code) = error.close_code() {
+      if !self.close_sent {
+        let _ = self.send(Message::Close(Some(CloseFrame {
+          code,
+          reason: String::new(),
+        })));
+      }
+    }
+    self.closed = true;
+    error
+  }
+
+  /// Writes a frame, masked if this is the client side.
+  ///
+  /// # Arguments
+  ///
+  /// * `frame`: Frame to write.
+  fn write_frame(
+    &mut self,
+    frame: &Frame,
+  ) -> Result<(), WebSocketError> {
+    let mask: Option<[u8; 4]> = match self.role {
+      Role::Client => Some(random_bytes::<4>()),
+      Role::Server => None,
+    };
+    self.stream.write_all(&frame.encode(mask))?;
+    self.stream.flush()?;
+    Ok(())
+  }
+}
+
+/// Masks or unmasks a payload with a masking key.
+///
+/// # Arguments
+///
+/// * `payload`: Payload to transform in place.
+/// * `mask`: Masking key.
+fn apply_mask(
+  payload: &mut [u8],
+  mask: [u8; 4],
+) {
+  for (i, byte) in payload.iter_mut().enumerate() {
+    *byte ^= mask[i % 4];
+  }
+}
+
+/// Generates unpredictable bytes for masking keys and handshake nonces, from the
+/// randomly seeded hasher of the standard library.
+fn random_bytes<const N: usize>() -> [u8; N] {
+  let mut bytes: [u8; N] = [0; N];
+  for chunk in bytes.chunks_mut(8) {
+    let mut hasher = RandomState::new().build_hasher();
+    let nanos: u128 = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map_or(0, |elapsed| elapsed.as_nanos());
+    hasher.write_u128(nanos);
+    chunk.copy_from_slice(&hasher.finish().to_be_bytes()[..chunk.len()]);
+  }
+  bytes
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Cursor;
+
+  use super::*;
+
+  /// Represents a stream reading prepared bytes and collecting the bytes written.
+  struct Pipe {
+    input: Cursor<Vec<u8>>,
+    output: Vec<u8>,
+  }
+
+  impl Read for Pipe {
+    fn read(
+      &mut self,
+      buf: &mut [u8],
+    ) -> io::Result<usize> {
+      self.input.read(buf)
+    }
+  }
+
+  impl Write for Pipe {
+    fn write(
+      &mut self,
+      buf: &[u8],
+    ) -> io::Result<usize> {
+      self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+      Ok(())
+    }
+  }
+
+  /// Creates the server side of a connection receiving the given frames from a client.
+  fn server(frames: &[Frame]) -> WebSocket<Pipe> {
+    let input: Vec<u8> = frames
+      .iter()
+      .flat_map(|frame| frame.encode(Some([0x37, 0xfa, 0x21, 0x3d])))
+      .collect();
+    let pipe = Pipe {
+      input: Cursor::new(input),
+      output: Vec::new(),
+    };
+    WebSocket::new(pipe, Role::Server)
+  }
+
+  /// Parses every frame the server sent.
+  fn sent(socket: &WebSocket<Pipe>) -> Vec<Frame> {
+    let mut bytes: &[u8] = &socket.stream.output;
+    let mut frames = Vec::new();
+    while let Some((frame, length)) = Frame::parse(bytes, false, usize::MAX).unwrap() {
+      frames.push(frame);
+      bytes = &bytes[length..];
+    }
+    frames
+  }
+
+  #[test]
+  fn test_handshake() {
+    // Example of RFC 6455, section 1.3
+    assert_eq!(
+      accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+      "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+    );
+
+    let text = "GET /chat HTTP/1.1\r\nHost: server.example.com\r\nUpgrade: websocket\r\n\
+                Connection: keep-alive, Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+                Sec-WebSocket-Version: 13\r\n\r\n";
+    let request = HttpRequest::parse(text.as_bytes()).unwrap().0;
+    let response = handshake(&request).unwrap();
+    assert_eq!(response.status_code(), &StatusCode::SWITCHING_PROTOCOLS);
+    assert_eq!(
+      response.header("Sec-WebSocket-Accept"),
+      Some("s3pPLMBiTxaQ9kYGzzhZRbK+xOo=")
+    );
+
+    let refused = |text: &str| handshake(&HttpRequest::parse(text.as_bytes()).unwrap().0);
+    assert_eq!(
+      refused("GET /chat HTTP/1.1\r\n\r\n").unwrap_err(),
+      HandshakeError::NotUpgrade
+    );
+    let old = text.replace("Version: 13", "Version: 8");
+    let error = refused(&old).unwrap_err();
+    assert_eq!(error, HandshakeError::UnsupportedVersion);
+    assert_eq!(
+      error.response().status_code(),
+      &StatusCode::UPGRADE_REQUIRED
+    );
+    let short = text.replace("dGhlIHNhbXBsZSBub25jZQ==", "c2hvcnQ=");
+    assert_eq!(refused(&short).unwrap_err(), HandshakeError::InvalidKey);
+  }
+
+  #[test]
+  fn test_frame_encoding() {
+    // Examples of RFC 6455, section 5.7
+    let hello = Frame::new(Opcode::Text, "Hello");
+    assert_eq!(hello.encode(None), b"\x81\x05Hello");
+    let masked = hello.encode(Some([0x37, 0xfa, 0x21, 0x3d]));
+    assert_eq!(masked, b"\x81\x85\x37\xfa\x21\x3d\x7f\x9f\x4d\x51\x58");
+    assert_eq!(
+      Frame::parse(&masked, true, 1024).unwrap(),
+      Some((hello.clone(), masked.len()))
+    );
+    assert_eq!(Frame::parse(&masked[..6], true, 1024).unwrap(), None);
+
+    // Payloads with 16-bit and 64-bit lengths
+    for length in [256, 65536] {
+      let frame = Frame::new(Opcode::Binary, vec![7; length]);
+      let bytes = frame.encode(None);
+      assert_eq!(bytes.len(), length + if length < 65536 { 4 } else { 10 });
+      assert_eq!(
+        Frame::parse(&bytes, false, length).unwrap(),
+        Some((frame, bytes.len()))
+      );
+      assert!(matches!(
+        Frame::parse(&bytes, false, length - 1),
+        Err(WebSocketError::MessageTooBig)
+      ));
+    }
+
+    // Unmasked client frames, reserved bits and fragmented control frames
+    assert!(Frame::parse(b"\x81\x05Hello", true, 1024).is_err());
+    assert!(Frame::parse(b"\xc1\x00", false, 1024).is_err());
+    assert!(Frame::parse(b"\x09\x00", false, 1024).is_err());
+  }
+
+  #[test]
+  fn test_fragmented_message_with_ping() {
+    let mut socket = server(&[
+      Frame {
+        fin: false,
+        opcode: Opcode::Text,
+        payload: b"Hel".to_vec(),
+      },
+      Frame::new(Opcode::Ping, "still there?"),
+      Frame::new(Opcode::Continuation, "lo"),
+      Frame::new(Opcode::Binary, vec![1, 2]),
+    ]);
+
+    assert_eq!(
+      socket.read_message().unwrap(),
+      Message::Text("Hello".into())
+    );
+    assert_eq!(socket.read_message().unwrap(), Message::Binary(vec![1, 2]));
+    assert_eq!(sent(&socket), [Frame::new(Opcode::Pong, "still there?")]);
+  }
+
+  #[test]
+  fn test_close_handshake() {
+    let close = [&1000u16.to_be_bytes()[..], b"bye"].concat();
+    let mut socket = server(&[Frame::new(Opcode::Close, close)]);
+
+    let expected = CloseFrame {
+      code: CLOSE_NORMAL,
+      reason: "bye".to_string(),
+    };
+    assert_eq!(
+      socket.read_message().unwrap(),
+      Message::Close(Some(expected))
+    );
+    assert!(matches!(socket.read_message(), Err(WebSocketError::Closed)));
+    assert!(socket.send(Message::Text("late".into())).is_err());
+    let echoed = Frame::new(Opcode::Close, 1000u16.to_be_bytes().to_vec());
+    assert_eq!(sent(&socket), [echoed]);
+  }
+
+  #[test]
+  fn test_protocol_errors() {
+    // A continuation frame without a message, answered with a close frame
+    let mut socket = server(&[Frame::new(Opcode::Continuation, "x")]);
+    assert!(matches!(
+      socket.read_message(),
+      Err(WebSocketError::Protocol(_))
+    ));
+    let close = Frame::new(Opcode::Close, 1002u16.to_be_bytes().to_vec());
+    assert_eq!(sent(&socket), [close]);
+
+    let mut socket = server(&[Frame::new(Opcode::Text, vec![0xff, 0xfe])]);
+    assert!(matches!(
+      socket.read_message(),
+      Err(WebSocketError::InvalidUtf8)
+    ));
+
+    let mut socket = server(&[
+      Frame {
+        fin: false,
+        opcode: Opcode::Binary,
+        payload: vec![0; 8],
+      },
+      Frame::new(Opcode::Continuation, vec![0; 8]),
+    ])
+    .with_max_message_size(10);
+    assert!(matches!(
+      socket.read_message(),
+      Err(WebSocketError::MessageTooBig)
+    ));
+  }
+
+  #[test]
+  fn test_send_fragments() {
+    let mut socket = server(&[]).with_max_frame_size(4);
+    socket.send(Message::Text("fragmented".into())).unwrap();
+
+    let frames = sent(&socket);
+    assert_eq!(frames.len(), 3);
+    assert_eq!(frames[0].opcode, Opcode::Text);
+    assert_eq!(frames[2].opcode, Opcode::Continuation);
+    assert!(!frames[1].fin && frames[2].fin);
+    let joined: Vec<u8> = frames.into_iter().flat_map(|f| f.payload).collect();
+    assert_eq!(joined, b"fragmented");
+  }
+
+  #[test]
+  fn test_client_connect() {
+    use std::net::{TcpListener, TcpStream};
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let echo = std::thread::spawn(move || {
+      let (mut stream, _) = listener.accept().unwrap();
+      let mut buffer = Vec::new();
+      let request = loop {
+        let mut chunk = [0; 1024];
+        let read = stream.read(&mut chunk).unwrap();
+        buffer.extend_from_slice(&chunk[..read]);
+        if let Ok((request, _)) = HttpRequest::parse(&buffer) {
+          break request;
+        }
+      };
+      handshake(&request)
+        .unwrap()
+        .send_response(&mut stream)
+        .unwrap();
+
+      let mut socket = WebSocket::new(stream, Role::Server);
+      while let Ok(Message::Text(text)) = socket.read_message() {
+        socket.send(Message::Text(text)).unwrap();
+      }
+    });
+
+    let stream = TcpStream::connect(address).unwrap();
+    let mut socket = WebSocket::connect(stream, &address.to_string(), "/echo").unwrap();
+    socket.send(Message::Text("echo me".into())).unwrap();
+    assert_eq!(
+      socket.read_message().unwrap(),
+      Message::Text("echo me".into())
+    );
+    socket.close(CLOSE_NORMAL, "done").unwrap();
+    echo.join().unwrap();
+  }
+}
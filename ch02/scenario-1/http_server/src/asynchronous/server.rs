@@ -0,0 +1,585 @@
+use std::io::{self, BufWriter, Write};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use http::body::Body;
+use http::http_request::{HttpRequest, Method, Version};
+use http::http_response::HttpResponse;
+use http::status_code::StatusCode;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::signal::unix::{self, SignalKind};
+use tokio::sync::{mpsc, watch};
+use tokio::task::{self, JoinSet};
+use tokio::time;
+
+use crate::config::Mode;
+use crate::middleware::{self, Chain, Compression, Context};
+use crate::reader::{ReadError, RequestLimits, RequestReader};
+use crate::router::Router;
+use crate::server::{listen_error, wants_keep_alive, KeepAlive};
+
+/// Size of the chunks read from the stream on each iteration.
+const READ_CHUNK_SIZE: usize = 1024;
+
+/// Size of the chunks of a file or stream body passed on to the client.
+const SEND_CHUNK_SIZE: usize = 8192;
+
+/// Number of chunks of a file or stream body read ahead of the client.
+const SEND_QUEUE_DEPTH: usize = 4;
+
+/// Represents a server that serves every connection on a task instead of a thread.
+pub struct Server<'a> {
+  /// Socket address to listen connections.
+  socket_address: &'a str,
+  /// Size limits for the incoming requests.
+  limits: RequestLimits,
+  /// Settings of persistent connections.
+  keep_alive: KeepAlive,
+  /// Routes served.
+  router: Arc<Router>,
+  /// Middlewares run around the routing of every request.
+  middlewares: Arc<Chain>,
+}
+
+impl<'a> Server<'a> {
+  /// Creates a new [`Server`] object.
+  ///
+  /// # Argument
+  ///
+  /// * `socket_address`: Socket address to listen new connections.
+  pub fn new(socket_address: &'a str) -> Self {
+    Self {
+      socket_address,
+      limits: RequestLimits::default(),
+      keep_alive: KeepAlive::default(),
+      router: Arc::new(Router::application(Mode::Async)),
+      middlewares: Arc::new(middleware::default_chain(Compression::default())),
+    }
+  }
+
+  /// Sets the size limits for the incoming requests.
+  ///
+  /// # Argument
+  ///
+  /// * `limits`: Maximum sizes for the headers and the body of a request.
+  pub fn with_limits(
+    mut self,
+    limits: RequestLimits,
+  ) -> Self {
+    self.limits = limits;
+    self
+  }
+
+  /// Sets the settings of persistent connections.
+  ///
+  /// # Argument
+  ///
+  /// * `keep_alive`: Idle timeout and maximum number of requests per connection.
+  pub fn with_keep_alive(
+    mut self,
+    keep_alive: KeepAlive,
+  ) -> Self {
+    self.keep_alive = keep_alive;
+    self
+  }
+
+  /// Sets the middlewares run around the routing of every request.
+  ///
+  /// # Argument
+  ///
+  /// * `middlewares`: Middlewares, from the outermost to the innermost.
+  pub fn with_middlewares(
+    mut self,
+    middlewares: Chain,
+  ) -> Self {
+    self.middlewares = Arc::new(middlewares);
+    self
+  }
+
+  /// Runs the server until it receives SIGINT or SIGTERM, and then waits for the
+  /// requests in progress to finish.
+  ///
+  /// Returns an error if the server cannot start, e.g. when the address is in use.
+  pub async fn run(&self) -> io::Result<()> {
+    // Start the server on the socket address
+    let connection_listener: TcpListener =
+      TcpListener::bind(self.socket_address)
+        .await
+        .map_err(|e| listen_error(self.socket_address, e))?;
+
+    info!("Server running on {}", self.socket_address);
+
+    let (shutdown_sender, shutdown) = watch::channel(false);
+    let mut connections: JoinSet<()> = JoinSet::new();
+    let signal = Self::shutdown_signal();
+    tokio::pin!(signal);
+
+    // Listen and waits for new connections
+    loop {
+      tokio::select! {
+        accepted = connection_listener.accept() => match accepted {
+          Ok((stream, _)) => {
+            debug!("Connection established with client.");
+            let handler: ConnectionHandler = ConnectionHandler {
+              limits: self.limits,
+              keep_alive: self.keep_alive,
+              shutdown: shutdown.clone(),
+              router: Arc::clone(&self.router),
+              middlewares: Arc::clone(&self.middlewares),
+            };
+            connections.spawn(handler.handle(stream));
+          }
+          Err(e) => error!("Failed to accept connection: {}", e),
+        },
+        _ = &mut signal => break,
+      }
+
+      // Forget the connections already closed
+      while connections.try_join_next().is_some() {}
+    }
+
+    info!("Shutting down, waiting for the requests in progress...");
+    let _ = shutdown_sender.send(true);
+    while connections.join_next().await.is_some() {}
+    info!("Server stopped");
+    Ok(())
+  }
+
+  /// Waits until the process receives SIGINT or SIGTERM.
+  async fn shutdown_signal() {
+    let mut terminate: unix::Signal = unix::signal(SignalKind::terminate()).unwrap();
+
+    tokio::select! {
+      _ = tokio::signal::ctrl_c() => {}
+      _ = terminate.recv() => {}
+    }
+  }
+}
+
+/// Represents the settings shared by the tasks to serve connections.
+#[derive(Clone)]
+struct ConnectionHandler {
+  /// Size limits for the incoming requests.
+  limits: RequestLimits,
+  /// Settings of persistent connections.
+  keep_alive: KeepAlive,
+  /// Flag set when the server is shutting down.
+  shutdown: watch::Receiver<bool>,
+  /// Routes served.
+  router: Arc<Router>,
+  /// Middlewares run around the routing of every request.
+  middlewares: Arc<Chain>,
+}
+
+impl ConnectionHandler {
+  /// Serves the requests received on a connection, in order, until the client or the
+  /// server closes it.
+  ///
+  /// # Argument
+  ///
+  /// * `stream`: Connection with the client.
+  async fn handle(
+    mut self,
+    mut stream: TcpStream,
+  ) {
+    let mut reader: RequestReader = RequestReader::new(self.limits);
+    let mut served: usize = 0;
+    let peer_address: Option<SocketAddr> = stream.peer_addr().ok();
+
+    loop {
+      // Create the request from the byte stream received
+      let req: HttpRequest = match self.read_request(&mut reader, &mut stream).await {
+        Ok(req) => req,
+        Err(ReadError::ConnectionClosed | ReadError::IdleTimeout) => break,
+        // Answer an invalid request with the respective error status and close
+        Err(e) => {
+          warn!("Failed to read request: {}", e);
+          if let Some(status_code) = e.status_code() {
+            let mut response: HttpResponse =
+              HttpResponse::new(status_code, None, Body::Empty);
+            response.headers_mut().insert("Connection", "close");
+            let _ = Self::send(response, false, false, &mut stream).await;
+          }
+          break;
+        }
+      };
+      served += 1;
+
+      // Route the request to the appropiate handler through the middlewares
+      let (req, mut response): (HttpRequest, HttpResponse) =
+        self.route(req, peer_address).await;
+
+      // Close the connection after this request when the server is shutting down or a
+      // middleware asks for it
+      let keep_alive: bool = wants_keep_alive(&req)
+        && served < self.keep_alive.max_requests
+        && !*self.shutdown.borrow()
+        && !response.headers().connection().iter().any(|o| o == "close");
+
+      if !keep_alive {
+        response.headers_mut().insert("Connection", "close");
+      } else if req.version == Version::V1_0 {
+        response.headers_mut().insert("Connection", "keep-alive");
+      }
+
+      // Answer HEAD requests without the body, and HTTP/1.0 ones without the chunked
+      // transfer coding, which they do not understand
+      let head_only: bool = req.method == Method::HEAD;
+      let buffered: bool = req.version == Version::V1_0;
+      let sent: io::Result<()> =
+        Self::send(response, head_only, buffered, &mut stream).await;
+
+      if sent.is_err() || !keep_alive {
+        break;
+      }
+    }
+  } // end fn handle()
+
+  /// Routes a request through the middlewares and returns it along with its response.
+  ///
+  /// The middlewares and the handlers are blocking code, e.g. compression reads file
+  /// bodies and the orders are kept in a file, so both run together on a single thread
+  /// of the runtime pool for blocking calls. A panic outside the chain is answered with
+  /// "500 Internal Server Error".
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request received.
+  /// * `peer_address`: Socket address of the client.
+  async fn route(
+    &self,
+    mut request: HttpRequest,
+    peer_address: Option<SocketAddr>,
+  ) -> (HttpRequest, HttpResponse) {
+    let fallback: HttpRequest = request.clone();
+    let router: Arc<Router> = Arc::clone(&self.router);
+    let middlewares: Arc<Chain> = Arc::clone(&self.middlewares);
+
+    let routed = task::spawn_blocking(move || {
+      let mut context: Context = Context::new(peer_address);
+      let response: HttpResponse =
+        middlewares.handle(&mut request, &mut context, &|req| router.route(req));
+      (request, response)
+    })
+    .await;
+
+    routed.unwrap_or_else(|e| {
+      error!("Failed to route a request: {}", e);
+      let response: HttpResponse =
+        HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR, None, Body::Empty);
+      (fallback, response)
+    })
+  } // end fn route()
+
+  /// Reads the next complete request from the connection.
+  ///
+  /// An idle connection is closed as soon as the server starts shutting down, while a
+  /// request already started is read to the end.
+  ///
+  /// # Arguments
+  ///
+  /// * `reader`: Reader keeping the bytes received on the connection.
+  /// * `stream`: Connection with the client.
+  async fn read_request(
+    &mut self,
+    reader: &mut RequestReader,
+    stream: &mut TcpStream,
+  ) -> Result<HttpRequest, ReadError> {
+    let mut chunk: [u8; READ_CHUNK_SIZE] = [0; READ_CHUNK_SIZE];
+
+    loop {
+      if let Some(request) = reader.next_request()? {
+        return Ok(request);
+      }
+
+      let idle: bool = reader.is_idle();
+      let read = tokio::select! {
+        read = time::timeout(self.keep_alive.idle_timeout, stream.read(&mut chunk)) => read,
+        _ = self.shutdown.changed(), if idle => return Err(ReadError::ConnectionClosed),
+      };
+
+      match read {
+        Err(_) => return Err(reader.timeout_error()),
+        Ok(Ok(0)) => return Err(reader.closed_error()),
+        Ok(Ok(bytes_count)) => reader.push(&chunk[..bytes_count]),
+        Ok(Err(e)) => return Err(e.into()),
+      }
+    }
+  } // end fn read_request()
+
+  /// Sends the response to the client. File and stream bodies are read on a blocking
+  /// thread and passed on in chunks, so only a few of them are held in memory at once.
+  ///
+  /// # Arguments
+  ///
+  /// * `response`: HTTP response to send.
+  /// * `head_only`: Whether to send only the status line and the headers.
+  /// * `buffered`: Whether to read a body of unknown length into memory instead of
+  ///   sending it with the chunked transfer coding.
+  /// * `stream`: Connection with the client.
+  async fn send(
+    mut response: HttpResponse,
+    head_only: bool,
+    buffered: bool,
+    stream: &mut TcpStream,
+  ) -> io::Result<()> {
+    if matches!(
+      response.body(),
+      Body::Empty | Body::Bytes(_) | Body::Shared(_)
+    ) {
+      let mut bytes: Vec<u8> = Vec::new();
+      if head_only {
+        response.send_head(&mut bytes)?;
+      } else {
+        response.send_response(&mut bytes)?;
+      }
+      stream.write_all(&bytes).await?;
+      return stream.flush().await;
+    }
+
+    // File and stream bodies can only be read with blocking calls
+    let (sender, mut receiver) = mpsc::channel::<Vec<u8>>(SEND_QUEUE_DEPTH);
+    let producer: task::JoinHandle<io::Result<()>> = task::spawn_blocking(move || {
+      if buffered {
+        response.buffer_body()?;
+      }
+      let mut writer: BufWriter<ChunkSender> =
+        BufWriter::with_capacity(SEND_CHUNK_SIZE, ChunkSender { sender });
+      if head_only {
+        response.send_head(&mut writer)
+      } else {
+        response.send_response(&mut writer)
+      }
+    });
+
+    // Dropping the receiver on a failed write stops the producer
+    while let Some(chunk) = receiver.recv().await {
+      stream.write_all(&chunk).await?;
+    }
+    producer
+      .await
+      .map_err(|e| io::Error::new(io::ErrorKind::Other, e))??;
+    stream.flush().await
+  } // end fn send()
+}
+
+/// Represents a writer passing the bytes written to an async task, as chunks sent on a
+/// bounded channel. Writing blocks while the channel is full.
+struct ChunkSender {
+  /// Sending half of the channel.
+  sender: mpsc::Sender<Vec<u8>>,
+}
+
+impl Write for ChunkSender {
+  fn write(
+    &mut self,
+    buf: &[u8],
+  ) -> io::Result<usize> {
+    self
+      .sender
+      .blocking_send(buf.to_vec())
+      .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use super::*;
+
+  /// Creates the settings to serve connections with the routes of the application.
+  fn handler(
+    keep_alive: KeepAlive,
+    shutdown: watch::Receiver<bool>,
+  ) -> ConnectionHandler {
+    ConnectionHandler {
+      limits: RequestLimits::default(),
+      keep_alive,
+      shutdown,
+      router: Arc::new(Router::application(Mode::Async)),
+      middlewares: Arc::new(middleware::default_chain(Compression::default())),
+    }
+  }
+
+  /// Serves a single connection with the given settings and returns everything the
+  /// client receives after sending the given bytes.
+  async fn exchange(
+    keep_alive: KeepAlive,
+    shutdown: watch::Receiver<bool>,
+    request: &[u8],
+  ) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+
+    let server = tokio::spawn(async move {
+      let (stream, _) = listener.accept().await.unwrap();
+      handler(keep_alive, shutdown).handle(stream).await;
+    });
+
+    let mut client = TcpStream::connect(address).await.unwrap();
+    client.write_all(request).await.unwrap();
+
+    let mut received = String::new();
+    client.read_to_string(&mut received).await.unwrap();
+    server.await.unwrap();
+    received
+  }
+
+  #[tokio::test]
+  async fn test_pipelined_requests_in_order() {
+    let (_sender, shutdown) = watch::channel(false);
+    let received = exchange(
+      KeepAlive::default(),
+      shutdown,
+      b"GET /health HTTP/1.1\r\n\r\nHEAD / HTTP/1.1\r\n\r\nGET /api/shipping/orders?order_id=x HTTP/1.1\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+
+    // Bodies do not end with a line break, so look for the status lines anywhere
+    let statuses: Vec<&str> = received
+      .split("HTTP/1.1 ")
+      .skip(1)
+      .map(|r| &r[..3])
+      .collect();
+    assert_eq!(statuses, vec!["200", "200", "400"]);
+    assert_eq!(received.matches("Connection:close").count(), 1);
+
+    // The middlewares run in async mode too
+    assert_eq!(received.matches("X-Request-Id:").count(), 3);
+  }
+
+  #[test]
+  fn test_more_requests_than_blocking_threads() {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+      .max_blocking_threads(2)
+      .enable_all()
+      .build()
+      .unwrap();
+
+    let answered = runtime.block_on(async {
+      let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+      let address = listener.local_addr().unwrap();
+      let (_sender, shutdown) = watch::channel(false);
+      let handler = handler(KeepAlive::default(), shutdown);
+      tokio::spawn(async move {
+        loop {
+          let (stream, _) = listener.accept().await.unwrap();
+          tokio::spawn(handler.clone().handle(stream));
+        }
+      });
+
+      let mut clients: JoinSet<String> = JoinSet::new();
+      for _ in 0..16 {
+        clients.spawn(async move {
+          let mut client = TcpStream::connect(address).await.unwrap();
+          client
+            .write_all(b"GET /api/shipping/orders HTTP/1.1\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+          let mut received = String::new();
+          client.read_to_string(&mut received).await.unwrap();
+          received
+        });
+      }
+
+      // A request holding a blocking thread while waiting for another one would never
+      // be answered once every thread is taken
+      time::timeout(Duration::from_secs(10), async {
+        let mut answered: Vec<String> = Vec::new();
+        while let Some(received) = clients.join_next().await {
+          answered.push(received.unwrap());
+        }
+        answered
+      })
+      .await
+    });
+    // Do not wait for blocking threads that may never finish
+    runtime.shutdown_background();
+
+    let answered = answered.expect("every request should be answered");
+    assert_eq!(answered.len(), 16);
+    assert!(answered
+      .iter()
+      .all(|r| r.starts_with("HTTP/1.1 200 OK\r\n")));
+  }
+
+  #[tokio::test]
+  async fn test_stream_body_sent_in_chunks() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let contents: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+    let expected = contents.clone();
+
+    let server = tokio::spawn(async move {
+      let (mut stream, _) = listener.accept().await.unwrap();
+      let response = HttpResponse::new(
+        http::status_code::StatusCode::OK,
+        None,
+        Body::from_reader(io::Cursor::new(contents), None),
+      );
+      ConnectionHandler::send(response, false, false, &mut stream)
+        .await
+        .unwrap();
+    });
+
+    let mut client = TcpStream::connect(address).await.unwrap();
+    let mut received = Vec::new();
+    client.read_to_end(&mut received).await.unwrap();
+    server.await.unwrap();
+
+    let head_end = received.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+    let head = String::from_utf8_lossy(&received[..head_end]);
+    assert!(head.contains("Transfer-Encoding: chunked"));
+    let body = http::chunked::decode(&received[head_end..]).unwrap();
+    assert_eq!(body.data, expected);
+  }
+
+  #[tokio::test]
+  async fn test_order_events_not_served() {
+    let (_sender, shutdown) = watch::channel(false);
+    let received = exchange(
+      KeepAlive::default(),
+      shutdown,
+      b"GET /api/shipping/orders/stream HTTP/1.1\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+
+    assert!(received.starts_with("HTTP/1.1 404 Not Found\r\n"));
+  }
+
+  #[tokio::test]
+  async fn test_shutdown_closes_idle_connection() {
+    let (sender, shutdown) = watch::channel(false);
+    let keep_alive = KeepAlive {
+      idle_timeout: Duration::from_secs(30),
+      max_requests: 100,
+    };
+    tokio::spawn(async move {
+      time::sleep(Duration::from_millis(100)).await;
+      sender.send(true).unwrap();
+    });
+
+    let received = exchange(keep_alive, shutdown, b"HEAD / HTTP/1.1\r\n\r\n").await;
+
+    assert_eq!(received.matches("HTTP/1.1 200 OK").count(), 1);
+  }
+
+  #[tokio::test]
+  async fn test_partial_request_times_out() {
+    let (_sender, shutdown) = watch::channel(false);
+    let keep_alive = KeepAlive {
+      idle_timeout: Duration::from_millis(100),
+      max_requests: 100,
+    };
+    let received = exchange(keep_alive, shutdown, b"GET / HTTP/1.1\r\nHost:").await;
+
+    assert!(received.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+  }
+}
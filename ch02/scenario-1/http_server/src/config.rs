@@ -0,0 +1,977 @@
+use std::net::ToSocketAddrs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::RwLock;
+use std::time::Duration;
+use std::{env, fmt, fs, io};
+
+use serde::Deserialize;
+
+use crate::logging::LogLevel;
+use crate::middleware::{self, Compression};
+use crate::reader::RequestLimits;
+use crate::server::{self, KeepAlive, Workers};
+use crate::static_files;
+use crate::virtual_host::{UnknownHost, VirtualHosts};
+
+/// Usage text printed by `--help` and on invalid arguments.
+pub const USAGE: &str = "Usage: http_server [options]
+
+Options:
+  -c, --config <file>            TOML configuration file
+  -b, --bind <address>           Socket address to listen on (repeatable)
+      --public <dir>             Directory of the static files
+      --data <dir>               Directory of the JSON data files
+      --mode <threads|async>     Serve connections on threads or on async tasks
+  -w, --workers <n>              Number of worker threads
+      --keep-alive-timeout <s>   Seconds an idle connection is kept open
+      --log-level <level>        off, error, warn, info or debug
+      --check-config             Validate the configuration and exit
+  -h, --help                     Show this help
+
+Settings are taken from the defaults, then the configuration file, then the
+environment variables (PUBLIC_PATH, DATA_PATH, WORKERS, ...) and then the options.";
+
+/// Paths of the running server, installed at startup.
+static PATHS: RwLock<Option<Paths>> = RwLock::new(None);
+
+/// Represents the errors found while loading the configuration.
+#[derive(Debug)]
+pub enum ConfigError {
+  /// The configuration file could not be read.
+  Read(PathBuf, io::Error),
+  /// The configuration file is not valid TOML or has unknown or mistyped settings.
+  Parse(PathBuf, toml::de::Error),
+  /// A command line argument is invalid.
+  Argument(String),
+  /// An environment variable holds an invalid value.
+  Environment(String),
+  /// The settings were read but cannot be used; holds every problem found.
+  Invalid(Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    match self {
+      ConfigError::Read(path, e) => write!(f, "cannot read {}: {}", path.display(), e),
+      ConfigError::Parse(path, e) => write!(f, "invalid {}: {}", path.display(), e),
+      ConfigError::Argument(message) => write!(f, "{}", message),
+      ConfigError::Environment(message) => write!(f, "{}", message),
+      ConfigError::Invalid(problems) => {
+        write!(f, "invalid configuration:")?;
+        for problem in problems {
+          write!(f, "\n  - {}", problem)?;
+        }
+        Ok(())
+      }
+    }
+  }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Represents how connections are served.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+  /// Every connection on a worker thread of a pool.
+  Threads,
+  /// Every connection on a task of the async runtime.
+  Async,
+}
+
+impl FromStr for Mode {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "threads" => Ok(Mode::Threads),
+      "async" => Ok(Mode::Async),
+      _ => Err(format!("invalid mode: {}", s)),
+    }
+  }
+}
+
+/// Represents the `[server]` section: listeners and workers.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSettings {
+  /// Socket addresses to listen on.
+  pub bind: Vec<String>,
+  /// How connections are served.
+  pub mode: Mode,
+  /// Number of worker threads.
+  pub workers: usize,
+  /// Number of accepted connections that can wait for a free worker.
+  pub worker_queue_depth: usize,
+  /// Maximum number of requests served on a single connection.
+  pub max_requests_per_connection: usize,
+  /// Maximum number of connections upgraded to WebSocket at once.
+  pub max_websockets: usize,
+  /// Maximum number of streams of server-sent events open at once.
+  pub max_event_streams: usize,
+  /// How requests for a host no `[[hosts]]` section names are answered.
+  pub unknown_host: UnknownHost,
+}
+
+impl Default for ServerSettings {
+  fn default() -> Self {
+    let workers: Workers = Workers::default();
+
+    Self {
+      bind: vec!["localhost:3000".to_string()],
+      mode: Mode::Threads,
+      workers: workers.count,
+      worker_queue_depth: workers.queue_depth,
+      max_requests_per_connection: KeepAlive::default().max_requests,
+      max_websockets: server::DEFAULT_MAX_WEBSOCKETS,
+      max_event_streams: server::DEFAULT_MAX_EVENT_STREAMS,
+      unknown_host: UnknownHost::Default,
+    }
+  }
+}
+
+/// Represents the `[paths]` section: directories the handlers read.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Paths {
+  /// Directory of the static files.
+  pub public: PathBuf,
+  /// Directory of the JSON data files.
+  pub data: PathBuf,
+}
+
+impl Default for Paths {
+  fn default() -> Self {
+    Self {
+      public: PathBuf::from(format!("{}/public", env!("CARGO_MANIFEST_DIR"))),
+      data: PathBuf::from(format!("{}/data", env!("CARGO_MANIFEST_DIR"))),
+    }
+  }
+}
+
+/// Represents the `[timeouts]` section, in seconds.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Timeouts {
+  /// Time an idle connection is kept open, and allowed to send the rest of a request.
+  pub keep_alive: u64,
+}
+
+impl Default for Timeouts {
+  fn default() -> Self {
+    Self {
+      keep_alive: KeepAlive::default().idle_timeout.as_secs(),
+    }
+  }
+}
+
+/// Represents the `[log]` section.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogSettings {
+  /// Most verbose level printed.
+  pub level: LogLevel,
+}
+
+impl Default for LogSettings {
+  fn default() -> Self {
+    Self {
+      level: LogLevel::Info,
+    }
+  }
+}
+
+/// Represents the `[cache]` section: caching of the static files.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheSettings {
+  /// `Cache-Control` values of the static files, as `prefix=value` rules separated by
+  /// semicolons.
+  pub control: String,
+  /// Maximum number of bytes of the files held in memory; zero disables the cache.
+  pub file_cache_size: usize,
+}
+
+impl Default for CacheSettings {
+  fn default() -> Self {
+    Self {
+      control: format!("/={}", static_files::DEFAULT_CACHE_CONTROL),
+      file_cache_size: 0,
+    }
+  }
+}
+
+/// Represents the `[compression]` section, in bytes.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompressionSettings {
+  /// Minimum size of a body to compress it.
+  pub min_size: u64,
+  /// Maximum size of a body to compress it, as bodies are compressed in memory.
+  pub max_size: u64,
+}
+
+impl CompressionSettings {
+  /// Creates the middleware compressing the bodies with these sizes.
+  pub fn middleware(&self) -> Compression {
+    Compression::new(self.min_size).with_max_size(self.max_size)
+  }
+}
+
+impl Default for CompressionSettings {
+  fn default() -> Self {
+    Self {
+      min_size: middleware::DEFAULT_COMPRESSION_MIN_SIZE,
+      max_size: middleware::DEFAULT_COMPRESSION_MAX_SIZE,
+    }
+  }
+}
+
+/// Represents the `[tls]` section. Its presence turns HTTPS on.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsSettings {
+  /// PEM file with the certificate chain.
+  pub cert: PathBuf,
+  /// PEM file with the private key.
+  pub key: PathBuf,
+  /// Socket address of a plaintext listener redirecting to HTTPS, if any.
+  pub redirect: Option<String>,
+}
+
+/// Represents a `[[hosts]]` section: a site chosen by the `Host` header.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HostSettings {
+  /// Host names, exact (`example.com`) or wildcards (`*.example.com`).
+  pub names: Vec<String>,
+  /// Directory of the static files, if not the one of `[paths]`.
+  pub public: Option<PathBuf>,
+  /// Whether the site serves the requests for unknown hosts, instead of the first one.
+  #[serde(default)]
+  pub default: bool,
+  /// Routes of the site, if not the ones of the application.
+  pub routes: Option<Vec<RouteSettings>>,
+}
+
+/// Represents a `[[hosts.routes]]` section.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteSettings {
+  /// HTTP method served by the route.
+  #[serde(default = "RouteSettings::default_method")]
+  pub method: String,
+  /// Pattern of the paths served by the route, e.g. `/orders/{order_id}`.
+  pub path: String,
+  /// Name of the handler: `orders`, `order_events`, `static`, `not_found`, or `echo`
+  /// for a WebSocket endpoint, whose method is ignored.
+  pub handler: String,
+}
+
+impl RouteSettings {
+  /// Gets the method of the routes that do not give one.
+  fn default_method() -> String {
+    "GET".to_string()
+  }
+}
+
+/// Represents a `[[proxy]]` section: a path prefix forwarded to upstream servers.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProxySettings {
+  /// Path prefix forwarded, e.g. `/api/inventory`.
+  pub prefix: String,
+  /// Socket addresses of the upstream servers, taking the requests in turns.
+  pub upstreams: Vec<String>,
+  /// Whether the prefix is removed from the path forwarded.
+  pub strip_prefix: bool,
+  /// Seconds allowed to connect to an upstream server.
+  pub connect_timeout: u64,
+  /// Seconds allowed to an upstream server between two reads of its response.
+  pub read_timeout: u64,
+  /// Number of failures in a row taking an upstream server out of the rotation.
+  pub max_fails: usize,
+  /// Seconds a failing upstream server stays out of the rotation.
+  pub fail_timeout: u64,
+}
+
+impl Default for ProxySettings {
+  fn default() -> Self {
+    Self {
+      prefix: String::new(),
+      upstreams: Vec::new(),
+      strip_prefix: false,
+      connect_timeout: 5,
+      read_timeout: 30,
+      max_fails: 1,
+      fail_timeout: 10,
+    }
+  }
+}
+
+/// Represents the whole configuration of the server.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+  /// Listeners and workers.
+  pub server: ServerSettings,
+  /// Directories the handlers read.
+  pub paths: Paths,
+  /// Timeouts of the connections.
+  pub timeouts: Timeouts,
+  /// Size limits for the incoming requests.
+  pub limits: RequestLimits,
+  /// Verbosity of the messages.
+  pub log: LogSettings,
+  /// Caching of the static files.
+  pub cache: CacheSettings,
+  /// Compression of the response bodies.
+  pub compression: CompressionSettings,
+  /// HTTPS settings, if enabled.
+  pub tls: Option<TlsSettings>,
+  /// Sites chosen by the `Host` header; a single one serving every host if empty.
+  pub hosts: Vec<HostSettings>,
+  /// Path prefixes forwarded to upstream servers.
+  pub proxy: Vec<ProxySettings>,
+}
+
+impl Config {
+  /// Loads the configuration: the defaults, overridden by the configuration file if
+  /// given, then by the environment variables and then by the command line options.
+  ///
+  /// # Arguments
+  ///
+  /// * `options`: Command line options.
+  pub fn load(options: &Options) -> Result<Config, ConfigError> {
+    let mut config: Config = match &options.config_path {
+      Some(path) => Config::from_file(path)?,
+      None => Config::default(),
+    };
+    config.apply_env()?;
+    config.apply_options(options);
+
+    Ok(config)
+  }
+
+  /// Reads a TOML configuration file. Relative paths in it are taken from the directory
+  /// of the file.
+  ///
+  /// # Arguments
+  ///
+  /// * `path`: Path of the configuration file.
+  pub fn from_file(path: &Path) -> Result<Config, ConfigError> {
+    let text: String =
+      fs::read_to_string(path).map_err(|e| ConfigError::Read(path.to_path_buf(), e))?;
+    let mut config: Config =
+      toml::from_str(&text).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))?;
+
+    let base: &Path = path.parent().unwrap_or_else(|| Path::new(""));
+    config.paths.public = base.join(&config.paths.public);
+    config.paths.data = base.join(&config.paths.data);
+    if let Some(tls) = &mut config.tls {
+      tls.cert = base.join(&tls.cert);
+      tls.key = base.join(&tls.key);
+    }
+    for host in &mut config.hosts {
+      host.public = host.public.as_ref().map(|public| base.join(public));
+    }
+
+    Ok(config)
+  } // end fn from_file()
+
+  /// Overrides the settings given by environment variables, which are kept for
+  /// compatibility with earlier versions of the server.
+  fn apply_env(&mut self) -> Result<(), ConfigError> {
+    if let Some(mode) = env_var::<Mode>("SERVER_MODE")? {
+      self.server.mode = mode;
+    }
+    if let Some(workers) = env_var("WORKERS")? {
+      self.server.workers = workers;
+    }
+    if let Some(depth) = env_var("WORKER_QUEUE_DEPTH")? {
+      self.server.worker_queue_depth = depth;
+    }
+    if let Some(max_requests) = env_var("KEEP_ALIVE_MAX")? {
+      self.server.max_requests_per_connection = max_requests;
+    }
+    if let Some(timeout) = env_var("KEEP_ALIVE_TIMEOUT")? {
+      self.timeouts.keep_alive = timeout;
+    }
+    if let Some(size) = env_var("MAX_HEADER_SIZE")? {
+      self.limits.max_header_size = size;
+    }
+    if let Some(size) = env_var("MAX_BODY_SIZE")? {
+      self.limits.max_body_size = size;
+    }
+    if let Some(path) = env_var("PUBLIC_PATH")? {
+      self.paths.public = path;
+    }
+    if let Some(path) = env_var("DATA_PATH")? {
+      self.paths.data = path;
+    }
+    if let Some(level) = env_var("LOG_LEVEL")? {
+      self.log.level = level;
+    }
+    if let Some(control) = env_var("CACHE_CONTROL")? {
+      self.cache.control = control;
+    }
+    if let Some(size) = env_var("FILE_CACHE_SIZE")? {
+      self.cache.file_cache_size = size;
+    }
+    if let Some(size) = env_var("COMPRESSION_MIN_SIZE")? {
+      self.compression.min_size = size;
+    }
+    if let Some(size) = env_var("COMPRESSION_MAX_SIZE")? {
+      self.compression.max_size = size;
+    }
+    if let (Some(cert), Some(key)) = (env_var("TLS_CERT")?, env_var("TLS_KEY")?) {
+      self.tls = Some(TlsSettings {
+        cert,
+        key,
+        redirect: env_var("HTTPS_REDIRECT_ADDRESS")?,
+      });
+    }
+    Ok(())
+  } // end fn apply_env()
+
+  /// Overrides the settings given as command line options.
+  ///
+  /// # Arguments
+  ///
+  /// * `options`: Command line options.
+  fn apply_options(
+    &mut self,
+    options: &Options,
+  ) {
+    if !options.bind.is_empty() {
+      self.server.bind = options.bind.clone();
+    }
+    if let Some(mode) = options.mode {
+      self.server.mode = mode;
+    }
+    if let Some(workers) = options.workers {
+      self.server.workers = workers;
+    }
+    if let Some(timeout) = options.keep_alive_timeout {
+      self.timeouts.keep_alive = timeout;
+    }
+    if let Some(path) = &options.public {
+      self.paths.public = path.clone();
+    }
+    if let Some(path) = &options.data {
+      self.paths.data = path.clone();
+    }
+    if let Some(level) = options.log_level {
+      self.log.level = level;
+    }
+  }
+
+  /// Checks that the server can start with these settings, reporting every problem
+  /// found at once.
+  pub fn validate(&self) -> Result<(), ConfigError> {
+    let mut problems: Vec<String> = Vec::new();
+
+    if self.server.bind.is_empty() {
+      problems.push("at least one bind address is required".to_string());
+    }
+    for (i, address) in self.server.bind.iter().enumerate() {
+      if let Err(e) = address.to_socket_addrs() {
+        problems.push(format!("bind address '{}' is invalid: {}", address, e));
+      }
+      if self.server.bind[..i].contains(address) {
+        problems.push(format!("bind address '{}' is given twice", address));
+      }
+    }
+    if self.server.mode == Mode::Async && self.server.bind.len() > 1 {
+      problems.push("the async mode listens on a single bind address".to_string());
+    }
+
+    let positive: [(&str, u64); 9] = [
+      ("server.workers", self.server.workers as u64),
+      (
+        "server.worker_queue_depth",
+        self.server.worker_queue_depth as u64,
+      ),
+      (
+        "server.max_requests_per_connection",
+        self.server.max_requests_per_connection as u64,
+      ),
+      ("server.max_websockets", self.server.max_websockets as u64),
+      (
+        "server.max_event_streams",
+        self.server.max_event_streams as u64,
+      ),
+      ("timeouts.keep_alive", self.timeouts.keep_alive),
+      ("limits.max_header_size", self.limits.max_header_size as u64),
+      ("limits.max_body_size", self.limits.max_body_size as u64),
+      ("compression.max_size", self.compression.max_size),
+    ];
+    for (name, value) in positive {
+      if value == 0 {
+        problems.push(format!("{} must be greater than zero", name));
+      }
+    }
+    if self.compression.min_size > self.compression.max_size {
+      problems.push(
+        "compression.min_size must not be greater than compression.max_size".to_string(),
+      );
+    }
+    for rule in self
+      .cache
+      .control
+      .split(';')
+      .filter(|r| !r.trim().is_empty())
+    {
+      if !rule.split_once('=').is_some_and(|(prefix, value)| {
+        prefix.trim().starts_with('/') && !value.trim().is_empty()
+      }) {
+        problems.push(format!(
+          "cache.control rule '{}' is not written as /prefix=value",
+          rule.trim()
+        ));
+      }
+    }
+
+    if !self.paths.public.is_dir() {
+      problems.push(format!(
+        "public directory {} does not exist",
+        self.paths.public.display()
+      ));
+    }
+    if !self.paths.data.is_dir() {
+      problems.push(format!(
+        "data directory {} does not exist",
+        self.paths.data.display()
+      ));
+    } else if !self.paths.data.join("orders.json").is_file() {
+      problems.push(format!(
+        "data directory {} has no orders.json file",
+        self.paths.data.display()
+      ));
+    }
+
+    if let Some(tls) = &self.tls {
+      problems.extend(Self::validate_tls(tls, &self.server));
+    }
+
+    if !self.hosts.is_empty() {
+      if let Err(host_problems) = self.virtual_hosts() {
+        problems.extend(host_problems);
+      }
+      if self.server.mode == Mode::Async {
+        problems.push("virtual hosts are only supported in the threads mode".to_string());
+      }
+    }
+
+    for (i, proxy) in self.proxy.iter().enumerate() {
+      problems.extend(Self::validate_proxy(i, proxy));
+      if self.proxy[..i].iter().any(|p| p.prefix == proxy.prefix) {
+        problems.push(format!("proxy prefix '{}' is given twice", proxy.prefix));
+      }
+    }
+    if !self.proxy.is_empty() && self.server.mode == Mode::Async {
+      problems.push("the proxy is only supported in the threads mode".to_string());
+    }
+    if self.server.mode == Mode::Async {
+      if self.server.max_websockets != server::DEFAULT_MAX_WEBSOCKETS {
+        problems.push("WebSockets are only supported in the threads mode".to_string());
+      }
+      if self.server.max_event_streams != server::DEFAULT_MAX_EVENT_STREAMS {
+        problems.push(
+          "streams of order events are only supported in the threads mode".to_string(),
+        );
+      }
+    }
+
+    match problems.is_empty() {
+      true => Ok(()),
+      false => Err(ConfigError::Invalid(problems)),
+    }
+  } // end fn validate()
+
+  /// Checks the settings of a forwarded prefix.
+  ///
+  /// # Arguments
+  ///
+  /// * `i`: Position of the `[[proxy]]` section.
+  /// * `proxy`: Settings of the prefix.
+  fn validate_proxy(
+    i: usize,
+    proxy: &ProxySettings,
+  ) -> Vec<String> {
+    let mut problems: Vec<String> = Vec::new();
+
+    if !proxy.prefix.starts_with('/') || proxy.prefix.contains(['?', '#']) {
+      problems.push(format!(
+        "proxy[{}] prefix '{}' must be a path starting with /",
+        i, proxy.prefix
+      ));
+    }
+    if proxy.upstreams.is_empty() {
+      problems.push(format!("proxy[{}] needs at least one upstream", i));
+    }
+    for upstream in &proxy.upstreams {
+      if let Err(e) = upstream.to_socket_addrs() {
+        problems.push(format!(
+          "proxy[{}] upstream '{}' is invalid: {}",
+          i, upstream, e
+        ));
+      }
+    }
+
+    let positive: [(&str, u64); 4] = [
+      ("connect_timeout", proxy.connect_timeout),
+      ("read_timeout", proxy.read_timeout),
+      ("max_fails", proxy.max_fails as u64),
+      ("fail_timeout", proxy.fail_timeout),
+    ];
+    for (name, value) in positive {
+      if value == 0 {
+        problems.push(format!("proxy[{}].{} must be greater than zero", i, name));
+      }
+    }
+    problems
+  } // end fn validate_proxy()
+
+  /// Checks the HTTPS settings, loading the certificate and the key.
+  ///
+  /// # Arguments
+  ///
+  /// * `tls`: HTTPS settings.
+  /// * `server`: Listeners and workers.
+  #[cfg(feature = "tls")]
+  fn validate_tls(
+    tls: &TlsSettings,
+    server: &ServerSettings,
+  ) -> Vec<String> {
+    let mut problems: Vec<String> = Vec::new();
+
+    if let Err(e) = crate::tls::load_config(&tls.cert, &tls.key) {
+      problems.push(format!("TLS certificate or key is invalid: {}", e));
+    }
+    if server.mode == Mode::Async {
+      problems.push("TLS is only supported in the threads mode".to_string());
+    }
+    if let Some(redirect) = &tls.redirect {
+      if let Err(e) = redirect.to_socket_addrs() {
+        problems.push(format!("redirect address '{}' is invalid: {}", redirect, e));
+      }
+      if server.bind.contains(redirect) {
+        problems.push(format!(
+          "redirect address '{}' is also a bind address",
+          redirect
+        ));
+      }
+    }
+    problems
+  }
+
+  /// Rejects the HTTPS settings of a server built without TLS support.
+  ///
+  /// # Arguments
+  ///
+  /// * `tls`: HTTPS settings.
+  /// * `server`: Listeners and workers.
+  #[cfg(not(feature = "tls"))]
+  fn validate_tls(
+    _tls: &TlsSettings,
+    _server: &ServerSettings,
+  ) -> Vec<String> {
+    vec!["TLS requires the server to be built with the `tls` feature".to_string()]
+  }
+
+  /// Gets the settings of persistent connections.
+  pub fn keep_alive(&self) -> KeepAlive {
+    KeepAlive {
+      idle_timeout: Duration::from_secs(self.timeouts.keep_alive),
+      max_requests: self.server.max_requests_per_connection,
+    }
+  }
+
+  /// Builds the sites served by the server.
+  pub fn virtual_hosts(&self) -> Result<VirtualHosts, Vec<String>> {
+    match self.hosts.is_empty() {
+      true => Ok(VirtualHosts::default()),
+      false => VirtualHosts::from_settings(&self.hosts, self.server.unknown_host),
+    }
+  }
+
+  /// Gets the settings of the worker threads.
+  pub fn workers(&self) -> Workers {
+    Workers {
+      count: self.server.workers,
+      queue_depth: self.server.worker_queue_depth,
+    }
+  }
+}
+
+/// Represents the command line options.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Options {
+  /// TOML configuration file.
+  pub config_path: Option<PathBuf>,
+  /// Whether to validate the configuration and exit.
+  pub check_config: bool,
+  /// Whether to print the usage and exit.
+  pub help: bool,
+  /// Socket addresses to listen on, replacing the configured ones.
+  pub bind: Vec<String>,
+  /// Directory of the static files.
+  pub public: Option<PathBuf>,
+  /// Directory of the JSON data files.
+  pub data: Option<PathBuf>,
+  /// How connections are served.
+  pub mode: Option<Mode>,
+  /// Number of worker threads.
+  pub workers: Option<usize>,
+  /// Seconds an idle connection is kept open.
+  pub keep_alive_timeout: Option<u64>,
+  /// Most verbose level printed.
+  pub log_level: Option<LogLevel>,
+}
+
+impl Options {
+  /// Parses the command line arguments, without the program name. Values are given as
+  /// the next argument or after `=`, e.g. `--workers 4` or `--workers=4`.
+  ///
+  /// # Arguments
+  ///
+  /// * `args`: Command line arguments.
+  pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Options, ConfigError> {
+    let mut options: Options = Options::default();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+      let (flag, inline) = match arg.split_once('=') {
+        Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value)),
+        _ => (arg.clone(), None),
+      };
+      let mut value = || -> Result<String, ConfigError> {
+        match inline {
+          Some(value) => Ok(value.to_string()),
+          None => args
+            .next()
+            .ok_or_else(|| ConfigError::Argument(format!("{} needs a value", flag))),
+        }
+      };
+
+      match flag.as_str() {
+        "-c" | "--config" => options.config_path = Some(PathBuf::from(value()?)),
+        "-b" | "--bind" => options.bind.push(value()?),
+        "--public" => options.public = Some(PathBuf::from(value()?)),
+        "--data" => options.data = Some(PathBuf::from(value()?)),
+        "--mode" => options.mode = Some(parse_value(&flag, &value()?)?),
+        "-w" | "--workers" => options.workers = Some(parse_value(&flag, &value()?)?),
+        "--keep-alive-timeout" => {
+          options.keep_alive_timeout = Some(parse_value(&flag, &value()?)?)
+        }
+        "--log-level" => options.log_level = Some(parse_value(&flag, &value()?)?),
+        "--check-config" => options.check_config = true,
+        "-h" | "--help" => options.help = true,
+        _ => return Err(ConfigError::Argument(format!("unknown argument: {}", arg))),
+      }
+    }
+    Ok(options)
+  } // end fn parse()
+}
+
+/// Parses the value of a command line option.
+///
+/// # Arguments
+///
+/// * `flag`: Name of the option, for the error message.
+/// * `value`: Text of the value.
+fn parse_value<T: FromStr>(
+  flag: &str,
+  value: &str,
+) -> Result<T, ConfigError> {
+  value
+    .parse()
+    .map_err(|_| ConfigError::Argument(format!("invalid value for {}: {}", flag, value)))
+}
+
+/// Parses an environment variable, if it is set.
+///
+/// # Arguments
+///
+/// * `name`: Name of the variable.
+fn env_var<T: FromStr>(name: &str) -> Result<Option<T>, ConfigError> {
+  match env::var(name) {
+    Ok(value) => value.parse().map(Some).map_err(|_| {
+      ConfigError::Environment(format!("invalid value for {}: {}", name, value))
+    }),
+    Err(_) => Ok(None),
+  }
+}
+
+/// Installs the paths of the running server, read by the handlers.
+///
+/// # Arguments
+///
+/// * `paths`: Directories the handlers read.
+pub fn install_paths(paths: Paths) {
+  *PATHS.write().unwrap() = Some(paths);
+}
+
+/// Gets the paths of the running server, or the default ones if none were installed.
+pub fn paths() -> Paths {
+  PATHS.read().unwrap().clone().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Gets the path of the example configuration file shipped with the server.
+  fn example_path() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("http_server.toml")
+  }
+
+  #[test]
+  fn test_example_file() {
+    let config = Config::from_file(&example_path()).unwrap();
+
+    assert_eq!(config.server.bind, vec!["localhost:3000"]);
+    assert_eq!(config.log.level, LogLevel::Info);
+    assert!(config.paths.public.ends_with("public"));
+    assert!(config.tls.is_none());
+    assert!(config.hosts.is_empty());
+    assert_eq!(config.cache, CacheSettings::default());
+    assert_eq!(config.compression, CompressionSettings::default());
+    config.validate().unwrap();
+  }
+
+  #[test]
+  fn test_cache_and_compression_sections() {
+    let text = "[cache]\ncontrol = \"/css=public, max-age=86400;/=no-cache\"\n\
+                file_cache_size = 1048576\n\n\
+                [compression]\nmin_size = 512\nmax_size = 4096\n";
+    let config: Config = toml::from_str(text).unwrap();
+
+    assert_eq!(
+      config.cache.control,
+      "/css=public, max-age=86400;/=no-cache"
+    );
+    assert_eq!(config.cache.file_cache_size, 1048576);
+    assert_eq!(config.compression.min_size, 512);
+    assert_eq!(config.compression.max_size, 4096);
+
+    let mut config = Config::default();
+    config.cache.control = "/=no-cache;css".to_string();
+    config.compression.min_size = 2048;
+    config.compression.max_size = 1024;
+
+    let Err(ConfigError::Invalid(problems)) = config.validate() else {
+      panic!("the configuration should be invalid");
+    };
+    assert_eq!(problems.len(), 2);
+    assert!(problems[0].contains("compression.min_size"));
+    assert!(problems[1].contains("'css'"));
+  }
+
+  #[test]
+  fn test_hosts_sections() {
+    let text = "[server]\nunknown_host = \"misdirected\"\n\n\
+                [[hosts]]\nnames = [\"example.com\"]\n\n\
+                [[hosts]]\nnames = [\"*.example.org\"]\npublic = \"public\"\ndefault = true\n\
+                [[hosts.routes]]\npath = \"/{*path}\"\nhandler = \"static\"\n";
+    let config: Config = toml::from_str(text).unwrap();
+
+    assert_eq!(config.server.unknown_host, UnknownHost::Misdirected);
+    assert_eq!(config.hosts.len(), 2);
+    assert!(config.hosts[0].routes.is_none());
+    assert!(config.hosts[1].default);
+    let routes = config.hosts[1].routes.as_ref().unwrap();
+    assert_eq!(routes[0].method, "GET");
+    assert_eq!(routes[0].handler, "static");
+
+    let unknown = toml::from_str::<Config>("[[hosts]]\nnames = []\nroot = \"x\"\n");
+    assert!(unknown.unwrap_err().to_string().contains("root"));
+  }
+
+  #[test]
+  fn test_proxy_sections() {
+    let text = "[[proxy]]\nprefix = \"/inventory\"\nupstreams = [\"127.0.0.1:8081\", \"127.0.0.1:8082\"]\n\
+                strip_prefix = true\n\n\
+                [[proxy]]\nprefix = \"inventory\"\nupstreams = [\"nowhere\"]\nmax_fails = 0\n";
+    let mut config: Config = toml::from_str(text).unwrap();
+
+    assert_eq!(config.proxy[0].upstreams.len(), 2);
+    assert!(config.proxy[0].strip_prefix);
+    assert_eq!(config.proxy[0].read_timeout, 30);
+
+    config.paths = Paths::default();
+    let Err(ConfigError::Invalid(problems)) = config.validate() else {
+      panic!("the configuration should be invalid");
+    };
+    assert_eq!(problems.len(), 3, "{:?}", problems);
+    assert!(problems[0].contains("prefix"));
+    assert!(problems[2].contains("max_fails"));
+  }
+
+  #[test]
+  fn test_parse_errors() {
+    let unknown = toml::from_str::<Config>("[server]\nport = 80\n");
+    assert!(unknown.unwrap_err().to_string().contains("port"));
+    let mistyped = toml::from_str::<Config>("[log]\nlevel = \"loud\"\n");
+    assert!(mistyped.is_err());
+
+    let partial: Config = toml::from_str("[limits]\nmax_body_size = 10\n").unwrap();
+    assert_eq!(partial.limits.max_body_size, 10);
+    assert_eq!(partial.limits.max_header_size, 8 * 1024);
+  }
+
+  #[test]
+  fn test_options_override_file() {
+    let example = example_path();
+    let args = [
+      "--config",
+      example.to_str().unwrap(),
+      "-b",
+      "127.0.0.1:0",
+      "--bind=[::1]:0",
+      "--workers=3",
+      "--log-level",
+      "debug",
+      "--check-config",
+    ];
+    let options = Options::parse(args.map(String::from)).unwrap();
+    assert!(options.check_config);
+
+    let mut config = Config::from_file(options.config_path.as_ref().unwrap()).unwrap();
+    config.apply_options(&options);
+    assert_eq!(config.server.bind, vec!["127.0.0.1:0", "[::1]:0"]);
+    assert_eq!(config.workers().count, 3);
+    assert_eq!(config.log.level, LogLevel::Debug);
+
+    for args in [&["--workers", "many"][..], &["--bind"], &["--verbose"]] {
+      let args = args.iter().map(|a| a.to_string());
+      assert!(matches!(
+        Options::parse(args),
+        Err(ConfigError::Argument(_))
+      ));
+    }
+  }
+
+  #[test]
+  fn test_validate_reports_every_problem() {
+    let mut config = Config::default();
+    config.server.bind = vec!["localhost:3000".to_string(), "localhost:3000".to_string()];
+    config.server.mode = Mode::Async;
+    config.server.workers = 0;
+    config.server.max_websockets = 8;
+    config.paths.data = PathBuf::from("/nonexistent");
+
+    let Err(ConfigError::Invalid(problems)) = config.validate() else {
+      panic!("the configuration should be invalid");
+    };
+    assert_eq!(problems.len(), 5);
+    assert!(problems[0].contains("given twice"));
+    assert!(problems[3].contains("data directory"));
+    assert!(problems[4].contains("WebSockets"));
+  }
+}
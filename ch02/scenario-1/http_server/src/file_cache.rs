@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use std::{fs, io};
+
+use http::body::Body;
+
+/// Cache shared by every worker, installed at startup when enabled.
+static FILE_CACHE: Mutex<Option<FileCache>> = Mutex::new(None);
+
+/// Represents a file held in the cache.
+struct CachedFile {
+  /// Contents of the file, shared with the bodies sending them.
+  contents: Arc<[u8]>,
+  /// Modification time of the file when it was read.
+  modified: Option<SystemTime>,
+  /// Value of the cache clock when the file was last used.
+  last_used: u64,
+}
+
+/// Represents an in-memory cache of file contents with a maximum total size. When it
+/// is full, the least recently used files are evicted first.
+pub struct FileCache {
+  /// Maximum number of bytes of all the files held.
+  capacity: usize,
+  /// Number of bytes of all the files held.
+  size: usize,
+  /// Clock increased on every use, to find the least recently used file.
+  clock: u64,
+  /// Files held, by path.
+  files: HashMap<PathBuf, CachedFile>,
+}
+
+impl FileCache {
+  /// Creates an empty [`FileCache`] object.
+  ///
+  /// # Arguments
+  ///
+  /// * `capacity`: Maximum number of bytes of all the files held.
+  pub fn new(capacity: usize) -> Self {
+    Self {
+      capacity,
+      size: 0,
+      clock: 0,
+      files: HashMap::new(),
+    }
+  }
+
+  /// Gets the contents of a file if they are held and the file did not change since
+  /// they were read.
+  ///
+  /// # Arguments
+  ///
+  /// * `path`: Path of the file.
+  /// * `metadata`: Current metadata of the file.
+  pub fn get(
+    &mut self,
+    path: &Path,
+    metadata: &fs::Metadata,
+  ) -> Option<Arc<[u8]>> {
+    self.clock += 1;
+    let file: &mut CachedFile = self.files.get_mut(path)?;
+
+    if file.modified != metadata.modified().ok()
+      || file.contents.len() as u64 != metadata.len()
+    {
+      self.remove(path);
+      return None;
+    }
+    file.last_used = self.clock;
+    Some(Arc::clone(&file.contents))
+  }
+
+  /// Holds the contents of a file, evicting the least recently used files if needed.
+  /// Files larger than the capacity are not held.
+  ///
+  /// # Arguments
+  ///
+  /// * `path`: Path of the file.
+  /// * `metadata`: Metadata of the file when it was read.
+  /// * `contents`: Contents of the file.
+  pub fn insert(
+    &mut self,
+    path: &Path,
+    metadata: &fs::Metadata,
+    contents: Arc<[u8]>,
+  ) {
+    if contents.len() > self.capacity {
+      return;
+    }
+    self.remove(path);
+
+    while self.size + contents.len() > self.capacity {
+      let oldest: Option<PathBuf> = self
+        .files
+        .iter()
+        .min_by_key(|(_, f)| f.last_used)
+        .map(|(p, _)| p.clone());
+      match oldest {
+        Some(oldest) => self.remove(&oldest),
+        None => break,
+      }
+    }
+
+    self.clock += 1;
+    self.size += contents.len();
+    self.files.insert(
+      path.to_path_buf(),
+      CachedFile {
+        contents,
+        modified: metadata.modified().ok(),
+        last_used: self.clock,
+      },
+    );
+  }
+
+  /// Removes a file from the cache.
+  ///
+  /// # Arguments
+  ///
+  /// * `path`: Path of the file.
+  fn remove(
+    &mut self,
+    path: &Path,
+  ) {
+    if let Some(file) = self.files.remove(path) {
+      self.size -= file.contents.len();
+    }
+  }
+}
+
+/// Installs the shared cache, or disables it when the capacity is zero.
+///
+/// # Arguments
+///
+/// * `capacity`: Maximum number of bytes of all the files held.
+pub fn install(capacity: usize) {
+  *FILE_CACHE.lock().unwrap() = (capacity > 0).then(|| FileCache::new(capacity));
+}
+
+/// Loads a file as a body, from the shared cache when enabled.
+///
+/// # Arguments
+///
+/// * `path`: Path of the file.
+/// * `metadata`: Current metadata of the file.
+pub fn load(
+  path: &Path,
+  metadata: &fs::Metadata,
+) -> io::Result<Body> {
+  // Only the lookup holds the lock, as the contents are shared and not copied
+  let cached: Option<Option<Arc<[u8]>>> = FILE_CACHE
+    .lock()
+    .unwrap()
+    .as_mut()
+    .map(|cache| cache.get(path, metadata));
+  match cached {
+    None => return Body::from_file(path),
+    Some(Some(contents)) => return Ok(Body::from(contents)),
+    Some(None) => {}
+  }
+
+  // Read the file without holding the lock
+  let contents: Arc<[u8]> = Arc::from(fs::read(path)?);
+  if let Some(cache) = FILE_CACHE.lock().unwrap().as_mut() {
+    cache.insert(path, metadata, Arc::clone(&contents));
+  }
+  Ok(Body::from(contents))
+}
+
+#[cfg(test)]
+mod tests {
+  use std::env;
+
+  use super::*;
+
+  #[test]
+  fn test_evicts_least_recently_used() {
+    let dir = env::temp_dir().join(format!("file-cache-lru-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let mut cache = FileCache::new(10);
+
+    for name in ["a", "b", "c"] {
+      fs::write(dir.join(name), name.repeat(4)).unwrap();
+    }
+    let meta = |name: &str| fs::metadata(dir.join(name)).unwrap();
+
+    cache.insert(&dir.join("a"), &meta("a"), Arc::from(&b"aaaa"[..]));
+    cache.insert(&dir.join("b"), &meta("b"), Arc::from(&b"bbbb"[..]));
+    assert_eq!(
+      cache.get(&dir.join("a"), &meta("a")).as_deref(),
+      Some(&b"aaaa"[..])
+    );
+    cache.insert(&dir.join("c"), &meta("c"), Arc::from(&b"cccc"[..]));
+
+    assert!(cache.get(&dir.join("b"), &meta("b")).is_none());
+    assert!(cache.get(&dir.join("a"), &meta("a")).is_some());
+    assert!(cache.get(&dir.join("c"), &meta("c")).is_some());
+    assert_eq!(cache.size, 8);
+
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_changed_file_is_invalidated() {
+    let path = env::temp_dir().join(format!("file-cache-stale-{}", std::process::id()));
+    fs::write(&path, "old").unwrap();
+    let mut cache = FileCache::new(100);
+
+    cache.insert(&path, &fs::metadata(&path).unwrap(), Arc::from(&b"old"[..]));
+    fs::write(&path, "newer").unwrap();
+
+    assert_eq!(cache.get(&path, &fs::metadata(&path).unwrap()), None);
+    assert_eq!(cache.size, 0);
+
+    fs::remove_file(&path).unwrap();
+  }
+}
@@ -1,11 +1,29 @@
-use std::{collections::HashMap, env, fs, path::Path};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
 
+use http::websocket::{Message, Stream, WebSocket};
 use http::{
-  http_request::{HttpRequest, Resource},
+  body::Body,
+  date::DateTime,
+  encoding::{self, AcceptEncoding, ContentCoding},
+  header_map::HeaderMap,
+  http_request::HttpRequest,
+  http_request::{Method, Version},
   http_response::HttpResponse,
+  resource::Query,
+  sse,
+  status_code::StatusCode,
 };
 use serde::{Deserialize, Serialize};
 
+use crate::config;
+use crate::file_cache;
+use crate::order_events::{self, EventStream};
+use crate::order_store::{OrderStore, StoreError};
+use crate::range::{self, RangeRequest};
+use crate::static_files::{self, public_root, ResolveError, Validators};
+
 /// Represents a handler for HTTP requests.
 pub trait Handler {
   /// Handles the given request to produce the respective response.
@@ -20,19 +38,24 @@ pub trait Handler {
   /// # Arguments
   ///
   /// * `filename`: Name of the file to load relative to the public directory.
-  fn load_file(file_name: &str) -> Option<String> {
-    let default_path = format!("{}/public", env!("CARGO_MANIFEST_DIR"));
-    let public_path = env::var("PUBLIC_PATH").unwrap_or(default_path);
-    let full_path = format!("{}/{}", public_path, file_name);
-
-    let contents: Result<String, std::io::Error> = fs::read_to_string(full_path);
+  fn load_file(file_name: &str) -> Option<Body> {
+    let contents: Result<Body, std::io::Error> =
+      Body::from_file(public_root().join(file_name));
 
     contents.ok()
   }
 }
 
+/// Statuses a shipping order can have.
+const ORDER_STATUSES: [&str; 5] =
+  ["Pending", "Shipped", "Delivered", "Cancelled", "Returned"];
+/// Abbreviated names of the months in order dates, starting with January.
+const MONTHS: [&str; 12] = [
+  "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
 /// Represents the status of shipping order.
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct OrderStatus {
   /// Unique identifier (ID) of the order.
   order_id: i32,
@@ -42,76 +65,587 @@ pub struct OrderStatus {
   order_status: String,
 }
 
+impl OrderStatus {
+  /// Gets the unique identifier (ID) of the order.
+  pub fn order_id(&self) -> i32 {
+    self.order_id
+  }
+
+  /// Checks the fields of the order, returning the problems found.
+  pub fn validate(&self) -> Vec<String> {
+    let mut problems: Vec<String> = Vec::new();
+
+    if self.order_id <= 0 {
+      problems.push("order_id must be a positive number".to_string());
+    }
+    if !is_order_date(&self.order_date) {
+      problems.push(format!(
+        "order_date must be a date like '21 Jan 2020', not '{}'",
+        self.order_date
+      ));
+    }
+    if !ORDER_STATUSES.contains(&self.order_status.as_str()) {
+      problems.push(format!(
+        "order_status must be one of {}, not '{}'",
+        ORDER_STATUSES.join(", "),
+        self.order_status
+      ));
+    }
+    problems
+  }
+}
+
+/// Represents the fields of an order sent by a client to create or change it.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OrderFields {
+  /// Unique identifier (ID) of the order.
+  order_id: Option<i32>,
+  /// Date of the order.
+  order_date: Option<String>,
+  /// Status of the order.
+  order_status: Option<String>,
+}
+
+/// Represents the reasons a request to the orders API fails.
+#[derive(Debug)]
+enum OrderError {
+  /// The request is invalid; holds every problem found.
+  Invalid(Vec<String>),
+  /// There is no order with the requested ID.
+  NotFound,
+  /// An order with the given ID already exists.
+  Conflict(i32),
+  /// The body of the request is not JSON.
+  UnsupportedMediaType,
+  /// Every order ID is taken.
+  IdsExhausted,
+  /// The data file cannot be used.
+  Store(StoreError),
+}
+
+impl From<StoreError> for OrderError {
+  fn from(value: StoreError) -> Self {
+    OrderError::Store(value)
+  }
+}
+
+impl OrderError {
+  /// Creates the response telling the client why its request failed.
+  fn response(self) -> HttpResponse {
+    let (status_code, problems): (StatusCode, Vec<String>) = match self {
+      OrderError::Invalid(problems) => (StatusCode::BAD_REQUEST, problems),
+      OrderError::NotFound => {
+        return HttpResponse::new(StatusCode::NOT_FOUND, None, Body::Empty);
+      }
+      OrderError::Conflict(order_id) => (
+        StatusCode::CONFLICT,
+        vec![format!("order {} already exists", order_id)],
+      ),
+      OrderError::UnsupportedMediaType => (
+        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        vec!["the body must be application/json".to_string()],
+      ),
+      OrderError::IdsExhausted => (
+        StatusCode::INSUFFICIENT_STORAGE,
+        vec!["no order ID is left".to_string()],
+      ),
+      OrderError::Store(e) => {
+        error!("Failed to use the orders data file: {}", e);
+        return HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR, None, Body::Empty);
+      }
+    };
+
+    json_response(status_code, &serde_json::json!({ "errors": problems }))
+  }
+}
+
 /// Represents a handler to serve the API (i.e. serve JSON files).
 pub struct WebServiceHandler;
 
 impl WebServiceHandler {
-  /// Loads shipping orders from a JSON data file.
-  fn load_json() -> Vec<OrderStatus> {
-    let default_path = format!("{}/data", env!("CARGO_MANIFEST_DIR"));
-    let data_path = env::var("DATA_PATH").unwrap_or(default_path);
+  /// Gets the path of the JSON data file with the shipping orders, in the data
+  /// directory configured at startup.
+  pub fn orders_path() -> PathBuf {
+    config::paths().data.join("orders.json")
+  }
 
-    let full_path = format!("{}/{}", data_path, "orders.json");
+  /// Creates the response with the orders matching the query parameters, or "400 Bad
+  /// Request" if they are invalid.
+  ///
+  /// # Arguments
+  ///
+  /// * `orders`: Shipping orders to filter.
+  /// * `query`: Query parameters of the request.
+  pub fn orders_response(
+    orders: Vec<OrderStatus>,
+    query: &Query,
+  ) -> HttpResponse {
+    match Self::filter_orders(orders, query) {
+      Some(orders) => json_response(StatusCode::OK, &orders),
+      None => HttpResponse::new(StatusCode::BAD_REQUEST, None, Body::Empty),
+    }
+  }
 
-    let json_contents = fs::read_to_string(full_path).unwrap();
+  /// Creates the response with the order of the given ID, "404 Not Found" if there is
+  /// no such order, or "400 Bad Request" if the ID is not a number.
+  ///
+  /// # Arguments
+  ///
+  /// * `orders`: Shipping orders to search.
+  /// * `order_id`: ID of the order, as given in the path.
+  pub fn order_response(
+    orders: Vec<OrderStatus>,
+    order_id: &str,
+  ) -> HttpResponse {
+    let Ok(order_id) = order_id.parse::<i32>() else {
+      return HttpResponse::new(StatusCode::BAD_REQUEST, None, Body::Empty);
+    };
 
-    let orders: Vec<OrderStatus> = serde_json::from_str(&json_contents.as_str()).unwrap();
+    match orders.into_iter().find(|o| o.order_id == order_id) {
+      Some(order) => json_response(StatusCode::OK, &order),
+      None => HttpResponse::new(StatusCode::NOT_FOUND, None, Body::Empty),
+    }
+  }
 
-    orders
+  /// Keeps the orders matching the `order_id` and `order_status` query parameters. A
+  /// parameter given several times matches any of its values.
+  ///
+  /// Returns `None` if an `order_id` value is not a number.
+  ///
+  /// # Arguments
+  ///
+  /// * `orders`: Shipping orders to filter.
+  /// * `query`: Query parameters of the request.
+  fn filter_orders(
+    orders: Vec<OrderStatus>,
+    query: &Query,
+  ) -> Option<Vec<OrderStatus>> {
+    let ids: Vec<i32> = query
+      .get_all("order_id")
+      .iter()
+      .map(|id| id.parse().ok())
+      .collect::<Option<Vec<i32>>>()?;
+    let statuses: &[String] = query.get_all("order_status");
+
+    let orders: Vec<OrderStatus> = orders
+      .into_iter()
+      .filter(|o| ids.is_empty() || ids.contains(&o.order_id))
+      .filter(|o| statuses.is_empty() || statuses.contains(&o.order_status))
+      .collect();
+
+    Some(orders)
+  }
+
+  /// Creates an order from the body of the request, with the next free ID unless the
+  /// body gives one, and responds with it.
+  ///
+  /// # Arguments
+  ///
+  /// * `store`: Store of the shipping orders.
+  /// * `request`: HTTP request with the order.
+  fn create_order(
+    store: &OrderStore,
+    request: &HttpRequest,
+  ) -> Result<HttpResponse, OrderError> {
+    let fields: OrderFields = Self::parse_fields(request)?;
+    let Some(order_status) = fields.order_status else {
+      return Err(OrderError::Invalid(vec![
+        "order_status is required".to_string()
+      ]));
+    };
+
+    let created: OrderStatus = store.modify(|orders| {
+      let order_id: i32 = match fields.order_id {
+        Some(order_id) if orders.iter().any(|o| o.order_id == order_id) => {
+          return Err(OrderError::Conflict(order_id));
+        }
+        Some(order_id) => order_id,
+        None => Self::next_order_id(orders).ok_or(OrderError::IdsExhausted)?,
+      };
+      let order: OrderStatus = OrderStatus {
+        order_id,
+        order_date: fields.order_date.unwrap_or_else(today),
+        order_status,
+      };
+      Self::check(&order)?;
+      orders.push(order.clone());
+      Ok(order)
+    })?;
+
+    let location: String = format!("/api/shipping/orders/{}", created.order_id);
+    let mut response: HttpResponse = json_response(StatusCode::CREATED, &created);
+    response.headers_mut().insert("Location", &location);
+    Ok(response)
+  } // end fn create_order()
+
+  /// Changes an order with the fields in the body of the request and responds with it.
+  ///
+  /// # Arguments
+  ///
+  /// * `store`: Store of the shipping orders.
+  /// * `request`: HTTP request with the fields.
+  /// * `order_id`: ID of the order, as given in the path.
+  /// * `replace`: Whether every field must be given (PUT) or only the changed ones
+  ///   (PATCH).
+  fn update_order(
+    store: &OrderStore,
+    request: &HttpRequest,
+    order_id: &str,
+    replace: bool,
+  ) -> Result<HttpResponse, OrderError> {
+    let order_id: i32 = Self::parse_order_id(order_id)?;
+    let fields: OrderFields = Self::parse_fields(request)?;
+
+    let mut problems: Vec<String> = Vec::new();
+    if fields.order_id.is_some_and(|id| id != order_id) {
+      problems.push("order_id cannot be changed".to_string());
+    }
+    if replace {
+      if fields.order_date.is_none() {
+        problems.push("order_date is required".to_string());
+      }
+      if fields.order_status.is_none() {
+        problems.push("order_status is required".to_string());
+      }
+    } else if fields.order_date.is_none() && fields.order_status.is_none() {
+      problems.push("order_date or order_status is required".to_string());
+    }
+    if !problems.is_empty() {
+      return Err(OrderError::Invalid(problems));
+    }
+
+    let updated: OrderStatus = store.modify(|orders| {
+      let Some(order) = orders.iter_mut().find(|o| o.order_id == order_id) else {
+        return Err(OrderError::NotFound);
+      };
+      let mut updated: OrderStatus = order.clone();
+      if let Some(order_date) = fields.order_date {
+        updated.order_date = order_date;
+      }
+      if let Some(order_status) = fields.order_status {
+        updated.order_status = order_status;
+      }
+      Self::check(&updated)?;
+      *order = updated.clone();
+      Ok(updated)
+    })?;
+
+    Ok(json_response(StatusCode::OK, &updated))
+  } // end fn update_order()
+
+  /// Removes an order.
+  ///
+  /// # Arguments
+  ///
+  /// * `store`: Store of the shipping orders.
+  /// * `order_id`: ID of the order, as given in the path.
+  fn delete_order(
+    store: &OrderStore,
+    order_id: &str,
+  ) -> Result<HttpResponse, OrderError> {
+    let order_id: i32 = Self::parse_order_id(order_id)?;
+
+    store.modify(|orders| {
+      let Some(position) = orders.iter().position(|o| o.order_id == order_id) else {
+        return Err(OrderError::NotFound);
+      };
+      orders.remove(position);
+      Ok(())
+    })?;
+
+    Ok(HttpResponse::new(StatusCode::NO_CONTENT, None, Body::Empty))
+  }
+
+  /// Gets the ID of a new order: the one after the highest ID, or the lowest free one
+  /// once the highest possible ID is taken.
+  ///
+  /// # Arguments
+  ///
+  /// * `orders`: Current shipping orders.
+  fn next_order_id(orders: &[OrderStatus]) -> Option<i32> {
+    let highest: i32 = orders.iter().map(|o| o.order_id).max().unwrap_or(0);
+    if let Some(order_id) = highest.max(0).checked_add(1) {
+      return Some(order_id);
+    }
+
+    let taken: HashSet<i32> = orders.iter().map(|o| o.order_id).collect();
+    (1..=i32::MAX).find(|order_id| !taken.contains(order_id))
+  }
+
+  /// Parses the fields of an order in the JSON body of a request.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request with the fields.
+  fn parse_fields(request: &HttpRequest) -> Result<OrderFields, OrderError> {
+    let media_type: Option<String> = request.headers.content_type().map(|t| {
+      t.split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+    });
+    if media_type.is_some_and(|t| t != "application/json") {
+      return Err(OrderError::UnsupportedMediaType);
+    }
+
+    serde_json::from_slice(&request.message_body)
+      .map_err(|e| OrderError::Invalid(vec![format!("invalid order: {}", e)]))
+  }
+
+  /// Parses the ID of an order given in the path.
+  ///
+  /// # Arguments
+  ///
+  /// * `order_id`: ID of the order, as given in the path.
+  fn parse_order_id(order_id: &str) -> Result<i32, OrderError> {
+    order_id.parse().map_err(|_| {
+      OrderError::Invalid(vec![format!(
+        "order ID must be a number, not '{}'",
+        order_id
+      )])
+    })
+  }
+
+  /// Checks the fields of an order about to be stored.
+  ///
+  /// # Arguments
+  ///
+  /// * `order`: Shipping order to check.
+  fn check(order: &OrderStatus) -> Result<(), OrderError> {
+    let problems: Vec<String> = order.validate();
+    if !problems.is_empty() {
+      return Err(OrderError::Invalid(problems));
+    }
+    Ok(())
   }
 }
 
 impl Handler for WebServiceHandler {
   fn handle(request: &HttpRequest) -> HttpResponse {
-    let Resource::Path(p) = &request.resource;
+    let store: OrderStore = OrderStore::new(Self::orders_path());
 
-    let route: Vec<&str> = p.split("/").collect();
+    let response: Result<HttpResponse, OrderError> =
+      match (&request.method, request.path_param("order_id")) {
+        // Match the path '/api/shipping/orders'
+        (Method::POST, None) => Self::create_order(&store, request),
+        (_, None) => store
+          .load()
+          .map(|orders| Self::orders_response(orders, &request.resource.query))
+          .map_err(OrderError::from),
+        // Match the path '/api/shipping/orders/{order_id}'
+        (Method::PUT, Some(order_id)) => {
+          Self::update_order(&store, request, order_id, true)
+        }
+        (Method::PATCH, Some(order_id)) => {
+          Self::update_order(&store, request, order_id, false)
+        }
+        (Method::DELETE, Some(order_id)) => Self::delete_order(&store, order_id),
+        (_, Some(order_id)) => store
+          .load()
+          .map(|orders| Self::order_response(orders, order_id))
+          .map_err(OrderError::from),
+      };
 
-    match route[2] {
-      // Match the path '/api/shipping/orders'
-      "shipping" if (route.len() > 2 && route[3] == "orders") => {
-        let body = serde_json::to_string(&Self::load_json()).unwrap();
-        let mut headers: HashMap<&str, &str> = HashMap::new();
-        headers.insert("Content-Type", "application/json;charset=UTF-8");
-        HttpResponse::new("200", Some(headers), Some(body))
-      }
-      _ => HttpResponse::new("404", None, Self::load_file("404.html")),
-    }
+    response.unwrap_or_else(OrderError::response)
   } // end fn handle()
 }
 
+/// Creates a response with a JSON body.
+///
+/// # Arguments
+///
+/// * `status_code`: Status of the response.
+/// * `value`: Value sent as JSON.
+fn json_response(
+  status_code: StatusCode,
+  value: &impl Serialize,
+) -> HttpResponse {
+  let body: String = serde_json::to_string(value).unwrap();
+  let mut headers: HeaderMap = HeaderMap::new();
+  headers.insert("Content-Type", "application/json;charset=UTF-8");
+
+  HttpResponse::new(status_code, Some(headers), Some(body))
+}
+
+/// Gets the current date as written in orders, e.g. `21 Jan 2020`.
+fn today() -> String {
+  let now: DateTime = DateTime::now();
+
+  format!(
+    "{} {} {}",
+    now.day,
+    MONTHS[now.month as usize - 1],
+    now.year
+  )
+}
+
+/// Checks whether a text is an existing date as written in orders, e.g. `21 Jan 2020`.
+///
+/// # Arguments
+///
+/// * `text`: Text to check.
+fn is_order_date(text: &str) -> bool {
+  let parts: Vec<&str> = text.split(' ').collect();
+  let [day, month, year] = parts.as_slice() else {
+    return false;
+  };
+  if year.len() != 4 || !year.bytes().all(|b| b.is_ascii_digit()) {
+    return false;
+  }
+  let (Ok(day), Some(month), Ok(year)) = (
+    day.parse::<u32>(),
+    MONTHS.iter().position(|m| m == month),
+    year.parse::<i64>(),
+  ) else {
+    return false;
+  };
+
+  // Days past the end of the month would roll over to the next one
+  let date: DateTime = DateTime {
+    year,
+    month: month as u32 + 1,
+    day,
+    hour: 0,
+    minute: 0,
+    second: 0,
+  };
+  day >= 1 && DateTime::from_unix(date.to_unix()) == date
+}
+
+/// Represents a handler to stream the changes of the shipping orders as server-sent
+/// events. The server sends every stream from a thread of its own, up to
+/// `max_event_streams` at once, so the workers stay free for other requests.
+pub struct OrderEventsHandler;
+
+impl Handler for OrderEventsHandler {
+  fn handle(request: &HttpRequest) -> HttpResponse {
+    // HTTP/1.0 clients cannot receive a body of unknown length before it ends
+    if request.version == Version::V1_0 {
+      return HttpResponse::new(
+        StatusCode::HTTP_VERSION_NOT_SUPPORTED,
+        None,
+        Body::Empty,
+      );
+    }
+
+    let stream: EventStream =
+      EventStream::new(order_events::order_events(), sse::last_event_id(request));
+    sse::response(stream)
+  }
+}
+
 /// Represents a handler to serve static web pages.
 pub struct StaticPageHandler;
 
+impl StaticPageHandler {
+  /// Serves a file of the public directory, or "304 Not Modified" if the copy the
+  /// client holds is still valid. GET requests with a `Range` header get only the
+  /// requested parts of the file, and clients accepting gzip get the precompressed
+  /// `.gz` sibling of the file if there is one.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request to handle.
+  /// * `path`: Canonical path of the file.
+  fn serve_file(
+    request: &HttpRequest,
+    path: &Path,
+  ) -> HttpResponse {
+    let content_type: String = static_files::mime_type(path);
+    let precompressed: Option<PathBuf> =
+      static_files::precompressed(&public_root(), path);
+    let accept: AcceptEncoding = AcceptEncoding::from_headers(&request.headers);
+    let gzip: bool = precompressed.is_some()
+      && accept.preferred(&[ContentCoding::Gzip, ContentCoding::Identity])
+        == Some(ContentCoding::Gzip);
+    let path: &Path = match &precompressed {
+      Some(precompressed) if gzip => precompressed,
+      _ => path,
+    };
+
+    let Ok(metadata) = fs::metadata(path) else {
+      return HttpResponse::new(StatusCode::NOT_FOUND, None, Self::load_file("404.html"));
+    };
+    let validators: Validators = Validators::from_metadata(&metadata);
+
+    let mut headers: HeaderMap = HeaderMap::new();
+    headers.insert("ETag", &validators.etag);
+    headers.insert("Last-Modified", &validators.last_modified.to_http_date());
+    headers.insert(
+      "Cache-Control",
+      &static_files::cache_control(&request.resource.path),
+    );
+    if precompressed.is_some() || encoding::is_compressible(&content_type) {
+      encoding::add_vary(&mut headers, "Accept-Encoding");
+    }
+    if gzip {
+      headers.insert("Content-Encoding", ContentCoding::Gzip.as_str());
+    }
+
+    if validators.not_modified(&request.headers) {
+      return HttpResponse::new(StatusCode::NOT_MODIFIED, Some(headers), Body::Empty);
+    }
+
+    let range: Option<&str> = request.headers.get("Range");
+    if let (Method::GET, Some(range)) = (&request.method, range) {
+      if validators.range_applies(&request.headers) {
+        match range::parse(range, metadata.len()) {
+          RangeRequest::Satisfiable(ranges) => {
+            return range::partial_response(
+              path,
+              &content_type,
+              metadata.len(),
+              &ranges,
+              headers,
+            )
+            .unwrap_or_else(|_| {
+              HttpResponse::new(StatusCode::NOT_FOUND, None, Self::load_file("404.html"))
+            });
+          }
+          RangeRequest::Unsatisfiable => {
+            return range::unsatisfiable_response(metadata.len(), headers);
+          }
+          RangeRequest::Ignored => {}
+        }
+      }
+    }
+
+    match file_cache::load(path, &metadata) {
+      Ok(contents) => {
+        headers.insert("Content-Type", &content_type);
+        headers.insert("Accept-Ranges", "bytes");
+        HttpResponse::new(StatusCode::OK, Some(headers), contents)
+      }
+      Err(_) => {
+        HttpResponse::new(StatusCode::NOT_FOUND, None, Self::load_file("404.html"))
+      }
+    }
+  }
+}
+
 impl Handler for StaticPageHandler {
   fn handle(request: &HttpRequest) -> HttpResponse {
     // Obtain the path of the static page resource
-    let Resource::Path(p) = &request.resource;
-    let route: Vec<&str> = p.split("/").collect();
-
-    match route[1] {
-      // Serve the home page (index.html)
-      "" => HttpResponse::new("200", None, Self::load_file("index.html")),
+    let route: Vec<&str> = match request.resource.segments().as_slice() {
       // Serve the health page (health.html)
-      "health" => HttpResponse::new("200", None, Self::load_file("health.html")),
-      // Serve any other page if the file exists
-      path => match Self::load_file(path) {
-        Some(contents) => {
-          let mut headers: HashMap<&str, &str> = HashMap::new();
-
-          // Set a header according to the file extension
-          match Path::new(path).extension().unwrap().to_str() {
-            Some("css") => headers.insert("Content-Type", "text/css"),
-            Some("js") => headers.insert("Content-Type", "text/javascript"),
-            None | _ => headers.insert("Content-Type", "text/html"),
-          };
-
-          HttpResponse::new("200", Some(headers), Some(contents))
-        } // end some(contents) for an existing file
-        // The requested page does not have a correspoding file, so respond with "Not Found"
-        None => HttpResponse::new("404", None, Self::load_file("404.html")),
-      },
-    } // end match route[]
+      ["health"] => vec!["health.html"],
+      route => route.to_vec(),
+    };
+
+    // Serve the page if the file exists inside the public directory
+    match static_files::resolve(&public_root(), &route) {
+      Ok(path) => Self::serve_file(request, &path),
+      // The requested page does not have a correspoding file, so respond with "Not Found"
+      Err(ResolveError::NotFound) => {
+        HttpResponse::new(StatusCode::NOT_FOUND, None, Self::load_file("404.html"))
+      }
+      // The requested path leads outside the public directory
+      Err(ResolveError::Forbidden) => {
+        HttpResponse::new(StatusCode::FORBIDDEN, None, Body::Empty)
+      }
+    }
   } // end fn handle()
 }
 
@@ -120,6 +654,197 @@ pub struct PageNotFoundHandler;
 
 impl Handler for PageNotFoundHandler {
   fn handle(_request: &HttpRequest) -> HttpResponse {
-    HttpResponse::new("404", None, Self::load_file("404.html"))
+    HttpResponse::new(StatusCode::NOT_FOUND, None, Self::load_file("404.html"))
+  }
+}
+
+/// Represents a handler for WebSocket connections.
+pub trait WebSocketHandler {
+  /// Serves a connection once the opening handshake is done, until it closes.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request upgraded to the connection.
+  /// * `socket`: WebSocket connection with the client.
+  fn serve(
+    request: &HttpRequest,
+    socket: &mut WebSocket<&mut dyn Stream>,
+  );
+}
+
+/// Represents a handler sending every message back to the client.
+pub struct EchoHandler;
+
+impl WebSocketHandler for EchoHandler {
+  fn serve(
+    _request: &HttpRequest,
+    socket: &mut WebSocket<&mut dyn Stream>,
+  ) {
+    loop {
+      let echoed: Message = match socket.read_message() {
+        Ok(Message::Text(text)) => Message::Text(text),
+        Ok(Message::Binary(data)) => Message::Binary(data),
+        Ok(_) | Err(_) => break,
+      };
+      if socket.send(echoed).is_err() {
+        break;
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::env;
+
+  /// Creates a request with a JSON body.
+  fn request(
+    method: &str,
+    target: &str,
+    body: &str,
+  ) -> HttpRequest {
+    HttpRequest::from(format!(
+      "{} {} HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+      method,
+      target,
+      body.len(),
+      body
+    ))
+  }
+
+  /// Gets the body of a response as text.
+  fn body(response: HttpResponse) -> String {
+    let mut response: HttpResponse = response;
+    String::from_utf8(response.take_body().into_bytes().unwrap()).unwrap()
+  }
+
+  #[test]
+  fn test_order_validation() {
+    assert!(is_order_date("21 Jan 2020"));
+    assert!(is_order_date("29 Feb 2024"));
+    assert!(!is_order_date("29 Feb 2023"));
+    assert!(!is_order_date("0 Jan 2020"));
+    assert!(!is_order_date("21 January 2020"));
+    assert!(!is_order_date("21 Jan +020"));
+    assert!(!is_order_date("2020-01-21"));
+    assert!(is_order_date(&today()));
+
+    let order = OrderStatus {
+      order_id: 0,
+      order_date: "31 Apr 2020".to_string(),
+      order_status: "Lost".to_string(),
+    };
+    let problems: Vec<String> = order.validate();
+    assert_eq!(problems.len(), 3);
+    assert!(problems[2].starts_with("order_status must be one of Pending, Shipped"));
+  }
+
+  #[test]
+  fn test_order_changes() {
+    let dir: PathBuf =
+      env::temp_dir().join(format!("http_server_handler_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path: PathBuf = dir.join("orders.json");
+    fs::write(
+      &path,
+      "[{\"order_id\":1,\"order_date\":\"21 Jan 2020\",\"order_status\":\"Pending\"}]",
+    )
+    .unwrap();
+    let store = OrderStore::new(&path);
+
+    // Create
+    let created = WebServiceHandler::create_order(
+      &store,
+      &request(
+        "POST",
+        "/api/shipping/orders",
+        "{\"order_status\":\"Pending\"}",
+      ),
+    )
+    .unwrap();
+    assert_eq!(created.status_code(), &StatusCode::CREATED);
+    assert_eq!(
+      created.headers().get("Location"),
+      Some("/api/shipping/orders/2")
+    );
+    let conflict = WebServiceHandler::create_order(
+      &store,
+      &request(
+        "POST",
+        "/api/shipping/orders",
+        "{\"order_id\":1,\"order_status\":\"Pending\"}",
+      ),
+    );
+    assert!(matches!(conflict, Err(OrderError::Conflict(1))));
+
+    // Update
+    let patched = WebServiceHandler::update_order(
+      &store,
+      &request("PATCH", "/", "{\"order_status\":\"Shipped\"}"),
+      "2",
+      false,
+    )
+    .unwrap();
+    assert!(body(patched).contains("\"order_status\":\"Shipped\""));
+    let incomplete = WebServiceHandler::update_order(
+      &store,
+      &request("PUT", "/", "{\"order_status\":\"Shipped\"}"),
+      "2",
+      true,
+    );
+    assert!(matches!(incomplete, Err(OrderError::Invalid(_))));
+    let missing = WebServiceHandler::update_order(
+      &store,
+      &request("PATCH", "/", "{\"order_status\":\"Shipped\"}"),
+      "9",
+      false,
+    );
+    assert!(matches!(missing, Err(OrderError::NotFound)));
+
+    // Invalid bodies
+    let response = WebServiceHandler::create_order(
+      &store,
+      &request("POST", "/", "{\"order_status\":\"Lost\",\"color\":\"red\"}"),
+    )
+    .unwrap_err()
+    .response();
+    assert_eq!(response.status_code(), &StatusCode::BAD_REQUEST);
+    assert!(body(response).starts_with("{\"errors\":[\"invalid order: unknown field"));
+    let mut form = request("POST", "/", "order_status=Pending");
+    form
+      .headers
+      .insert("Content-Type", "application/x-www-form-urlencoded");
+    assert!(matches!(
+      WebServiceHandler::create_order(&store, &form),
+      Err(OrderError::UnsupportedMediaType)
+    ));
+
+    // IDs after the highest possible one wrap around to the free ones
+    let order = |order_id: i32| OrderStatus {
+      order_id,
+      order_date: "1 Mar 2020".to_string(),
+      order_status: "Pending".to_string(),
+    };
+    assert_eq!(
+      WebServiceHandler::next_order_id(&[order(1), order(i32::MAX)]),
+      Some(2)
+    );
+    assert_eq!(WebServiceHandler::next_order_id(&[order(7)]), Some(8));
+    assert_eq!(WebServiceHandler::next_order_id(&[]), Some(1));
+
+    // Delete
+    let deleted = WebServiceHandler::delete_order(&store, "1").unwrap();
+    assert_eq!(deleted.status_code(), &StatusCode::NO_CONTENT);
+    assert!(matches!(
+      WebServiceHandler::delete_order(&store, "1"),
+      Err(OrderError::NotFound)
+    ));
+
+    let orders: Vec<OrderStatus> = OrderStore::new(&path).load().unwrap();
+    assert_eq!(orders.len(), 1);
+    assert_eq!(orders[0].order_id, 2);
+    assert_eq!(orders[0].order_status, "Shipped");
+    fs::remove_dir_all(&dir).unwrap();
   }
 }
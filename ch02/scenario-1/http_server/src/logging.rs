@@ -0,0 +1,125 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use serde::Deserialize;
+
+/// Level of the messages printed by the server, set once at startup.
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+/// Represents the verbosity of the server messages, from the least to the most verbose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+  /// No messages.
+  Off,
+  /// Failures of the server itself.
+  Error,
+  /// Invalid requests and failed connections.
+  Warn,
+  /// Startup, shutdown and one access log line per request.
+  Info,
+  /// Every connection.
+  Debug,
+}
+
+impl LogLevel {
+  /// Gets the name of the level, as written in the configuration.
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      LogLevel::Off => "off",
+      LogLevel::Error => "error",
+      LogLevel::Warn => "warn",
+      LogLevel::Info => "info",
+      LogLevel::Debug => "debug",
+    }
+  }
+}
+
+impl FromStr for LogLevel {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.to_ascii_lowercase().as_str() {
+      "off" => Ok(LogLevel::Off),
+      "error" => Ok(LogLevel::Error),
+      "warn" => Ok(LogLevel::Warn),
+      "info" => Ok(LogLevel::Info),
+      "debug" => Ok(LogLevel::Debug),
+      _ => Err(format!("invalid log level: {}", s)),
+    }
+  }
+}
+
+impl fmt::Display for LogLevel {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    write!(f, "{}", self.as_str())
+  }
+}
+
+/// Sets the level of the messages printed from now on.
+///
+/// # Arguments
+///
+/// * `level`: Most verbose level printed.
+pub fn set_level(level: LogLevel) {
+  LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Checks whether messages of the given level are printed.
+///
+/// # Arguments
+///
+/// * `level`: Level of the message.
+pub fn enabled(level: LogLevel) -> bool {
+  level != LogLevel::Off && level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+/// Prints a message with the given level, if enabled.
+macro_rules! log {
+  ($level:expr, $($arg:tt)+) => {
+    if $crate::logging::enabled($level) {
+      println!($($arg)+);
+    }
+  };
+}
+
+/// Prints a message about a failure of the server itself.
+macro_rules! error {
+  ($($arg:tt)+) => { log!($crate::logging::LogLevel::Error, $($arg)+) };
+}
+
+/// Prints a message about an invalid request or a failed connection.
+macro_rules! warn {
+  ($($arg:tt)+) => { log!($crate::logging::LogLevel::Warn, $($arg)+) };
+}
+
+/// Prints a message about the normal operation of the server.
+macro_rules! info {
+  ($($arg:tt)+) => { log!($crate::logging::LogLevel::Info, $($arg)+) };
+}
+
+/// Prints a detailed message, only useful while debugging.
+macro_rules! debug {
+  ($($arg:tt)+) => { log!($crate::logging::LogLevel::Debug, $($arg)+) };
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_log_levels() {
+    assert_eq!("WARN".parse::<LogLevel>(), Ok(LogLevel::Warn));
+    assert!("verbose".parse::<LogLevel>().is_err());
+    assert!(LogLevel::Error < LogLevel::Debug);
+
+    // The default level prints everything but debug messages
+    assert!(enabled(LogLevel::Info));
+    assert!(!enabled(LogLevel::Debug));
+    assert!(!enabled(LogLevel::Off));
+  }
+}
@@ -1,11 +1,118 @@
+#[macro_use]
+mod logging;
+
+mod asynchronous;
+mod config;
+mod file_cache;
 mod handler;
+mod middleware;
+mod order_events;
+mod order_store;
+mod proxy;
+mod range;
+mod reader;
 mod router;
 mod server;
+mod static_files;
+mod thread_pool;
+#[cfg(feature = "tls")]
+mod tls;
+mod virtual_host;
+
+use std::{env, process};
 
+use config::{Config, Mode, Options};
+use middleware::Chain;
+use proxy::ProxyHandler;
 use server::Server;
+use static_files::CachePolicy;
 
 fn main() {
+  let options: Options = match Options::parse(env::args().skip(1)) {
+    Ok(options) => options,
+    Err(e) => {
+      eprintln!("http_server: {}\n\n{}", e, config::USAGE);
+      process::exit(2);
+    }
+  };
+  if options.help {
+    println!("{}", config::USAGE);
+    return;
+  }
+
+  // Load and validate the settings before binding anything
+  let config: Config = match Config::load(&options).and_then(|c| c.validate().map(|_| c))
+  {
+    Ok(config) => config,
+    Err(e) => {
+      eprintln!("http_server: {}", e);
+      process::exit(1);
+    }
+  };
+  if options.check_config {
+    println!("Configuration is valid");
+    println!("  bind: {}", config.server.bind.join(", "));
+    println!("  public: {}", config.paths.public.display());
+    println!("  data: {}", config.paths.data.display());
+    for host in &config.hosts {
+      println!("  host: {}", host.names.join(", "));
+    }
+    return;
+  }
+
+  logging::set_level(config.log.level);
+  config::install_paths(config.paths.clone());
+  static_files::install_cache_policy(CachePolicy::parse(&config.cache.control));
+  file_cache::install(config.cache.file_cache_size);
+  let mut middlewares: Chain = middleware::default_chain(config.compression.middleware());
+
+  // Serve every connection on a task instead of a worker thread when requested
+  if config.server.mode == Mode::Async {
+    let server: asynchronous::server::Server =
+      asynchronous::server::Server::new(&config.server.bind[0])
+        .with_limits(config.limits)
+        .with_keep_alive(config.keep_alive())
+        .with_middlewares(middlewares);
+    let runtime: tokio::runtime::Runtime = tokio::runtime::Runtime::new().unwrap();
+    if let Err(e) = runtime.block_on(server.run()) {
+      eprintln!("http_server: {}", e);
+      process::exit(1);
+    }
+    return;
+  }
+
   // Start and then run the server
-  let server: Server = Server::new("localhost:3000");
-  server.run();
+  let mut server: Server = Server::new(&config.server.bind[0])
+    .with_limits(config.limits)
+    .with_keep_alive(config.keep_alive())
+    .with_workers(config.workers())
+    .with_max_websockets(config.server.max_websockets)
+    .with_max_event_streams(config.server.max_event_streams)
+    // Already built once by the validation
+    .with_virtual_hosts(config.virtual_hosts().unwrap());
+  for address in &config.server.bind[1..] {
+    server = server.with_address(address);
+  }
+
+  // Forward the configured prefixes before routing the requests
+  if !config.proxy.is_empty() {
+    middlewares = middlewares.with(ProxyHandler::from_settings(&config.proxy));
+  }
+  server = server.with_middlewares(middlewares);
+
+  // Serve HTTPS when a certificate and its key are given
+  #[cfg(feature = "tls")]
+  if let Some(tls) = &config.tls {
+    // Already loaded once by the validation
+    let tls_config = tls::load_config(&tls.cert, &tls.key).unwrap();
+    server = server.with_tls(tls_config);
+    if let Some(address) = &tls.redirect {
+      server = server.with_https_redirect(address);
+    }
+  }
+
+  if let Err(e) = server.run() {
+    eprintln!("http_server: {}", e);
+    process::exit(1);
+  }
 }
@@ -0,0 +1,623 @@
+use std::net::SocketAddr;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use http::body::Body;
+use http::date::DateTime;
+use http::encoding::{self, AcceptEncoding, ContentCoding};
+use http::header_map::HeaderMap;
+use http::http_request::{HttpRequest, Method};
+use http::http_response::HttpResponse;
+use http::status_code::StatusCode;
+
+/// Maximum length of a request ID received from the client.
+const MAX_REQUEST_ID_LENGTH: usize = 64;
+
+/// Default minimum number of bytes of a body to compress it.
+pub const DEFAULT_COMPRESSION_MIN_SIZE: u64 = 1024;
+
+/// Default maximum number of bytes of a body to compress it.
+pub const DEFAULT_COMPRESSION_MAX_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Represents the data about a request shared by the middlewares.
+#[derive(Debug, Clone)]
+pub struct Context {
+  /// Address of the client, if known.
+  pub peer_address: Option<SocketAddr>,
+  /// Moment the request was received.
+  pub started: Instant,
+  /// Identifier of the request, once assigned.
+  pub request_id: Option<String>,
+  /// Whether the request was received over TLS.
+  pub secure: bool,
+}
+
+impl Context {
+  /// Creates a new [`Context`] object for a request received now.
+  ///
+  /// # Arguments
+  ///
+  /// * `peer_address`: Address of the client, if known.
+  pub fn new(peer_address: Option<SocketAddr>) -> Self {
+    Self {
+      peer_address,
+      started: Instant::now(),
+      request_id: None,
+      secure: false,
+    }
+  }
+}
+
+/// Function producing the response of the rest of the chain.
+pub type Next<'a> = &'a dyn Fn(&mut HttpRequest, &mut Context) -> HttpResponse;
+
+/// Represents logic that runs around the handling of every request.
+///
+/// Most middlewares only need the `before` and `after` hooks. Those needing to control
+/// how the rest of the chain runs override `around` instead.
+pub trait Middleware: Send + Sync {
+  /// Runs before the request is handled. Returning a response skips the rest of the
+  /// chain, including the `after` hook of this middleware.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request received, which may be rewritten.
+  /// * `context`: Data about the request.
+  fn before(
+    &self,
+    _request: &mut HttpRequest,
+    _context: &mut Context,
+  ) -> Option<HttpResponse> {
+    None
+  }
+
+  /// Runs after the response is produced, which may be rewritten.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request handled.
+  /// * `response`: HTTP response to send.
+  /// * `context`: Data about the request.
+  fn after(
+    &self,
+    _request: &HttpRequest,
+    _response: &mut HttpResponse,
+    _context: &mut Context,
+  ) {
+  }
+
+  /// Produces the response by running the `before` hook, the rest of the chain and then
+  /// the `after` hook.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request received.
+  /// * `context`: Data about the request.
+  /// * `next`: Rest of the chain.
+  fn around(
+    &self,
+    request: &mut HttpRequest,
+    context: &mut Context,
+    next: Next<'_>,
+  ) -> HttpResponse {
+    if let Some(response) = self.before(request, context) {
+      return response;
+    }
+    let mut response: HttpResponse = next(request, context);
+    self.after(request, &mut response, context);
+    response
+  }
+}
+
+/// Represents a sequence of middlewares. The first one added is the outermost, so it
+/// sees the request first and the response last.
+#[derive(Default)]
+pub struct Chain {
+  /// Middlewares, from the outermost to the innermost.
+  middlewares: Vec<Box<dyn Middleware>>,
+}
+
+impl Chain {
+  /// Creates a [`Chain`] object without middlewares.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Adds a middleware inside the ones already added.
+  ///
+  /// # Arguments
+  ///
+  /// * `middleware`: Middleware to add.
+  pub fn with(
+    mut self,
+    middleware: impl Middleware + 'static,
+  ) -> Self {
+    self.middlewares.push(Box::new(middleware));
+    self
+  }
+
+  /// Runs the middlewares around the given endpoint and returns the response.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request received.
+  /// * `context`: Data about the request.
+  /// * `endpoint`: Function producing the response at the end of the chain.
+  pub fn handle(
+    &self,
+    request: &mut HttpRequest,
+    context: &mut Context,
+    endpoint: &dyn Fn(&mut HttpRequest) -> HttpResponse,
+  ) -> HttpResponse {
+    self.run(0, request, context, endpoint)
+  }
+
+  /// Runs the middlewares from the given position onwards.
+  fn run(
+    &self,
+    index: usize,
+    request: &mut HttpRequest,
+    context: &mut Context,
+    endpoint: &dyn Fn(&mut HttpRequest) -> HttpResponse,
+  ) -> HttpResponse {
+    match self.middlewares.get(index) {
+      Some(middleware) => middleware.around(request, context, &|request, context| {
+        self.run(index + 1, request, context, endpoint)
+      }),
+      None => endpoint(request),
+    }
+  }
+}
+
+/// Middleware that prints every request in the Common Log Format.
+pub struct AccessLog;
+
+impl AccessLog {
+  /// Formats the log line of a request.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request handled.
+  /// * `response`: HTTP response to send.
+  /// * `context`: Data about the request.
+  /// * `date`: Date the request was received.
+  pub fn format(
+    request: &HttpRequest,
+    response: &HttpResponse,
+    context: &Context,
+    date: DateTime,
+  ) -> String {
+    let host: String = context
+      .peer_address
+      .map_or("-".to_string(), |address| address.ip().to_string());
+    let bytes: String = match response.body().len() {
+      Some(0) | None => "-".to_string(),
+      Some(length) => length.to_string(),
+    };
+
+    format!(
+      "{} - - [{}] \"{} {} {}\" {} {}",
+      host,
+      date.to_clf(),
+      request.method.as_str(),
+      request.resource.target,
+      request.version.as_str(),
+      response.status_code().as_u16(),
+      bytes
+    )
+  }
+}
+
+impl Middleware for AccessLog {
+  fn after(
+    &self,
+    request: &HttpRequest,
+    response: &mut HttpResponse,
+    context: &mut Context,
+  ) {
+    info!(
+      "{}",
+      Self::format(request, response, context, DateTime::now())
+    );
+  }
+}
+
+/// Middleware that identifies every request with the `X-Request-Id` header. A valid ID
+/// sent by the client is kept; otherwise a new one is generated.
+pub struct RequestId {
+  /// Prefix making the IDs of this process unique.
+  prefix: u64,
+  /// Number of IDs generated.
+  counter: AtomicU64,
+}
+
+impl RequestId {
+  /// Creates a new [`RequestId`] object.
+  pub fn new() -> Self {
+    let nanos: u128 = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map_or(0, |elapsed| elapsed.as_nanos());
+
+    Self {
+      prefix: nanos as u64 ^ u64::from(std::process::id()),
+      counter: AtomicU64::new(0),
+    }
+  }
+
+  /// Checks whether a request ID received from the client can be reused.
+  ///
+  /// # Arguments
+  ///
+  /// * `id`: Request ID received.
+  fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+      && id.len() <= MAX_REQUEST_ID_LENGTH
+      && id
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b"-_.".contains(&b))
+  }
+}
+
+impl Default for RequestId {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Middleware for RequestId {
+  fn before(
+    &self,
+    request: &mut HttpRequest,
+    context: &mut Context,
+  ) -> Option<HttpResponse> {
+    let id: String = match request.headers.get("X-Request-Id") {
+      Some(id) if Self::is_valid(id) => id.to_string(),
+      _ => format!(
+        "{:016x}-{:08x}",
+        self.prefix,
+        self.counter.fetch_add(1, Ordering::Relaxed)
+      ),
+    };
+
+    request.headers.insert("X-Request-Id", &id);
+    context.request_id = Some(id);
+    None
+  }
+
+  fn after(
+    &self,
+    _request: &HttpRequest,
+    response: &mut HttpResponse,
+    context: &mut Context,
+  ) {
+    if let Some(id) = &context.request_id {
+      response.headers_mut().insert("X-Request-Id", id);
+    }
+  }
+}
+
+/// Middleware that adds the `X-Response-Time` header with the milliseconds taken to
+/// produce the response.
+pub struct Timing;
+
+impl Middleware for Timing {
+  fn after(
+    &self,
+    _request: &HttpRequest,
+    response: &mut HttpResponse,
+    context: &mut Context,
+  ) {
+    let elapsed: f64 = context.started.elapsed().as_secs_f64() * 1000.0;
+    response
+      .headers_mut()
+      .insert("X-Response-Time", &format!("{:.3}ms", elapsed));
+  }
+}
+
+/// Middleware that compresses the bodies of responses with the coding the client
+/// prefers, when their media type is compressible and they are large enough. Bodies
+/// are compressed in memory, so larger ones are sent as they are.
+pub struct Compression {
+  /// Minimum number of bytes of a body to compress it.
+  min_size: u64,
+  /// Maximum number of bytes of a body to compress it.
+  max_size: u64,
+}
+
+impl Compression {
+  /// Codings applied, from the most to the least preferred.
+  const CODINGS: [ContentCoding; 4] = [
+    ContentCoding::Brotli,
+    ContentCoding::Gzip,
+    ContentCoding::Deflate,
+    ContentCoding::Identity,
+  ];
+
+  /// Creates a new [`Compression`] object.
+  ///
+  /// # Arguments
+  ///
+  /// * `min_size`: Minimum number of bytes of a body to compress it.
+  pub fn new(min_size: u64) -> Self {
+    Self {
+      min_size,
+      max_size: DEFAULT_COMPRESSION_MAX_SIZE,
+    }
+  }
+
+  /// Sets the maximum size of a body to compress it, 8 MiB by default.
+  ///
+  /// # Arguments
+  ///
+  /// * `max_size`: Maximum number of bytes of a body to compress it.
+  pub fn with_max_size(
+    mut self,
+    max_size: u64,
+  ) -> Self {
+    self.max_size = max_size;
+    self
+  }
+}
+
+impl Default for Compression {
+  fn default() -> Self {
+    Self::new(DEFAULT_COMPRESSION_MIN_SIZE)
+  }
+}
+
+impl Middleware for Compression {
+  fn after(
+    &self,
+    request: &HttpRequest,
+    response: &mut HttpResponse,
+    _context: &mut Context,
+  ) {
+    let compressible: bool = response
+      .headers()
+      .content_type()
+      .is_some_and(encoding::is_compressible);
+    // Partial and already encoded responses are left alone, and so are streamed ones,
+    // e.g. proxied, whose length may only describe what a GET request would receive
+    if !compressible
+      || response.status_code() != &StatusCode::OK
+      || response.headers().contains("Content-Encoding")
+      || matches!(response.body(), Body::Stream { .. })
+    {
+      return;
+    }
+    encoding::add_vary(response.headers_mut(), "Accept-Encoding");
+
+    // Answers to HEAD requests have no body to compress, and large files stay streamed
+    let size: std::ops::RangeInclusive<u64> = self.min_size..=self.max_size;
+    if request.method == Method::HEAD
+      || !matches!(response.body().len(), Some(length) if size.contains(&length))
+    {
+      return;
+    }
+    let coding: ContentCoding =
+      match AcceptEncoding::from_headers(&request.headers).preferred(&Self::CODINGS) {
+        Some(ContentCoding::Identity) | None => return,
+        Some(coding) => coding,
+      };
+
+    let contents: Vec<u8> = match response.take_body().into_bytes() {
+      Ok(contents) => contents,
+      Err(e) => {
+        error!("Failed to read a body to compress it: {}", e);
+        *response =
+          HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR, None, Body::Empty);
+        return;
+      }
+    };
+    match coding.encode(&contents) {
+      Ok(encoded) if encoded.len() < contents.len() => {
+        let headers: &mut HeaderMap = response.headers_mut();
+        headers.insert("Content-Encoding", coding.as_str());
+        // Ranges refer to the unencoded body, and the encoded one is only equivalent
+        headers.remove("Accept-Ranges");
+        if let Some(etag) = headers.get("ETag").filter(|e| !e.starts_with("W/")) {
+          let weak: String = format!("W/{}", etag);
+          headers.insert("ETag", &weak);
+        }
+        response.set_body(encoded);
+      }
+      _ => response.set_body(contents),
+    }
+  }
+}
+
+/// Middleware that turns a panic in the rest of the chain into a "500 Internal Server
+/// Error" response, so the worker thread keeps serving.
+pub struct CatchPanic;
+
+impl Middleware for CatchPanic {
+  fn around(
+    &self,
+    request: &mut HttpRequest,
+    context: &mut Context,
+    next: Next<'_>,
+  ) -> HttpResponse {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| next(request, context)));
+
+    result.unwrap_or_else(|_| {
+      error!("Handler panicked while serving {}", request.resource.target);
+      let mut response: HttpResponse =
+        HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR, None, Body::Empty);
+      // The state of the connection is unknown after a panic
+      response.headers_mut().insert("Connection", "close");
+      response
+    })
+  }
+}
+
+/// Creates the chain of middlewares of the application.
+///
+/// # Arguments
+///
+/// * `compression`: Middleware compressing the bodies of the responses.
+pub fn default_chain(compression: Compression) -> Chain {
+  Chain::new()
+    .with(AccessLog)
+    .with(RequestId::new())
+    .with(Timing)
+    .with(compression)
+    .with(CatchPanic)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn request(text: &str) -> HttpRequest {
+    HttpRequest::parse(text.as_bytes()).unwrap().0
+  }
+
+  /// Middleware that rejects requests without a `Token` header.
+  struct RequireToken;
+
+  impl Middleware for RequireToken {
+    fn before(
+      &self,
+      request: &mut HttpRequest,
+      _context: &mut Context,
+    ) -> Option<HttpResponse> {
+      (!request.headers.contains("Token"))
+        .then(|| HttpResponse::new(StatusCode::UNAUTHORIZED, None, Body::Empty))
+    }
+  }
+
+  #[test]
+  fn test_short_circuit_skips_inner_middlewares() {
+    let chain = Chain::new()
+      .with(RequestId::new())
+      .with(RequireToken)
+      .with(Timing);
+    let endpoint = |_: &mut HttpRequest| HttpResponse::new(StatusCode::OK, None, "ok");
+
+    let mut context = Context::new(None);
+    let response = chain.handle(
+      &mut request("GET / HTTP/1.1\r\n\r\n"),
+      &mut context,
+      &endpoint,
+    );
+    assert_eq!(response.status_code(), &StatusCode::UNAUTHORIZED);
+    assert!(response.header("X-Request-Id").is_some());
+    assert!(response.header("X-Response-Time").is_none());
+
+    let mut context = Context::new(None);
+    let mut req = request("GET / HTTP/1.1\r\nToken: 1\r\nX-Request-Id: abc-1\r\n\r\n");
+    let response = chain.handle(&mut req, &mut context, &endpoint);
+    assert_eq!(response.status_code(), &StatusCode::OK);
+    assert_eq!(response.header("X-Request-Id"), Some("abc-1"));
+    assert!(response.header("X-Response-Time").unwrap().ends_with("ms"));
+  }
+
+  #[test]
+  fn test_request_ids_are_unique() {
+    let chain = Chain::new().with(RequestId::new());
+    let endpoint =
+      |_: &mut HttpRequest| HttpResponse::new(StatusCode::OK, None, Body::Empty);
+    let mut ids: Vec<String> = Vec::new();
+
+    for _ in 0..3 {
+      // An invalid ID from the client is replaced
+      let mut req = request("GET / HTTP/1.1\r\nX-Request-Id: a b\r\n\r\n");
+      let response = chain.handle(&mut req, &mut Context::new(None), &endpoint);
+      ids.push(response.header("X-Request-Id").unwrap().to_string());
+    }
+
+    ids.dedup();
+    assert_eq!(ids.len(), 3);
+  }
+
+  #[test]
+  fn test_panic_becomes_internal_server_error() {
+    let chain = default_chain(Compression::default());
+    let endpoint = |_: &mut HttpRequest| -> HttpResponse { panic!("handler failed") };
+
+    let mut req = request("GET / HTTP/1.1\r\n\r\n");
+    let response = chain.handle(&mut req, &mut Context::new(None), &endpoint);
+    assert_eq!(response.status_code(), &StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(response.header("X-Response-Time").is_some());
+  }
+
+  #[test]
+  fn test_compression() {
+    let chain = Chain::new().with(Compression::new(16).with_max_size(1000));
+    let endpoint = |req: &mut HttpRequest| {
+      let body: Body = match req.resource.path.as_str() {
+        "/small" => Body::from("tiny"),
+        "/large" => Body::from("compress me ".repeat(100)),
+        // A directory cannot be read as a file
+        "/unreadable" => Body::File {
+          file: std::fs::File::open(std::env::temp_dir()).unwrap(),
+          length: 240,
+        },
+        // Body of a proxied response to a HEAD request
+        "/stream" => Body::from_reader(std::io::empty(), Some(240)),
+        _ => Body::from("compress me ".repeat(20)),
+      };
+      let mut headers = HeaderMap::from([
+        ("Content-Type", "text/plain;charset=UTF-8"),
+        ("ETag", "\"1-2\""),
+        ("Accept-Ranges", "bytes"),
+      ]);
+      if req.resource.path == "/png" {
+        headers.insert("Content-Type", "image/png");
+      }
+      HttpResponse::new(StatusCode::OK, Some(headers), body)
+    };
+    let handle =
+      |text: &str| chain.handle(&mut request(text), &mut Context::new(None), &endpoint);
+
+    let response = handle("GET / HTTP/1.1\r\nAccept-Encoding: gzip;q=0.5, br\r\n\r\n");
+    assert_eq!(response.header("Content-Encoding"), Some("br"));
+    assert_eq!(response.header("Vary"), Some("Accept-Encoding"));
+    assert_eq!(response.header("ETag"), Some("W/\"1-2\""));
+    assert_eq!(response.header("Accept-Ranges"), None);
+    assert!(response.body().len().unwrap() < 240);
+
+    let response = handle("GET / HTTP/1.1\r\nAccept-Encoding: gzip\r\n\r\n");
+    assert_eq!(response.header("Content-Encoding"), Some("gzip"));
+
+    // Without a usable coding the body is sent as it is, but the response still varies
+    for text in [
+      "GET / HTTP/1.1\r\n\r\n",
+      "GET /small HTTP/1.1\r\nAccept-Encoding: gzip\r\n\r\n",
+      "GET /large HTTP/1.1\r\nAccept-Encoding: gzip\r\n\r\n",
+      "HEAD / HTTP/1.1\r\nAccept-Encoding: gzip\r\n\r\n",
+    ] {
+      let response = handle(text);
+      assert_eq!(response.header("Content-Encoding"), None);
+      assert_eq!(response.header("Vary"), Some("Accept-Encoding"));
+      assert_eq!(response.header("ETag"), Some("\"1-2\""));
+    }
+
+    let response = handle("GET /unreadable HTTP/1.1\r\nAccept-Encoding: gzip\r\n\r\n");
+    assert_eq!(response.status_code(), &StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(response.body().len(), Some(0));
+
+    let response = handle("HEAD /stream HTTP/1.1\r\nAccept-Encoding: gzip\r\n\r\n");
+    assert_eq!(response.header("Content-Encoding"), None);
+    assert_eq!(response.header("Vary"), None);
+    assert_eq!(response.body().len(), Some(240));
+
+    let response = handle("GET /png HTTP/1.1\r\nAccept-Encoding: gzip\r\n\r\n");
+    assert_eq!(response.header("Content-Encoding"), None);
+    assert_eq!(response.header("Vary"), None);
+  }
+
+  #[test]
+  fn test_access_log_format() {
+    let mut req = request("GET /api/shipping/orders?order_id=1 HTTP/1.1\r\n\r\n");
+    req.method = Method::POST;
+    let response = HttpResponse::new(StatusCode::CREATED, None, "12345");
+    let context = Context::new(Some("127.0.0.1:5000".parse().unwrap()));
+
+    assert_eq!(
+      AccessLog::format(&req, &response, &context, DateTime::from_unix(0)),
+      "127.0.0.1 - - [01/Jan/1970:00:00:00 +0000] \"POST /api/shipping/orders?order_id=1 HTTP/1.1\" 201 5"
+    );
+  }
+}
@@ -0,0 +1,426 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use http::sse::{self, Event};
+
+use crate::handler::{OrderStatus, WebServiceHandler};
+use crate::order_store::{file_version, OrderStore};
+
+/// Interval between two checks of the data file.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Interval after which an idle stream gets a comment, so proxies keep it open and
+/// closed clients are noticed.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+/// Time clients wait before reconnecting, in milliseconds.
+const RETRY_MILLISECONDS: u64 = 3000;
+/// Number of events kept to be replayed to reconnecting clients.
+const HISTORY_SIZE: usize = 1000;
+/// Type of the events sent when an order is created or changes.
+pub const ORDER_UPDATED: &str = "order-updated";
+/// Type of the events sent when an order is removed.
+pub const ORDER_DELETED: &str = "order-deleted";
+
+/// Events of the data file of the running server, watched from first use.
+static ORDER_EVENTS: Mutex<Option<Arc<OrderEvents>>> = Mutex::new(None);
+
+/// Represents the events recorded so far and the orders they lead to.
+struct EventLog {
+  /// ID of the last event recorded.
+  last_id: u64,
+  /// Most recent events, oldest first.
+  history: VecDeque<(u64, Event)>,
+  /// Current orders as JSON, by order ID.
+  orders: BTreeMap<i32, String>,
+  /// Whether the streams must end, because the server is shutting down.
+  closed: bool,
+}
+
+impl EventLog {
+  /// Gets the events a client must receive after the one of the given ID: the ones
+  /// that followed it, or every current order when they are no longer known, e.g. the
+  /// ID comes from a previous run of the server.
+  ///
+  /// # Arguments
+  ///
+  /// * `id`: ID of the last event the client received.
+  fn events_after(
+    &self,
+    id: u64,
+  ) -> Vec<Event> {
+    let first_id: u64 = self.history.front().map_or(self.last_id + 1, |(id, _)| *id);
+    if id > self.last_id || id + 1 < first_id {
+      return self
+        .orders
+        .values()
+        .map(|order| {
+          Event::new(order.as_str())
+            .with_event(ORDER_UPDATED)
+            .with_id(self.last_id.to_string())
+        })
+        .collect();
+    }
+
+    self
+      .history
+      .iter()
+      .filter(|(event_id, _)| *event_id > id)
+      .map(|(_, event)| event.clone())
+      .collect()
+  }
+}
+
+/// Represents the changes of the shipping orders, as events for the clients of
+/// `/api/shipping/orders/stream`.
+pub struct OrderEvents {
+  /// Events recorded.
+  log: Mutex<EventLog>,
+  /// Signaled when an event is recorded or the streams are closed.
+  changed: Condvar,
+}
+
+impl OrderEvents {
+  /// Creates a new [`OrderEvents`] object without events.
+  ///
+  /// # Arguments
+  ///
+  /// * `last_id`: ID preceding the one of the first event. Starting from the current
+  ///   time keeps the IDs growing across restarts of the server.
+  /// * `orders`: Current shipping orders.
+  pub fn new(
+    last_id: u64,
+    orders: &[OrderStatus],
+  ) -> Self {
+    Self {
+      log: Mutex::new(EventLog {
+        last_id,
+        history: VecDeque::new(),
+        orders: to_json(orders),
+        closed: false,
+      }),
+      changed: Condvar::new(),
+    }
+  }
+
+  /// Records an event for every order created, changed or removed since the previous
+  /// orders, and wakes up the streams.
+  ///
+  /// # Arguments
+  ///
+  /// * `orders`: Current shipping orders.
+  pub fn update(
+    &self,
+    orders: &[OrderStatus],
+  ) {
+    let orders: BTreeMap<i32, String> = to_json(orders);
+    let mut log = self.log.lock().unwrap();
+
+    let mut events: Vec<Event> = Vec::new();
+    for (order_id, order) in &orders {
+      if log.orders.get(order_id) != Some(order) {
+        events.push(Event::new(order.as_str()).with_event(ORDER_UPDATED));
+      }
+    }
+    for order_id in log.orders.keys().filter(|id| !orders.contains_key(id)) {
+      let data: String = format!("{{\"order_id\":{}}}", order_id);
+      events.push(Event::new(data).with_event(ORDER_DELETED));
+    }
+    if events.is_empty() {
+      return;
+    }
+
+    for event in events {
+      log.last_id += 1;
+      let id: u64 = log.last_id;
+      log.history.push_back((id, event.with_id(id.to_string())));
+    }
+    while log.history.len() > HISTORY_SIZE {
+      log.history.pop_front();
+    }
+    log.orders = orders;
+    self.changed.notify_all();
+  } // end fn update()
+
+  /// Waits until there are events after the one of the given ID and returns them with
+  /// the ID of the last event recorded, or returns an empty list after the timeout.
+  /// Returns `None` once the streams are closed.
+  ///
+  /// # Arguments
+  ///
+  /// * `id`: ID of the last event the client received.
+  /// * `timeout`: Maximum time to wait.
+  pub fn wait(
+    &self,
+    id: u64,
+    timeout: Duration,
+  ) -> Option<(u64, Vec<Event>)> {
+    let log = self.log.lock().unwrap();
+    let (log, _) = self
+      .changed
+      .wait_timeout_while(log, timeout, |log| !log.closed && log.last_id == id)
+      .unwrap();
+
+    if log.closed {
+      return None;
+    }
+    if log.last_id == id {
+      return Some((id, Vec::new()));
+    }
+    Some((log.last_id, log.events_after(id)))
+  }
+
+  /// Ends every stream, now and in the future.
+  pub fn close(&self) {
+    self.log.lock().unwrap().closed = true;
+    self.changed.notify_all();
+  }
+
+  /// Starts a thread that records the changes of a data file until the streams are
+  /// closed. A file that cannot be read or parsed, e.g. while it is being written, is
+  /// checked again later.
+  ///
+  /// # Arguments
+  ///
+  /// * `events`: Events to record the changes into.
+  /// * `path`: Path of the data file.
+  fn watch(
+    events: Arc<OrderEvents>,
+    path: PathBuf,
+  ) {
+    thread::spawn(move || {
+      let store: OrderStore = OrderStore::new(&path);
+      let mut version: Option<(SystemTime, u64)> = file_version(&path);
+      while !events.log.lock().unwrap().closed {
+        thread::sleep(POLL_INTERVAL);
+
+        let current: Option<(SystemTime, u64)> = file_version(&path);
+        if current == version {
+          continue;
+        }
+        if let Ok(orders) = store.load() {
+          events.update(&orders);
+          version = current;
+        }
+      }
+    });
+  }
+}
+
+/// Represents the body of a response streaming the events, waiting for them as it is
+/// read.
+pub struct EventStream {
+  /// Events of the orders.
+  events: Arc<OrderEvents>,
+  /// ID of the last event sent.
+  last_id: u64,
+  /// Encoded data not read yet.
+  pending: Vec<u8>,
+  /// Number of bytes of the pending data already read.
+  position: usize,
+}
+
+impl EventStream {
+  /// Creates a new [`EventStream`] object, starting with the events the client missed.
+  ///
+  /// # Arguments
+  ///
+  /// * `events`: Events of the orders.
+  /// * `last_event_id`: Value of the `Last-Event-ID` header of a reconnecting client.
+  pub fn new(
+    events: Arc<OrderEvents>,
+    last_event_id: Option<&str>,
+  ) -> Self {
+    let mut pending: String = sse::retry(RETRY_MILLISECONDS);
+    let last_id: u64 = {
+      let log = events.log.lock().unwrap();
+      if let Some(last_event_id) = last_event_id {
+        // An ID that is not ours is treated as a forgotten one
+        let id: u64 = last_event_id.parse().unwrap_or(u64::MAX);
+        for event in log.events_after(id) {
+          pending.push_str(&event.encode());
+        }
+      }
+      log.last_id
+    };
+
+    Self {
+      events,
+      last_id,
+      pending: pending.into_bytes(),
+      position: 0,
+    }
+  }
+}
+
+impl Read for EventStream {
+  fn read(
+    &mut self,
+    buf: &mut [u8],
+  ) -> io::Result<usize> {
+    if self.position == self.pending.len() {
+      let mut pending: String = String::new();
+      match self.events.wait(self.last_id, KEEP_ALIVE_INTERVAL) {
+        // The server is shutting down
+        None => return Ok(0),
+        Some((_, events)) if events.is_empty() => {
+          pending.push_str(&sse::comment("keep-alive"))
+        }
+        Some((last_id, events)) => {
+          for event in events {
+            pending.push_str(&event.encode());
+          }
+          self.last_id = last_id;
+        }
+      }
+      self.pending = pending.into_bytes();
+      self.position = 0;
+    }
+
+    let read: usize = buf.len().min(self.pending.len() - self.position);
+    buf[..read].copy_from_slice(&self.pending[self.position..self.position + read]);
+    self.position += read;
+    Ok(read)
+  }
+}
+
+/// Gets the events of the data file of the running server, starting to watch it on
+/// first use.
+pub fn order_events() -> Arc<OrderEvents> {
+  let mut order_events = ORDER_EVENTS.lock().unwrap();
+  if let Some(events) = &*order_events {
+    return Arc::clone(events);
+  }
+
+  let path: PathBuf = WebServiceHandler::orders_path();
+  let now: u64 = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map_or(0, |d| d.as_millis() as u64);
+  let events: Arc<OrderEvents> = Arc::new(OrderEvents::new(
+    now,
+    &OrderStore::new(&path).load().unwrap_or_default(),
+  ));
+  OrderEvents::watch(Arc::clone(&events), path);
+  *order_events = Some(Arc::clone(&events));
+  events
+}
+
+/// Ends the streams of events of the running server, which would otherwise keep their
+/// connections open while the server shuts down.
+pub fn close() {
+  if let Some(events) = &*ORDER_EVENTS.lock().unwrap() {
+    events.close();
+  }
+}
+
+/// Serializes orders by order ID.
+///
+/// # Arguments
+///
+/// * `orders`: Shipping orders.
+fn to_json(orders: &[OrderStatus]) -> BTreeMap<i32, String> {
+  orders
+    .iter()
+    .map(|order| (order.order_id(), serde_json::to_string(order).unwrap()))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Parses orders given as `(order_id, order_status)` pairs.
+  fn orders(list: &[(i32, &str)]) -> Vec<OrderStatus> {
+    let json: Vec<String> = list
+      .iter()
+      .map(|(id, status)| {
+        format!(
+          "{{\"order_id\":{},\"order_date\":\"2023-01-01\",\"order_status\":\"{}\"}}",
+          id, status
+        )
+      })
+      .collect();
+    serde_json::from_str(&format!("[{}]", json.join(","))).unwrap()
+  }
+
+  /// Reads what a stream sends until it waits for new events.
+  fn read_available(stream: &mut EventStream) -> String {
+    let mut buffer: [u8; 4096] = [0; 4096];
+    let read: usize = stream.read(&mut buffer).unwrap();
+    let mut received: String = String::from_utf8_lossy(&buffer[..read]).to_string();
+    while stream.position < stream.pending.len() {
+      let read: usize = stream.read(&mut buffer).unwrap();
+      received.push_str(&String::from_utf8_lossy(&buffer[..read]));
+    }
+    received
+  }
+
+  #[test]
+  fn test_update_records_changes() {
+    let events = OrderEvents::new(100, &orders(&[(1, "Shipped"), (2, "Pending")]));
+    events.update(&orders(&[(1, "Shipped"), (2, "Pending")]));
+    assert_eq!(events.wait(100, Duration::ZERO), Some((100, Vec::new())));
+
+    events.update(&orders(&[(2, "Delivered"), (3, "Pending")]));
+    let (last_id, recorded) = events.wait(100, Duration::ZERO).unwrap();
+    assert_eq!(last_id, 103);
+    let summary: Vec<(Option<&str>, Option<&str>, &str)> = recorded
+      .iter()
+      .map(|e| (e.event.as_deref(), e.id.as_deref(), &e.data[..13]))
+      .collect();
+    assert_eq!(
+      summary,
+      vec![
+        (Some(ORDER_UPDATED), Some("101"), "{\"order_id\":2"),
+        (Some(ORDER_UPDATED), Some("102"), "{\"order_id\":3"),
+        (Some(ORDER_DELETED), Some("103"), "{\"order_id\":1"),
+      ]
+    );
+    assert!(recorded[0].data.contains("Delivered"));
+  }
+
+  #[test]
+  fn test_stream_replays_missed_events() {
+    let events = Arc::new(OrderEvents::new(100, &orders(&[(1, "Pending")])));
+    events.update(&orders(&[(1, "Shipped")]));
+    events.update(&orders(&[(1, "Delivered")]));
+
+    // A new client gets the following events only
+    let mut stream = EventStream::new(Arc::clone(&events), None);
+    assert_eq!(read_available(&mut stream), "retry: 3000\n\n");
+
+    // A reconnecting client gets the events after its last one
+    let mut stream = EventStream::new(Arc::clone(&events), Some("101"));
+    let received: String = read_available(&mut stream);
+    assert!(received.starts_with("retry: 3000\n\nevent: order-updated\nid: 102\n"));
+    assert!(received.contains("Delivered") && !received.contains("Shipped"));
+
+    // An unknown ID gets the current orders
+    let mut stream = EventStream::new(Arc::clone(&events), Some("7"));
+    let received: String = read_available(&mut stream);
+    assert_eq!(
+      received.matches("event: order-updated\nid: 102\n").count(),
+      1
+    );
+
+    // Events recorded later are sent as they happen, and closing ends the stream
+    let mut stream = EventStream::new(Arc::clone(&events), None);
+    read_available(&mut stream);
+    let updater = {
+      let events = Arc::clone(&events);
+      thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        events.update(&orders(&[(1, "Returned")]));
+      })
+    };
+    let received: String = read_available(&mut stream);
+    assert!(received.starts_with("event: order-updated\nid: 103\n"));
+    assert!(received.contains("Returned"));
+    updater.join().unwrap();
+
+    events.close();
+    assert_eq!(stream.read(&mut [0; 16]).unwrap(), 0);
+  }
+}
@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Mutex;
+use std::time::SystemTime;
+use std::{error, fmt};
+
+use crate::handler::OrderStatus;
+
+/// Orders read from every data file, by path. Holding the lock also makes the writers
+/// of a file take turns.
+static STORES: Mutex<Option<HashMap<PathBuf, CachedOrders>>> = Mutex::new(None);
+
+/// Represents the errors of the data file of the orders.
+#[derive(Debug)]
+pub enum StoreError {
+  /// The file could not be read or written.
+  Io(io::Error),
+  /// The file does not hold a list of orders.
+  Parse(serde_json::Error),
+}
+
+impl fmt::Display for StoreError {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    match self {
+      StoreError::Io(e) => write!(f, "{}", e),
+      StoreError::Parse(e) => write!(f, "invalid orders: {}", e),
+    }
+  }
+}
+
+impl error::Error for StoreError {}
+
+impl From<io::Error> for StoreError {
+  fn from(value: io::Error) -> Self {
+    StoreError::Io(value)
+  }
+}
+
+/// Represents the orders of a data file, as they were when it was last read or written.
+struct CachedOrders {
+  /// Modification time and size of the file.
+  version: (SystemTime, u64),
+  /// Orders held in the file.
+  orders: Vec<OrderStatus>,
+}
+
+/// Represents the shipping orders held in a JSON data file. The file is only read
+/// again when it changes, and it is replaced as a whole on every change, so readers
+/// never see it half written.
+pub struct OrderStore {
+  /// Path of the data file.
+  path: PathBuf,
+}
+
+impl OrderStore {
+  /// Creates a new [`OrderStore`] object.
+  ///
+  /// # Arguments
+  ///
+  /// * `path`: Path of the data file.
+  pub fn new(path: impl Into<PathBuf>) -> Self {
+    Self { path: path.into() }
+  }
+
+  /// Gets the current orders.
+  pub fn load(&self) -> Result<Vec<OrderStatus>, StoreError> {
+    let mut stores = STORES.lock().unwrap();
+
+    self.read(stores.get_or_insert_with(HashMap::new))
+  }
+
+  /// Changes the orders and writes them back to the data file, unless the change
+  /// fails. No other change of the file can happen in the meantime.
+  ///
+  /// # Arguments
+  ///
+  /// * `change`: Function changing the orders, returning its result.
+  pub fn modify<T, E: From<StoreError>>(
+    &self,
+    change: impl FnOnce(&mut Vec<OrderStatus>) -> Result<T, E>,
+  ) -> Result<T, E> {
+    let mut stores = STORES.lock().unwrap();
+    let stores: &mut HashMap<PathBuf, CachedOrders> =
+      stores.get_or_insert_with(HashMap::new);
+
+    let mut orders: Vec<OrderStatus> = self.read(stores)?;
+    let result: T = change(&mut orders)?;
+    self.write(&orders)?;
+
+    let version: Option<(SystemTime, u64)> = file_version(&self.path);
+    match version {
+      Some(version) => {
+        stores.insert(self.path.clone(), CachedOrders { version, orders });
+      }
+      None => {
+        stores.remove(&self.path);
+      }
+    }
+    Ok(result)
+  } // end fn modify()
+
+  /// Gets the orders of the data file, reading it if it changed since it was cached.
+  ///
+  /// # Arguments
+  ///
+  /// * `stores`: Orders cached.
+  fn read(
+    &self,
+    stores: &mut HashMap<PathBuf, CachedOrders>,
+  ) -> Result<Vec<OrderStatus>, StoreError> {
+    let version: Option<(SystemTime, u64)> = file_version(&self.path);
+    if let Some(cached) = stores.get(&self.path) {
+      if Some(cached.version) == version {
+        return Ok(cached.orders.clone());
+      }
+    }
+
+    let json_contents: String = fs::read_to_string(&self.path)?;
+    let orders: Vec<OrderStatus> =
+      serde_json::from_str(&json_contents).map_err(StoreError::Parse)?;
+    match version {
+      Some(version) => {
+        let cached: CachedOrders = CachedOrders {
+          version,
+          orders: orders.clone(),
+        };
+        stores.insert(self.path.clone(), cached);
+      }
+      None => {
+        stores.remove(&self.path);
+      }
+    }
+    Ok(orders)
+  }
+
+  /// Writes the orders to a temporary file next to the data file, then renames it over
+  /// the data file.
+  ///
+  /// # Arguments
+  ///
+  /// * `orders`: Orders to write.
+  fn write(
+    &self,
+    orders: &[OrderStatus],
+  ) -> Result<(), StoreError> {
+    let mut json_contents: String =
+      serde_json::to_string_pretty(orders).map_err(StoreError::Parse)?;
+    json_contents.push('\n');
+
+    let file_name: String = self.path.file_name().map_or("orders.json".into(), |name| {
+      name.to_string_lossy().to_string()
+    });
+    let temporary: PathBuf =
+      self
+        .path
+        .with_file_name(format!(".{}.{}.tmp", file_name, process::id()));
+
+    let written: io::Result<()> = File::create(&temporary).and_then(|mut file| {
+      file.write_all(json_contents.as_bytes())?;
+      file.sync_all()
+    });
+    if let Err(e) = written.and_then(|_| fs::rename(&temporary, &self.path)) {
+      let _ = fs::remove_file(&temporary);
+      return Err(StoreError::Io(e));
+    }
+    Ok(())
+  } // end fn write()
+}
+
+/// Gets the modification time and the size of a file, which change when it is written.
+///
+/// # Arguments
+///
+/// * `path`: Path of the file.
+pub fn file_version(path: &Path) -> Option<(SystemTime, u64)> {
+  let metadata: fs::Metadata = fs::metadata(path).ok()?;
+
+  Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::env;
+  use std::sync::Arc;
+  use std::thread;
+
+  /// Creates a data file with two orders in a new temporary directory.
+  fn data_file(name: &str) -> PathBuf {
+    let dir: PathBuf = env::temp_dir().join(format!(
+      "http_server_order_store_{}_{}",
+      name,
+      process::id()
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let path: PathBuf = dir.join("orders.json");
+    fs::write(
+      &path,
+      "[{\"order_id\":1,\"order_date\":\"21 Jan 2020\",\"order_status\":\"Delivered\"},\
+       {\"order_id\":2,\"order_date\":\"2 Feb 2020\",\"order_status\":\"Pending\"}]",
+    )
+    .unwrap();
+    path
+  }
+
+  #[test]
+  fn test_modify_writes_back() {
+    let path: PathBuf = data_file("modify");
+    let store = OrderStore::new(&path);
+    assert_eq!(store.load().unwrap().len(), 2);
+
+    let removed: i32 = store
+      .modify(|orders| {
+        let order: OrderStatus = orders.remove(0);
+        Ok::<i32, StoreError>(order.order_id())
+      })
+      .unwrap();
+    assert_eq!(removed, 1);
+    let ids: Vec<i32> = OrderStore::new(&path)
+      .load()
+      .unwrap()
+      .iter()
+      .map(OrderStatus::order_id)
+      .collect();
+    assert_eq!(ids, vec![2]);
+    assert!(fs::read_to_string(&path).unwrap().starts_with("[\n  {\n"));
+
+    // A failed change leaves the file as it is
+    let failed: Result<(), StoreError> =
+      store.modify(|_| Err(StoreError::Io(io::ErrorKind::Other.into())));
+    assert!(failed.is_err());
+    assert_eq!(store.load().unwrap().len(), 1);
+    assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+
+    // The file is read again when it changes behind the store
+    fs::write(&path, "not json").unwrap();
+    assert!(matches!(store.load(), Err(StoreError::Parse(_))));
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+  }
+
+  #[test]
+  fn test_concurrent_writers() {
+    let path: PathBuf = data_file("concurrent");
+    let store = Arc::new(OrderStore::new(&path));
+
+    let writers: Vec<thread::JoinHandle<()>> = (0..8)
+      .map(|_| {
+        let store = Arc::clone(&store);
+        thread::spawn(move || {
+          store
+            .modify(|orders| {
+              let order_id: i32 = orders.iter().map(OrderStatus::order_id).max().unwrap() + 1;
+              let order: String = format!(
+                "{{\"order_id\":{},\"order_date\":\"1 Mar 2020\",\"order_status\":\"Pending\"}}",
+                order_id
+              );
+              orders.push(serde_json::from_str(&order).unwrap());
+              Ok::<(), StoreError>(())
+            })
+            .unwrap();
+        })
+      })
+      .collect();
+    for writer in writers {
+      writer.join().unwrap();
+    }
+
+    // No change was lost
+    let mut ids: Vec<i32> = store
+      .load()
+      .unwrap()
+      .iter()
+      .map(OrderStatus::order_id)
+      .collect();
+    ids.sort();
+    assert_eq!(ids, (1..=10).collect::<Vec<i32>>());
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+  }
+}
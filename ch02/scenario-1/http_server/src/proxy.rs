@@ -0,0 +1,658 @@
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use http::body::Body;
+use http::chunked::ChunkedReader;
+use http::header_map::HeaderMap;
+use http::http_request::{HttpRequest, Method, Version};
+use http::http_response::HttpResponse;
+use http::resource::Resource;
+use http::status_code::{self, StatusCode};
+
+use crate::config::ProxySettings;
+use crate::middleware::{Context, Middleware};
+
+/// Headers that only apply to a single connection, so they are never forwarded.
+const HOP_BY_HOP_HEADERS: [&str; 9] = [
+  "Connection",
+  "Keep-Alive",
+  "Proxy-Authenticate",
+  "Proxy-Authorization",
+  "Proxy-Connection",
+  "TE",
+  "Trailer",
+  "Transfer-Encoding",
+  "Upgrade",
+];
+
+/// Maximum number of bytes of the status line and headers of an upstream response.
+const MAX_HEAD_SIZE: u64 = 64 * 1024;
+
+/// Represents the ways an exchange with an upstream server fails.
+#[derive(Debug)]
+enum ProxyError {
+  /// The connection could not be established, so nothing was sent and another
+  /// upstream server can be tried.
+  Connect(io::Error),
+  /// The upstream server did not answer in time.
+  Timeout,
+  /// The exchange failed or the response is not valid HTTP/1.1.
+  Failed(String),
+}
+
+impl fmt::Display for ProxyError {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    match self {
+      ProxyError::Connect(e) => write!(f, "cannot connect: {}", e),
+      ProxyError::Timeout => write!(f, "timed out"),
+      ProxyError::Failed(message) => write!(f, "{}", message),
+    }
+  }
+}
+
+impl From<io::Error> for ProxyError {
+  fn from(value: io::Error) -> Self {
+    match value.kind() {
+      io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => ProxyError::Timeout,
+      _ => ProxyError::Failed(value.to_string()),
+    }
+  }
+}
+
+/// Represents the health of an upstream server, as seen from the failed exchanges.
+#[derive(Debug, Default)]
+struct Health {
+  /// Number of exchanges failed in a row.
+  failures: usize,
+  /// Moment the server is tried again after too many failures.
+  down_until: Option<Instant>,
+}
+
+/// Represents an upstream server requests are forwarded to.
+#[derive(Debug)]
+struct Upstream {
+  /// Socket address of the server, e.g. `localhost:8080`.
+  address: String,
+  /// Health of the server.
+  health: Mutex<Health>,
+}
+
+impl Upstream {
+  /// Checks whether the server can be tried now.
+  fn is_available(&self) -> bool {
+    let health = self.health.lock().unwrap();
+    !matches!(health.down_until, Some(until) if until > Instant::now())
+  }
+
+  /// Records a failed exchange, taking the server out of the rotation for a while once
+  /// it failed too many times in a row.
+  ///
+  /// # Arguments
+  ///
+  /// * `max_fails`: Number of failures in a row taking the server out.
+  /// * `fail_timeout`: Time the server stays out.
+  fn failed(
+    &self,
+    max_fails: usize,
+    fail_timeout: Duration,
+  ) {
+    let mut health = self.health.lock().unwrap();
+    health.failures += 1;
+    if health.failures >= max_fails {
+      warn!(
+        "Upstream {} is down for {}s",
+        self.address,
+        fail_timeout.as_secs()
+      );
+      health.failures = 0;
+      health.down_until = Some(Instant::now() + fail_timeout);
+    }
+  }
+
+  /// Records a successful exchange.
+  fn succeeded(&self) {
+    let mut health = self.health.lock().unwrap();
+    health.failures = 0;
+    health.down_until = None;
+  }
+}
+
+/// Represents a path prefix forwarded to a group of upstream servers.
+#[derive(Debug)]
+struct ProxyRoute {
+  /// Path prefix, without a trailing slash, e.g. `/api/inventory`.
+  prefix: String,
+  /// Whether the prefix is removed from the path forwarded.
+  strip_prefix: bool,
+  /// Servers taking the requests in turns.
+  upstreams: Vec<Upstream>,
+  /// Counter choosing the next server.
+  next: AtomicUsize,
+  /// Time allowed to connect to a server.
+  connect_timeout: Duration,
+  /// Time allowed to a server between two reads of its response.
+  read_timeout: Duration,
+  /// Number of failures in a row taking a server out of the rotation.
+  max_fails: usize,
+  /// Time a failing server stays out of the rotation.
+  fail_timeout: Duration,
+}
+
+impl ProxyRoute {
+  /// Gets the path of the given origin-form target forwarded by this route, or `None`
+  /// if the route does not match it.
+  ///
+  /// # Arguments
+  ///
+  /// * `target`: Request target in origin-form (`/path?query`).
+  fn forwarded_target(
+    &self,
+    target: &str,
+  ) -> Option<String> {
+    let rest: &str = target.strip_prefix(self.prefix.as_str())?;
+    if !(rest.is_empty() || rest.starts_with(['/', '?'])) {
+      return None;
+    }
+
+    match (self.strip_prefix, rest.starts_with('/')) {
+      (false, _) => Some(target.to_string()),
+      (true, true) => Some(rest.to_string()),
+      (true, false) => Some(format!("/{}", rest)),
+    }
+  }
+
+  /// Gets the servers to try for the next request: all of them in turns, starting with
+  /// the next one of the rotation, leaving out the ones that failed recently.
+  fn candidates(&self) -> impl Iterator<Item = &Upstream> {
+    let start: usize = self.next.fetch_add(1, Ordering::Relaxed);
+    let count: usize = self.upstreams.len();
+
+    (0..count)
+      .map(move |i| &self.upstreams[(start + i) % count])
+      .filter(|upstream| upstream.is_available())
+  }
+}
+
+/// Represents a middleware forwarding the requests whose path starts with a configured
+/// prefix to upstream HTTP/1.1 servers, and answering with their responses. Other
+/// requests go on to the routes of the server.
+#[derive(Debug, Default)]
+pub struct ProxyHandler {
+  /// Prefixes forwarded, the longest first.
+  routes: Vec<ProxyRoute>,
+}
+
+impl ProxyHandler {
+  /// Creates the handler from the `[[proxy]]` sections of the configuration.
+  ///
+  /// # Arguments
+  ///
+  /// * `settings`: Settings of every forwarded prefix.
+  pub fn from_settings(settings: &[ProxySettings]) -> Self {
+    let mut routes: Vec<ProxyRoute> = settings
+      .iter()
+      .map(|proxy| ProxyRoute {
+        prefix: proxy.prefix.trim_end_matches('/').to_string(),
+        strip_prefix: proxy.strip_prefix,
+        upstreams: proxy
+          .upstreams
+          .iter()
+          .map(|address| Upstream {
+            address: address.clone(),
+            health: Mutex::new(Health::default()),
+          })
+          .collect(),
+        next: AtomicUsize::new(0),
+        connect_timeout: Duration::from_secs(proxy.connect_timeout),
+        read_timeout: Duration::from_secs(proxy.read_timeout),
+        max_fails: proxy.max_fails,
+        fail_timeout: Duration::from_secs(proxy.fail_timeout),
+      })
+      .collect();
+    routes.sort_by_key(|route| std::cmp::Reverse(route.prefix.len()));
+
+    Self { routes }
+  }
+
+  /// Forwards a request to the servers of a route, trying the next one while they
+  /// cannot be reached, and returns the response: the one of the server, or "502 Bad
+  /// Gateway" or "504 Gateway Timeout" when they fail.
+  ///
+  /// # Arguments
+  ///
+  /// * `route`: Route matching the request.
+  /// * `request`: HTTP request to forward, already rewritten.
+  fn forward(
+    route: &ProxyRoute,
+    mut request: HttpRequest,
+  ) -> HttpResponse {
+    let mut status_code: StatusCode = StatusCode::BAD_GATEWAY;
+
+    for upstream in route.candidates() {
+      request.headers.insert("Host", &upstream.address);
+      match Self::exchange(route, upstream, &request) {
+        Ok(response) => {
+          upstream.succeeded();
+          return response;
+        }
+        Err(e) => {
+          warn!(
+            "Failed to forward {} to {}: {}",
+            request.resource.target, upstream.address, e
+          );
+          upstream.failed(route.max_fails, route.fail_timeout);
+          match e {
+            ProxyError::Connect(e) if e.kind() == io::ErrorKind::TimedOut => {
+              status_code = StatusCode::GATEWAY_TIMEOUT;
+            }
+            ProxyError::Connect(_) => status_code = StatusCode::BAD_GATEWAY,
+            // The request may have been processed, so it is not sent again
+            ProxyError::Timeout => {
+              return HttpResponse::new(StatusCode::GATEWAY_TIMEOUT, None, Body::Empty)
+            }
+            ProxyError::Failed(_) => {
+              return HttpResponse::new(StatusCode::BAD_GATEWAY, None, Body::Empty)
+            }
+          }
+        }
+      }
+    }
+    HttpResponse::new(status_code, None, Body::Empty)
+  } // end fn forward()
+
+  /// Sends a request to an upstream server on a new connection and reads the head of
+  /// its response. The body is streamed from the connection as the response is sent.
+  ///
+  /// # Arguments
+  ///
+  /// * `route`: Route matching the request.
+  /// * `upstream`: Server receiving the request.
+  /// * `request`: HTTP request to forward, with the `Host` of the server.
+  fn exchange(
+    route: &ProxyRoute,
+    upstream: &Upstream,
+    request: &HttpRequest,
+  ) -> Result<HttpResponse, ProxyError> {
+    let stream: TcpStream =
+      connect(&upstream.address, route.connect_timeout).map_err(ProxyError::Connect)?;
+    stream.set_read_timeout(Some(route.read_timeout))?;
+    stream.set_write_timeout(Some(route.read_timeout))?;
+
+    request.write_to(&mut &stream)?;
+
+    // Interim responses, e.g. "100 Continue", are not passed on
+    let mut reader: BufReader<TcpStream> = BufReader::new(stream);
+    let (status_code, mut headers) = loop {
+      let (status_code, headers) = read_head(&mut reader)?;
+      if status_code.as_u16() == 101 {
+        return Err(ProxyError::Failed(
+          "protocol upgrades are not proxied".to_string(),
+        ));
+      }
+      if !status_code.is_informational() {
+        break (status_code, headers);
+      }
+    };
+
+    let chunked: bool = headers
+      .get_all("Transfer-Encoding")
+      .iter()
+      .any(|coding| coding.to_ascii_lowercase().contains("chunked"));
+    let content_length: Option<u64> = match headers.get("Content-Length") {
+      Some(length) => Some(length.trim().parse().map_err(|_| {
+        ProxyError::Failed(format!("invalid Content-Length: {}", length))
+      })?),
+      None => None,
+    };
+    remove_hop_by_hop(&mut headers);
+    headers.remove("Content-Length");
+
+    // The length of the body a GET request would receive is kept for HEAD requests
+    let body: Body = if request.method == Method::HEAD
+      || status_code == StatusCode::NO_CONTENT
+      || status_code == StatusCode::NOT_MODIFIED
+    {
+      Body::from_reader(io::empty(), content_length)
+    } else if chunked {
+      Body::from_reader(ChunkedReader::new(reader), None)
+    } else {
+      Body::from_reader(reader, content_length)
+    };
+    Ok(HttpResponse::new(status_code, Some(headers), body))
+  } // end fn exchange()
+
+  /// Rewrites a request for the upstream servers: the target of the route, no
+  /// hop-by-hop headers, and the `X-Forwarded-*` headers describing the client.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request received.
+  /// * `target`: Target forwarded.
+  /// * `context`: Data about the request.
+  fn rewrite(
+    request: &HttpRequest,
+    target: &str,
+    context: &Context,
+  ) -> Result<HttpRequest, HttpResponse> {
+    let mut outgoing: HttpRequest = request.clone();
+    outgoing.version = Version::V1_1;
+    outgoing.resource = Resource::parse(target)
+      .map_err(|_| HttpResponse::new(StatusCode::BAD_REQUEST, None, Body::Empty))?;
+    outgoing.path_params.clear();
+
+    let headers: &mut HeaderMap = &mut outgoing.headers;
+    remove_hop_by_hop(headers);
+    // The body was already received
+    headers.remove("Expect");
+    headers.insert("Connection", "close");
+
+    if let Some(peer_address) = context.peer_address {
+      let forwarded_for: String = match headers.get("X-Forwarded-For") {
+        Some(previous) => format!("{}, {}", previous, peer_address.ip()),
+        None => peer_address.ip().to_string(),
+      };
+      headers.insert("X-Forwarded-For", &forwarded_for);
+    }
+    let proto: &str = if context.secure { "https" } else { "http" };
+    headers.insert("X-Forwarded-Proto", proto);
+    if let Some(host) = request.headers.host().map(str::to_string) {
+      headers.insert("X-Forwarded-Host", &host);
+    }
+
+    Ok(outgoing)
+  } // end fn rewrite()
+}
+
+impl Middleware for ProxyHandler {
+  fn before(
+    &self,
+    request: &mut HttpRequest,
+    context: &mut Context,
+  ) -> Option<HttpResponse> {
+    let target: &str = origin_form(&request.resource.target);
+    let (route, forwarded) = self
+      .routes
+      .iter()
+      .find_map(|route| Some((route, route.forwarded_target(target)?)))?;
+
+    match Self::rewrite(request, &forwarded, context) {
+      Ok(outgoing) => Some(Self::forward(route, outgoing)),
+      Err(response) => Some(response),
+    }
+  }
+}
+
+/// Gets the origin-form (`/path?query`) of a request target, which may be in
+/// absolute-form (`http://host/path?query`).
+///
+/// # Arguments
+///
+/// * `target`: Request target.
+fn origin_form(target: &str) -> &str {
+  match target.split_once("://") {
+    Some((_, rest)) => match rest.find(['/', '?']) {
+      Some(i) if rest[i..].starts_with('/') => &rest[i..],
+      _ => "/",
+    },
+    None => target,
+  }
+}
+
+/// Removes the hop-by-hop headers, including the ones named by `Connection`.
+///
+/// # Arguments
+///
+/// * `headers`: Headers of a message being forwarded.
+fn remove_hop_by_hop(headers: &mut HeaderMap) {
+  for name in headers.connection() {
+    headers.remove(&name);
+  }
+  for name in HOP_BY_HOP_HEADERS {
+    headers.remove(name);
+  }
+}
+
+/// Connects to the first address of a server that accepts the connection.
+///
+/// # Arguments
+///
+/// * `address`: Socket address of the server, e.g. `localhost:8080`.
+/// * `timeout`: Time allowed to each connection attempt.
+fn connect(
+  address: &str,
+  timeout: Duration,
+) -> io::Result<TcpStream> {
+  let mut last_error: io::Error =
+    io::Error::new(io::ErrorKind::NotFound, "address not resolved");
+  for socket_address in address.to_socket_addrs()? {
+    match TcpStream::connect_timeout(&socket_address, timeout) {
+      Ok(stream) => return Ok(stream),
+      Err(e) => last_error = e,
+    }
+  }
+  Err(last_error)
+}
+
+/// Reads the status line and the headers of a response.
+///
+/// # Arguments
+///
+/// * `reader`: Connection with the upstream server.
+fn read_head(reader: &mut impl BufRead) -> Result<(StatusCode, HeaderMap), ProxyError> {
+  let mut limited = reader.take(MAX_HEAD_SIZE);
+  let mut next_line = || -> Result<String, ProxyError> {
+    let mut line: String = String::new();
+    if limited.read_line(&mut line)? == 0 || !line.ends_with('\n') {
+      return Err(ProxyError::Failed("response head ends early".to_string()));
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+  };
+
+  // Status line: "HTTP/1.1 200 OK"; the reason phrase may be empty
+  let status_line: String = next_line()?;
+  let mut parts = status_line.splitn(3, ' ');
+  let version: &str = parts.next().unwrap_or_default();
+  let code: Option<u16> = parts.next().and_then(|code| code.parse().ok());
+  let reason: &str = parts.next().unwrap_or_default().trim();
+  let status_code: StatusCode = match (version.starts_with("HTTP/1."), code) {
+    (true, Some(code)) => match status_code::canonical_reason(code) {
+      Some(canonical) if canonical == reason || reason.is_empty() => {
+        StatusCode::from_u16(code)
+      }
+      _ => StatusCode::with_reason(code, reason),
+    },
+    _ => None,
+  }
+  .ok_or_else(|| ProxyError::Failed(format!("invalid status line: {}", status_line)))?;
+
+  let mut headers: HeaderMap = HeaderMap::new();
+  loop {
+    let line: String = next_line()?;
+    if line.is_empty() {
+      break;
+    }
+    let (name, value) = line
+      .split_once(':')
+      .ok_or_else(|| ProxyError::Failed(format!("invalid header line: {}", line)))?;
+    headers.append(name.trim(), value.trim());
+  }
+  Ok((status_code, headers))
+} // end fn read_head()
+
+#[cfg(test)]
+mod tests {
+  use std::io::Write;
+  use std::net::TcpListener;
+  use std::thread;
+
+  use super::*;
+
+  /// Starts an upstream server answering each connection with the bytes the given
+  /// function builds from the head of the request, and returns its address.
+  fn upstream(respond: fn(&str) -> String) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+
+    thread::spawn(move || {
+      for stream in listener.incoming() {
+        let mut stream = stream.unwrap();
+        let mut head: Vec<u8> = Vec::new();
+        let mut byte = [0; 1];
+        while !head.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap_or(0) == 1 {
+          head.push(byte[0]);
+        }
+        let response = respond(&String::from_utf8_lossy(&head));
+        let _ = stream.write_all(response.as_bytes());
+      }
+    });
+    address
+  }
+
+  /// Gets an address nothing listens on.
+  fn closed_address() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().to_string()
+  }
+
+  fn settings(
+    prefix: &str,
+    upstreams: &[&str],
+  ) -> ProxySettings {
+    ProxySettings {
+      prefix: prefix.to_string(),
+      upstreams: upstreams.iter().map(|u| u.to_string()).collect(),
+      read_timeout: 1,
+      ..ProxySettings::default()
+    }
+  }
+
+  /// Runs a request through the handler, as the first hook of the chain would.
+  fn proxy(
+    handler: &ProxyHandler,
+    text: &str,
+  ) -> Option<HttpResponse> {
+    let mut request = HttpRequest::parse(text.as_bytes()).unwrap().0;
+    let mut context = Context::new(Some("10.0.0.7:50000".parse().unwrap()));
+    handler.before(&mut request, &mut context)
+  }
+
+  fn body(mut response: HttpResponse) -> String {
+    String::from_utf8(response.take_body().into_bytes().unwrap()).unwrap()
+  }
+
+  #[test]
+  fn test_forward_rewrites_headers() {
+    // The upstream answers with the head of the request it received
+    let address = upstream(|head| {
+      format!(
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\nX-Upstream: yes\r\n\r\n{}",
+        head.len(),
+        head
+      )
+    });
+    let mut proxied = settings("/inventory/", &[&address]);
+    proxied.strip_prefix = true;
+    let handler = ProxyHandler::from_settings(&[proxied]);
+
+    assert!(proxy(&handler, "GET /inventoryx HTTP/1.1\r\n\r\n").is_none());
+    assert!(proxy(&handler, "GET /orders HTTP/1.1\r\n\r\n").is_none());
+
+    let response = proxy(
+      &handler,
+      "GET /inventory/items?page=2 HTTP/1.1\r\nHost: shop.example.com\r\n\
+       X-Forwarded-For: 192.0.2.1\r\nConnection: keep-alive, X-Secret\r\nX-Secret: 1\r\n\r\n",
+    )
+    .unwrap();
+    assert_eq!(response.status_code(), &StatusCode::OK);
+    assert_eq!(response.header("X-Upstream"), Some("yes"));
+    assert_eq!(response.header("Connection"), None);
+
+    let head = body(response);
+    assert!(
+      head.starts_with("GET /items?page=2 HTTP/1.1\r\n"),
+      "{}",
+      head
+    );
+    assert!(head.contains(&format!("Host: {}\r\n", address)));
+    assert!(head.contains("X-Forwarded-For: 192.0.2.1, 10.0.0.7\r\n"));
+    assert!(head.contains("X-Forwarded-Proto: http\r\n"));
+    assert!(head.contains("X-Forwarded-Host: shop.example.com\r\n"));
+    assert!(head.contains("Connection: close\r\n"));
+    assert!(!head.contains("X-Secret"));
+  }
+
+  #[test]
+  fn test_stream_chunked_response() {
+    let address = upstream(|_| {
+      "HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+       5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n"
+        .to_string()
+    });
+    let handler = ProxyHandler::from_settings(&[settings("/", &[&address])]);
+
+    let response = proxy(&handler, "GET /stream HTTP/1.1\r\n\r\n").unwrap();
+    assert_eq!(response.body().len(), None);
+    assert_eq!(body(response), "hello world");
+  }
+
+  #[test]
+  fn test_round_robin_and_health() {
+    let first =
+      upstream(|_| "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nfirst".to_string());
+    let second =
+      upstream(|_| "HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nsecond".to_string());
+    let handler = ProxyHandler::from_settings(&[settings("/api", &[&first, &second])]);
+
+    let bodies: Vec<String> = (0..4)
+      .map(|_| body(proxy(&handler, "GET /api HTTP/1.1\r\n\r\n").unwrap()))
+      .collect();
+    assert_eq!(bodies, ["first", "second", "first", "second"]);
+
+    // A server that cannot be reached is skipped, then left out of the rotation
+    let dead = closed_address();
+    let handler = ProxyHandler::from_settings(&[settings("/api", &[&dead, &first])]);
+    for _ in 0..3 {
+      let response = proxy(&handler, "GET /api/x HTTP/1.1\r\n\r\n").unwrap();
+      assert_eq!(body(response), "first");
+    }
+    assert!(!handler.routes[0].upstreams[0].is_available());
+    assert!(handler.routes[0].upstreams[1].is_available());
+  }
+
+  #[test]
+  fn test_gateway_errors() {
+    let handler = ProxyHandler::from_settings(&[settings("/api", &[&closed_address()])]);
+    let response = proxy(&handler, "GET /api HTTP/1.1\r\n\r\n").unwrap();
+    assert_eq!(response.status_code(), &StatusCode::BAD_GATEWAY);
+    // The server is now out of the rotation
+    let response = proxy(&handler, "GET /api HTTP/1.1\r\n\r\n").unwrap();
+    assert_eq!(response.status_code(), &StatusCode::BAD_GATEWAY);
+
+    let invalid = upstream(|_| "SMTP ready\r\n\r\n".to_string());
+    let handler = ProxyHandler::from_settings(&[settings("/api", &[&invalid])]);
+    let response = proxy(&handler, "GET /api HTTP/1.1\r\n\r\n").unwrap();
+    assert_eq!(response.status_code(), &StatusCode::BAD_GATEWAY);
+
+    // The upstream accepts the connection but never answers
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let silent = listener.local_addr().unwrap().to_string();
+    let handler = ProxyHandler::from_settings(&[settings("/api", &[&silent])]);
+    let response = proxy(&handler, "GET /api HTTP/1.1\r\n\r\n").unwrap();
+    assert_eq!(response.status_code(), &StatusCode::GATEWAY_TIMEOUT);
+    drop(listener);
+  }
+
+  #[test]
+  fn test_origin_form() {
+    assert_eq!(origin_form("/a?b"), "/a?b");
+    assert_eq!(origin_form("http://example.com/a?b"), "/a?b");
+    assert_eq!(origin_form("http://example.com?b"), "/");
+  }
+}
@@ -0,0 +1,343 @@
+use std::fs::File;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use http::body::Body;
+use http::header_map::HeaderMap;
+use http::http_response::HttpResponse;
+use http::status_code::StatusCode;
+
+/// Maximum number of ranges accepted in a single request. Requests with more ranges
+/// get the whole file instead.
+const MAX_RANGES: usize = 32;
+
+/// Represents a range of bytes of a file, with both ends included.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ByteRange {
+  /// Position of the first byte.
+  pub start: u64,
+  /// Position of the last byte.
+  pub end: u64,
+}
+
+impl ByteRange {
+  /// Gets the number of bytes of the range.
+  pub fn len(&self) -> u64 {
+    self.end - self.start + 1
+  }
+
+  /// Gets the value of the `Content-Range` header for this range.
+  ///
+  /// # Arguments
+  ///
+  /// * `length`: Length of the whole file.
+  pub fn content_range(
+    &self,
+    length: u64,
+  ) -> String {
+    format!("bytes {}-{}/{}", self.start, self.end, length)
+  }
+}
+
+/// Represents how a `Range` header must be answered.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum RangeRequest {
+  /// The header is invalid or not worth honouring, so the whole file is sent.
+  Ignored,
+  /// The ranges to send, sorted and without overlaps.
+  Satisfiable(Vec<ByteRange>),
+  /// No range overlaps the file.
+  Unsatisfiable,
+}
+
+/// Parses a `Range` header (e.g. `bytes=0-99,200-,-50`) for a file of the given length.
+///
+/// Overlapping and adjacent ranges are merged. Ranges past the end of the file are
+/// skipped, and the ones ending past it are shortened.
+///
+/// # Arguments
+///
+/// * `value`: Value of the `Range` header.
+/// * `length`: Length of the file.
+pub fn parse(
+  value: &str,
+  length: u64,
+) -> RangeRequest {
+  let Some((unit, specs)) = value.split_once('=') else {
+    return RangeRequest::Ignored;
+  };
+  if !unit.trim().eq_ignore_ascii_case("bytes") {
+    return RangeRequest::Ignored;
+  }
+
+  let specs: Vec<&str> = specs
+    .split(',')
+    .map(str::trim)
+    .filter(|s| !s.is_empty())
+    .collect();
+  if specs.is_empty() || specs.len() > MAX_RANGES {
+    return RangeRequest::Ignored;
+  }
+
+  let mut ranges: Vec<ByteRange> = Vec::new();
+  for spec in specs {
+    let Some((first, last)) = spec.split_once('-') else {
+      return RangeRequest::Ignored;
+    };
+    let number = |text: &str| -> Option<u64> {
+      (!text.is_empty() && text.bytes().all(|b| b.is_ascii_digit()))
+        .then(|| text.parse().ok())
+        .flatten()
+    };
+
+    let range: Option<ByteRange> = match (number(first), number(last), last.is_empty()) {
+      // Suffix range: the last bytes of the file
+      (None, Some(suffix), _) if first.is_empty() => {
+        (suffix > 0 && length > 0).then(|| ByteRange {
+          start: length.saturating_sub(suffix),
+          end: length - 1,
+        })
+      }
+      (Some(start), None, true) => (start < length).then(|| ByteRange {
+        start,
+        end: length - 1,
+      }),
+      (Some(start), Some(end), _) if start <= end => {
+        (start < length).then(|| ByteRange {
+          start,
+          end: end.min(length - 1),
+        })
+      }
+      _ => return RangeRequest::Ignored,
+    };
+    ranges.extend(range);
+  }
+
+  if ranges.is_empty() {
+    return RangeRequest::Unsatisfiable;
+  }
+
+  ranges.sort_by_key(|r| r.start);
+  let mut merged: Vec<ByteRange> = Vec::new();
+  for range in ranges {
+    match merged.last_mut() {
+      Some(last) if range.start <= last.end + 1 => last.end = last.end.max(range.end),
+      _ => merged.push(range),
+    }
+  }
+  RangeRequest::Satisfiable(merged)
+}
+
+/// Opens a file positioned at the start of a range and limited to its length.
+///
+/// # Arguments
+///
+/// * `path`: Path of the file.
+/// * `range`: Range to read.
+fn open_range(
+  path: &Path,
+  range: &ByteRange,
+) -> io::Result<io::Take<File>> {
+  let mut file: File = File::open(path)?;
+  file.seek(SeekFrom::Start(range.start))?;
+  Ok(file.take(range.len()))
+}
+
+/// Creates a "206 Partial Content" response with the given ranges of a file: the range
+/// itself for a single one, or a `multipart/byteranges` body for several.
+///
+/// # Arguments
+///
+/// * `path`: Path of the file.
+/// * `content_type`: MIME type of the file.
+/// * `length`: Length of the file.
+/// * `ranges`: Ranges to send, sorted and without overlaps.
+/// * `headers`: Headers of the response, such as the validators of the file.
+pub fn partial_response(
+  path: &Path,
+  content_type: &str,
+  length: u64,
+  ranges: &[ByteRange],
+  mut headers: HeaderMap,
+) -> io::Result<HttpResponse> {
+  if let [range] = ranges {
+    headers.insert("Content-Type", content_type);
+    headers.insert("Content-Range", &range.content_range(length));
+    let body: Body = Body::from_reader(open_range(path, range)?, Some(range.len()));
+    return Ok(HttpResponse::new(
+      StatusCode::PARTIAL_CONTENT,
+      Some(headers),
+      body,
+    ));
+  }
+
+  let boundary: String = format!(
+    "{:016x}",
+    SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+  );
+
+  // Chain the part headers with the ranges of the file
+  let mut total: u64 = 0;
+  let mut body: Box<dyn Read + Send> = Box::new(io::empty());
+  for range in ranges {
+    let part_head: String = format!(
+      "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+      boundary,
+      content_type,
+      range.content_range(length)
+    );
+    total += part_head.len() as u64 + range.len();
+    body = Box::new(
+      body
+        .chain(Cursor::new(part_head.into_bytes()))
+        .chain(open_range(path, range)?),
+    );
+  }
+  let closing: String = format!("\r\n--{}--\r\n", boundary);
+  total += closing.len() as u64;
+  body = Box::new(body.chain(Cursor::new(closing.into_bytes())));
+
+  headers.insert(
+    "Content-Type",
+    &format!("multipart/byteranges; boundary={}", boundary),
+  );
+  Ok(HttpResponse::new(
+    StatusCode::PARTIAL_CONTENT,
+    Some(headers),
+    Body::from_reader(body, Some(total)),
+  ))
+}
+
+/// Creates a "416 Range Not Satisfiable" response.
+///
+/// # Arguments
+///
+/// * `length`: Length of the file.
+/// * `headers`: Headers of the response, such as the validators of the file.
+pub fn unsatisfiable_response(
+  length: u64,
+  mut headers: HeaderMap,
+) -> HttpResponse {
+  headers.insert("Content-Range", &format!("bytes */{}", length));
+  HttpResponse::new(
+    StatusCode::RANGE_NOT_SATISFIABLE,
+    Some(headers),
+    Body::Empty,
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{env, fs};
+
+  use super::*;
+
+  fn range(
+    start: u64,
+    end: u64,
+  ) -> ByteRange {
+    ByteRange { start, end }
+  }
+
+  #[test]
+  fn test_parse_ranges() {
+    assert_eq!(
+      parse("bytes=0-9", 100),
+      RangeRequest::Satisfiable(vec![range(0, 9)])
+    );
+    assert_eq!(
+      parse("bytes=90-", 100),
+      RangeRequest::Satisfiable(vec![range(90, 99)])
+    );
+    assert_eq!(
+      parse("bytes=-10", 100),
+      RangeRequest::Satisfiable(vec![range(90, 99)])
+    );
+    assert_eq!(
+      parse("bytes=-500", 100),
+      RangeRequest::Satisfiable(vec![range(0, 99)])
+    );
+    assert_eq!(
+      parse("bytes=50-500", 100),
+      RangeRequest::Satisfiable(vec![range(50, 99)])
+    );
+    // Sorted, merged and skipping the ranges past the end
+    assert_eq!(
+      parse("Bytes=50-59, 0-4, 5-9, 55-70, 200-", 100),
+      RangeRequest::Satisfiable(vec![range(0, 9), range(50, 70)])
+    );
+  }
+
+  #[test]
+  fn test_parse_invalid_ranges() {
+    assert_eq!(parse("bytes=100-", 100), RangeRequest::Unsatisfiable);
+    assert_eq!(parse("bytes=-0", 100), RangeRequest::Unsatisfiable);
+    assert_eq!(parse("bytes=0-0", 0), RangeRequest::Unsatisfiable);
+    assert_eq!(parse("bytes=9-0", 100), RangeRequest::Ignored);
+    assert_eq!(parse("bytes=a-b", 100), RangeRequest::Ignored);
+    assert_eq!(parse("bytes=", 100), RangeRequest::Ignored);
+    assert_eq!(parse("items=0-9", 100), RangeRequest::Ignored);
+    assert_eq!(
+      parse(&format!("bytes={}", "0-0,".repeat(40)), 100),
+      RangeRequest::Ignored
+    );
+  }
+
+  #[test]
+  fn test_partial_responses() {
+    let path = env::temp_dir().join(format!("range-{}.txt", std::process::id()));
+    fs::write(&path, "0123456789").unwrap();
+
+    let response =
+      partial_response(&path, "text/plain", 10, &[range(2, 4)], HeaderMap::new())
+        .unwrap();
+    assert_eq!(response.status_code(), &StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.header("Content-Range"), Some("bytes 2-4/10"));
+    let mut sent: Vec<u8> = Vec::new();
+    response.send_response(&mut sent).unwrap();
+    assert!(String::from_utf8(sent)
+      .unwrap()
+      .ends_with("Content-Length: 3\r\n\r\n234"));
+
+    let response = partial_response(
+      &path,
+      "text/plain",
+      10,
+      &[range(0, 1), range(8, 9)],
+      HeaderMap::new(),
+    )
+    .unwrap();
+    let boundary = response
+      .header("Content-Type")
+      .unwrap()
+      .split_once("boundary=")
+      .unwrap()
+      .1
+      .to_string();
+    let length = response.body().len().unwrap();
+    let mut sent: Vec<u8> = Vec::new();
+    response.send_response(&mut sent).unwrap();
+    let sent = String::from_utf8(sent).unwrap();
+    let body = sent.split_once("\r\n\r\n").unwrap().1;
+
+    assert_eq!(body.len() as u64, length);
+    assert_eq!(
+      body,
+      format!(
+        "\r\n--{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-1/10\r\n\r\n01\
+         \r\n--{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 8-9/10\r\n\r\n89\
+         \r\n--{b}--\r\n",
+        b = boundary
+      )
+    );
+
+    let response = unsatisfiable_response(10, HeaderMap::new());
+    assert_eq!(response.status_code(), &StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(response.header("Content-Range"), Some("bytes */10"));
+
+    fs::remove_file(&path).unwrap();
+  }
+}
@@ -0,0 +1,376 @@
+use std::fmt;
+use std::io::{self, Read};
+
+use http::chunked::ChunkedScanner;
+use http::http_request::{HttpRequest, ParseError};
+use http::status_code::StatusCode;
+use serde::Deserialize;
+
+/// Size of the chunks read from the stream on each iteration.
+const READ_CHUNK_SIZE: usize = 1024;
+
+/// Represents the size limits enforced while reading a request.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RequestLimits {
+  /// Maximum number of bytes for the request line and the headers.
+  pub max_header_size: usize,
+  /// Maximum number of bytes for the request body.
+  pub max_body_size: usize,
+}
+
+impl Default for RequestLimits {
+  fn default() -> Self {
+    Self {
+      max_header_size: 8 * 1024,
+      max_body_size: 1024 * 1024,
+    }
+  }
+}
+
+/// Represents the errors that can happen while reading a request.
+#[derive(Debug)]
+pub enum ReadError {
+  /// The client closed the connection before sending any byte.
+  ConnectionClosed,
+  /// The request is not a valid HTTP request.
+  Parse(ParseError),
+  /// The request line and headers exceed the configured limit.
+  HeadersTooLarge,
+  /// The request body exceeds the configured limit.
+  BodyTooLarge,
+  /// The connection was idle for too long before a new request started.
+  IdleTimeout,
+  /// The client took too long to send the rest of a request.
+  Timeout,
+  /// The underlying stream failed.
+  Io(io::Error),
+}
+
+impl ReadError {
+  /// Gets the HTTP status code to answer the client with, if any.
+  pub fn status_code(&self) -> Option<StatusCode> {
+    match self {
+      ReadError::Parse(ParseError::UnsupportedTransferEncoding) => {
+        Some(StatusCode::NOT_IMPLEMENTED)
+      }
+      ReadError::Parse(_) => Some(StatusCode::BAD_REQUEST),
+      ReadError::BodyTooLarge => Some(StatusCode::CONTENT_TOO_LARGE),
+      ReadError::HeadersTooLarge => Some(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE),
+      ReadError::Timeout => Some(StatusCode::REQUEST_TIMEOUT),
+      ReadError::ConnectionClosed | ReadError::IdleTimeout | ReadError::Io(_) => None,
+    }
+  }
+}
+
+impl fmt::Display for ReadError {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    match self {
+      ReadError::ConnectionClosed => write!(f, "connection closed by the client"),
+      ReadError::Parse(e) => write!(f, "malformed request: {}", e),
+      ReadError::HeadersTooLarge => write!(f, "request headers too large"),
+      ReadError::BodyTooLarge => write!(f, "request body too large"),
+      ReadError::IdleTimeout => write!(f, "connection idle for too long"),
+      ReadError::Timeout => write!(f, "request not received in time"),
+      ReadError::Io(e) => write!(f, "I/O error: {}", e),
+    }
+  }
+}
+
+impl From<io::Error> for ReadError {
+  fn from(value: io::Error) -> Self {
+    ReadError::Io(value)
+  }
+}
+
+/// Represents a reader of HTTP requests from a byte stream.
+pub struct RequestReader {
+  /// Size limits for the incoming requests.
+  limits: RequestLimits,
+  /// Bytes received but not consumed yet.
+  buffer: Vec<u8>,
+  /// Progress of the check of the chunked body of the request being read, if any.
+  scanner: ChunkedScanner,
+}
+
+impl RequestReader {
+  /// Creates a new [`RequestReader`] object.
+  ///
+  /// # Arguments
+  ///
+  /// * `limits`: Size limits for the incoming requests.
+  pub fn new(limits: RequestLimits) -> Self {
+    Self {
+      limits,
+      buffer: Vec::new(),
+      scanner: ChunkedScanner::new(),
+    }
+  }
+
+  /// Reads the next complete request from the given stream.
+  ///
+  /// Bytes are read until the request is complete according to its framing, and any
+  /// extra bytes are kept for the next request.
+  ///
+  /// # Arguments
+  ///
+  /// * `stream`: Byte stream to read from. Recommended: a TCP stream.
+  pub fn read_request(
+    &mut self,
+    stream: &mut impl Read,
+  ) -> Result<HttpRequest, ReadError> {
+    loop {
+      if let Some(request) = self.next_request()? {
+        return Ok(request);
+      }
+      if self.fill(stream)? == 0 {
+        return Err(self.closed_error());
+      }
+    }
+  } // end fn read_request()
+
+  /// Takes the next complete request from the bytes received so far, or returns `None`
+  /// if more bytes are needed.
+  pub fn next_request(&mut self) -> Result<Option<HttpRequest>, ReadError> {
+    match HttpRequest::parse_framed_with(&self.buffer, &mut self.scanner) {
+      Ok((request, head_length, request_length)) => {
+        self.check_limits(Some(head_length), Some(request.message_body.len()))?;
+        self.buffer.drain(..request_length);
+        self.scanner = ChunkedScanner::new();
+        Ok(Some(request))
+      }
+      Err(ParseError::Incomplete {
+        head_length,
+        body_length,
+      }) => {
+        self.check_limits(head_length, body_length)?;
+        Ok(None)
+      }
+      Err(e) => Err(ReadError::Parse(e)),
+    }
+  }
+
+  /// Adds bytes received from the stream.
+  ///
+  /// # Arguments
+  ///
+  /// * `bytes`: Bytes received.
+  pub fn push(
+    &mut self,
+    bytes: &[u8],
+  ) {
+    self.buffer.extend_from_slice(bytes);
+  }
+
+  /// Checks whether no bytes of the next request were received yet.
+  pub fn is_idle(&self) -> bool {
+    self.buffer.is_empty()
+  }
+
+  /// Gets the error for a stream that ended before the next request was complete.
+  pub fn closed_error(&self) -> ReadError {
+    if self.is_idle() {
+      return ReadError::ConnectionClosed;
+    }
+    match HttpRequest::parse(&self.buffer) {
+      Err(e) => ReadError::Parse(e),
+      Ok(_) => ReadError::ConnectionClosed,
+    }
+  }
+
+  /// Gets the error for a stream that took too long to send more bytes: an idle
+  /// connection if no request was started, otherwise a slow request.
+  pub fn timeout_error(&self) -> ReadError {
+    if self.is_idle() {
+      ReadError::IdleTimeout
+    } else {
+      ReadError::Timeout
+    }
+  }
+
+  /// Checks the sizes known so far of the request being read against the limits.
+  ///
+  /// # Arguments
+  ///
+  /// * `head_length`: Length of the request line and headers, if they are complete.
+  /// * `body_length`: Length of the body, if it is known.
+  fn check_limits(
+    &self,
+    head_length: Option<usize>,
+    body_length: Option<usize>,
+  ) -> Result<(), ReadError> {
+    if head_length.unwrap_or(self.buffer.len()) > self.limits.max_header_size {
+      return Err(ReadError::HeadersTooLarge);
+    }
+    if body_length.unwrap_or(0) > self.limits.max_body_size {
+      return Err(ReadError::BodyTooLarge);
+    }
+    Ok(())
+  }
+
+  /// Reads one more chunk from the stream into the buffer and returns its size.
+  fn fill(
+    &mut self,
+    stream: &mut impl Read,
+  ) -> Result<usize, ReadError> {
+    let mut chunk: [u8; READ_CHUNK_SIZE] = [0; READ_CHUNK_SIZE];
+    let bytes_count: usize = loop {
+      match stream.read(&mut chunk) {
+        Ok(count) => break count,
+        Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+        // A read timeout is reported as one of these kinds depending on the platform
+        Err(e)
+          if matches!(
+            e.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+          ) =>
+        {
+          return Err(self.timeout_error());
+        }
+        Err(e) => return Err(e.into()),
+      }
+    };
+    self.push(&chunk[..bytes_count]);
+    Ok(bytes_count)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use http::http_request::Method;
+
+  use super::*;
+
+  /// Stream that hands out its data in small pieces, like a slow client.
+  struct SlowStream {
+    data: Vec<u8>,
+    position: usize,
+  }
+
+  impl Read for SlowStream {
+    fn read(
+      &mut self,
+      buf: &mut [u8],
+    ) -> io::Result<usize> {
+      let end = (self.position + 7)
+        .min(self.data.len())
+        .min(self.position + buf.len());
+      let count = end - self.position;
+      buf[..count].copy_from_slice(&self.data[self.position..end]);
+      self.position = end;
+      Ok(count)
+    }
+  }
+
+  fn slow(data: &[u8]) -> SlowStream {
+    SlowStream {
+      data: data.to_vec(),
+      position: 0,
+    }
+  }
+
+  #[test]
+  fn test_read_request_in_pieces() {
+    let mut stream = slow(
+      b"POST /api HTTP/1.1\r\nHost: localhost\r\ncontent-length: 12\r\n\r\nHello World!",
+    );
+    let mut reader = RequestReader::new(RequestLimits::default());
+
+    let req = reader.read_request(&mut stream).unwrap();
+
+    assert_eq!(req.method, Method::POST);
+    assert_eq!(req.resource.path, "/api");
+    assert_eq!(req.message_body, b"Hello World!");
+  }
+
+  #[test]
+  fn test_read_chunked_request() {
+    let mut stream = slow(
+      b"POST /api HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n6\r\nHello \r\n6\r\nWorld!\r\n0\r\nDone: yes\r\n\r\nGET / HTTP/1.1\r\n\r\n",
+    );
+    let mut reader = RequestReader::new(RequestLimits::default());
+
+    let req = reader.read_request(&mut stream).unwrap();
+    assert_eq!(req.message_body, b"Hello World!");
+    assert_eq!(req.trailers.get("Done"), Some("yes"));
+    assert_eq!(reader.read_request(&mut stream).unwrap().resource.path, "/");
+
+    // The limit applies to the chunks announced so far
+    let limits = RequestLimits {
+      max_header_size: 100,
+      max_body_size: 8,
+    };
+    let mut stream =
+      slow(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n4\r\nabcd\r\n5\r\n");
+    let err = RequestReader::new(limits)
+      .read_request(&mut stream)
+      .unwrap_err();
+    assert_eq!(err.status_code(), Some(StatusCode::CONTENT_TOO_LARGE));
+  }
+
+  #[test]
+  fn test_read_request_keeps_extra_bytes() {
+    let mut stream = slow(b"GET /a HTTP/1.1\r\n\r\nGET /b HTTP/1.1\r\n\r\n");
+    let mut reader = RequestReader::new(RequestLimits::default());
+
+    let first = reader.read_request(&mut stream).unwrap();
+    let second = reader.read_request(&mut stream).unwrap();
+
+    assert_eq!(first.resource.path, "/a");
+    assert_eq!(second.resource.path, "/b");
+    assert!(matches!(
+      reader.read_request(&mut stream),
+      Err(ReadError::ConnectionClosed)
+    ));
+  }
+
+  #[test]
+  fn test_read_request_limits() {
+    let limits = RequestLimits {
+      max_header_size: 40,
+      max_body_size: 4,
+    };
+
+    let mut stream =
+      slow(b"GET / HTTP/1.1\r\nUser-Agent: a very long user agent\r\n\r\n");
+    let err = RequestReader::new(limits)
+      .read_request(&mut stream)
+      .unwrap_err();
+    assert_eq!(
+      err.status_code(),
+      Some(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE)
+    );
+
+    let mut stream = slow(b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nHello");
+    let err = RequestReader::new(limits)
+      .read_request(&mut stream)
+      .unwrap_err();
+    assert_eq!(err.status_code(), Some(StatusCode::CONTENT_TOO_LARGE));
+  }
+
+  #[test]
+  fn test_read_request_malformed() {
+    let malformed: [&[u8]; 4] = [
+      b"GET HTTP/1.1\r\n\r\n",
+      b"GET / HTTP/1.1\r\nContent-Length: abc\r\n\r\n",
+      b"GET /\xff HTTP/1.1\r\n\r\n",
+      b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort",
+    ];
+
+    for bytes in malformed {
+      let err = RequestReader::new(RequestLimits::default())
+        .read_request(&mut slow(bytes))
+        .unwrap_err();
+      assert_eq!(err.status_code(), Some(StatusCode::BAD_REQUEST));
+    }
+
+    let mut stream = slow(b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n");
+    let err = RequestReader::new(RequestLimits::default())
+      .read_request(&mut stream)
+      .unwrap_err();
+    assert_eq!(err.status_code(), Some(StatusCode::NOT_IMPLEMENTED));
+  }
+}
@@ -1,51 +1,583 @@
-use http::{
-  http_request::{HttpRequest, Method, Resource},
-  http_response::HttpResponse,
-};
+use std::collections::HashMap;
 
-use std::io::prelude::*;
+use http::body::Body;
+use http::header_map::HeaderMap;
+use http::http_request::{HttpRequest, Method};
+use http::http_response::HttpResponse;
+use http::status_code::StatusCode;
+use http::websocket::{self, Stream, WebSocket};
 
+use crate::config::Mode;
 use crate::handler::{
-  Handler, PageNotFoundHandler, StaticPageHandler, WebServiceHandler,
+  EchoHandler, Handler, OrderEventsHandler, PageNotFoundHandler, StaticPageHandler,
+  WebServiceHandler, WebSocketHandler,
 };
 
+/// Function serving the requests of a route.
+pub type HandlerFn = fn(&HttpRequest) -> HttpResponse;
+
+/// Function serving the WebSocket connections of a route, once upgraded.
+pub type WebSocketFn = fn(&HttpRequest, &mut WebSocket<&mut dyn Stream>);
+
+/// Represents a segment of a route pattern.
+#[derive(Debug, PartialEq, Clone)]
+enum PatternSegment {
+  /// Segment that must be equal to the given text.
+  Literal(String),
+  /// Segment captured as a path parameter (`{name}`).
+  Param(String),
+  /// Remaining segments, possibly none, captured as a single path parameter
+  /// (`{*name}`).
+  Rest(String),
+}
+
+/// Represents a route pattern such as `/api/shipping/orders/{order_id}`.
+///
+/// Patterns are matched against the decoded path segments of a request, so empty
+/// segments and trailing slashes are ignored: `/api/shipping/orders/` matches the
+/// pattern `/api/shipping/orders`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RoutePattern {
+  /// Segments of the pattern, without the empty ones.
+  segments: Vec<PatternSegment>,
+}
+
+impl RoutePattern {
+  /// Creates a [`RoutePattern`] object from its textual form.
+  ///
+  /// # Arguments
+  ///
+  /// * `pattern`: Pattern with literal segments, `{name}` parameters and an optional
+  ///   final `{*name}` parameter taking the rest of the path.
+  ///
+  /// # Panics
+  ///
+  /// Panics if a `{*name}` parameter is not the last segment, as this is a mistake in
+  /// the route table itself.
+  pub fn parse(pattern: &str) -> RoutePattern {
+    RoutePattern::try_parse(pattern).unwrap_or_else(|e| panic!("{}", e))
+  }
+
+  /// Creates a [`RoutePattern`] object from its textual form, as
+  /// [`RoutePattern::parse`] does, but returns an error instead of panicking. Meant for
+  /// patterns read from the configuration.
+  ///
+  /// # Arguments
+  ///
+  /// * `pattern`: Pattern with literal segments, `{name}` parameters and an optional
+  ///   final `{*name}` parameter taking the rest of the path.
+  pub fn try_parse(pattern: &str) -> Result<RoutePattern, String> {
+    let segments: Vec<PatternSegment> = pattern
+      .split('/')
+      .filter(|s| !s.is_empty())
+      .map(
+        |s| match s.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+          Some(name) => match name.strip_prefix('*') {
+            Some(name) => PatternSegment::Rest(name.to_string()),
+            None => PatternSegment::Param(name.to_string()),
+          },
+          None => PatternSegment::Literal(s.to_string()),
+        },
+      )
+      .collect();
+
+    let rest_position: Option<usize> = segments
+      .iter()
+      .position(|s| matches!(s, PatternSegment::Rest(_)));
+    if matches!(rest_position, Some(p) if p + 1 != segments.len()) {
+      return Err(format!(
+        "`{{*name}}` must be the last segment of the route `{}`",
+        pattern
+      ));
+    }
+
+    Ok(RoutePattern { segments })
+  }
+
+  /// Matches the given path segments and returns the path parameters captured.
+  ///
+  /// # Arguments
+  ///
+  /// * `path`: Decoded, non-empty segments of the request path.
+  pub fn matches(
+    &self,
+    path: &[&str],
+  ) -> Option<HashMap<String, String>> {
+    let mut params: HashMap<String, String> = HashMap::new();
+
+    for (index, segment) in self.segments.iter().enumerate() {
+      match segment {
+        PatternSegment::Rest(name) => {
+          params.insert(name.clone(), path.get(index..)?.join("/"));
+          return Some(params);
+        }
+        PatternSegment::Literal(text) if path.get(index)? != text => return None,
+        PatternSegment::Literal(_) => {}
+        PatternSegment::Param(name) => {
+          params.insert(name.clone(), path.get(index)?.to_string());
+        }
+      }
+    }
+
+    (path.len() == self.segments.len()).then_some(params)
+  }
+}
+
+/// Represents the result of looking up a request in a [`RouteTable`].
+#[derive(Debug, PartialEq)]
+pub enum RouteMatch<H> {
+  /// A route accepts the request: its handler and the path parameters captured.
+  Found(H, HashMap<String, String>),
+  /// Some routes match the path, but none accepts the method. Holds the methods
+  /// allowed on the path.
+  MethodNotAllowed(Vec<Method>),
+  /// No route matches the path.
+  NotFound,
+}
+
+/// Represents a set of routes, each one with a method, a pattern and a handler.
+///
+/// Routes are tried in the order they were added, so more specific patterns must be
+/// added before the general ones (e.g. `{*path}`). A GET route also serves HEAD
+/// requests.
+#[derive(Debug, Clone)]
+pub struct RouteTable<H> {
+  /// Method, pattern and handler of each route, in registration order.
+  routes: Vec<(Method, RoutePattern, H)>,
+}
+
+impl<H: Copy> RouteTable<H> {
+  /// Creates an empty [`RouteTable`] object.
+  pub fn new() -> Self {
+    Self { routes: Vec::new() }
+  }
+
+  /// Adds a route.
+  ///
+  /// # Arguments
+  ///
+  /// * `method`: HTTP method served by the route.
+  /// * `pattern`: Pattern of the paths served by the route.
+  /// * `handler`: Handler of the requests.
+  pub fn add(
+    &mut self,
+    method: Method,
+    pattern: &str,
+    handler: H,
+  ) {
+    self
+      .routes
+      .push((method, RoutePattern::parse(pattern), handler));
+  }
+
+  /// Finds the route for the given method and path.
+  ///
+  /// # Arguments
+  ///
+  /// * `method`: HTTP method of the request.
+  /// * `path`: Decoded, non-empty segments of the request path.
+  pub fn find(
+    &self,
+    method: &Method,
+    path: &[&str],
+  ) -> RouteMatch<H> {
+    let mut allowed: Vec<Method> = Vec::new();
+
+    for (route_method, pattern, handler) in &self.routes {
+      let Some(params) = pattern.matches(path) else {
+        continue;
+      };
+      if route_method == method
+        || (method == &Method::HEAD && route_method == &Method::GET)
+      {
+        return RouteMatch::Found(*handler, params);
+      }
+      if !allowed.contains(route_method) {
+        allowed.push(route_method.clone());
+      }
+    }
+
+    if allowed.is_empty() {
+      return RouteMatch::NotFound;
+    }
+    if allowed.contains(&Method::GET) && !allowed.contains(&Method::HEAD) {
+      allowed.push(Method::HEAD);
+    }
+    if !allowed.contains(&Method::OPTIONS) {
+      allowed.push(Method::OPTIONS);
+    }
+    RouteMatch::MethodNotAllowed(allowed)
+  }
+}
+
+impl<H: Copy> Default for RouteTable<H> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
 /// Represents a router to process requests.
-pub struct Router;
+#[derive(Debug, Clone)]
+pub struct Router {
+  /// Routes served.
+  table: RouteTable<HandlerFn>,
+  /// Routes upgraded to WebSocket connections.
+  websockets: RouteTable<WebSocketFn>,
+}
 
 impl Router {
+  /// Creates a [`Router`] object without routes.
+  pub fn new() -> Self {
+    Self {
+      table: RouteTable::new(),
+      websockets: RouteTable::new(),
+    }
+  }
+
+  /// Adds a route.
+  ///
+  /// # Arguments
+  ///
+  /// * `method`: HTTP method served by the route.
+  /// * `pattern`: Pattern of the paths served by the route, e.g. `/orders/{order_id}`.
+  /// * `handler`: Handler of the requests.
+  pub fn add(
+    mut self,
+    method: Method,
+    pattern: &str,
+    handler: HandlerFn,
+  ) -> Self {
+    self.table.add(method, pattern, handler);
+    self
+  }
+
+  /// Adds a route upgrading its GET requests to WebSocket connections.
+  ///
+  /// # Arguments
+  ///
+  /// * `pattern`: Pattern of the paths served by the route, e.g. `/ws/echo`.
+  /// * `handler`: Handler of the connections.
+  pub fn websocket(
+    mut self,
+    pattern: &str,
+    handler: WebSocketFn,
+  ) -> Self {
+    self.websockets.add(Method::GET, pattern, handler);
+    self
+  }
+
+  /// Finds the handler of the WebSocket route matching the given request, if any.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request upgraded.
+  pub fn websocket_handler(
+    &self,
+    request: &HttpRequest,
+  ) -> Option<WebSocketFn> {
+    match self
+      .websockets
+      .find(&Method::GET, &request.resource.segments())
+    {
+      RouteMatch::Found(handler, _) => Some(handler),
+      _ => None,
+    }
+  }
+
+  /// Routes the given request to the appropiate handler and returns its response. The
+  /// path parameters of the route are stored in the request.
+  ///
+  /// HEAD requests get the response of a GET request, so the caller must send only its
+  /// head. Requests to a WebSocket route get the response to their opening handshake;
+  /// after a "101 Switching Protocols" response, the caller hands the connection to the
+  /// handler from [`Router::websocket_handler`].
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request to route.
   pub fn route(
-    request: HttpRequest,
-    stream: &mut impl Write,
-  ) -> () {
-    match request.method {
-      // Process GET requests
-      Method::GET => {
-        // Route according to the resource requested
-        match &request.resource {
-          Resource::Path(p) => {
-            let route: Vec<&str> = p.split("/").collect();
-
-            match route[1] {
-              // Process a request to the API (/api)
-              "api" => {
-                // Invoke the web service
-                let response: HttpResponse = WebServiceHandler::handle(&request);
-                let _ = response.send_response(stream);
-              }
-              // Process a requet to the page handler (/**)
-              _ => {
-                let response: HttpResponse = StaticPageHandler::handle(&request);
-                let _ = response.send_response(stream);
-              }
-            }
-          }
-        }
-      } // end match GET
-      // Any other method is regarded as not found
-      _ => {
-        let response: HttpResponse = PageNotFoundHandler::handle(&request);
-        let _ = response.send_response(stream);
+    &self,
+    request: &mut HttpRequest,
+  ) -> HttpResponse {
+    match self
+      .websockets
+      .find(&request.method, &request.resource.segments())
+    {
+      RouteMatch::Found(_, params) => {
+        request.path_params = params;
+        return websocket::handshake(request).unwrap_or_else(|e| e.response());
+      }
+      RouteMatch::MethodNotAllowed(allowed) => {
+        return Self::allowed_methods(&request.method, &allowed);
+      }
+      RouteMatch::NotFound => {}
+    }
+
+    match self
+      .table
+      .find(&request.method, &request.resource.segments())
+    {
+      RouteMatch::Found(handler, params) => {
+        request.path_params = params;
+        handler(request)
       }
+      RouteMatch::MethodNotAllowed(allowed) => {
+        Self::allowed_methods(&request.method, &allowed)
+      }
+      RouteMatch::NotFound => PageNotFoundHandler::handle(request),
     }
   } // end fn route()
+
+  /// Creates the response listing the methods allowed on an existing resource: an empty
+  /// "200 OK" for OPTIONS, or "405 Method Not Allowed" for any other method.
+  ///
+  /// # Arguments
+  ///
+  /// * `method`: HTTP method of the request.
+  /// * `allowed`: Methods allowed on the resource.
+  pub fn allowed_methods(
+    method: &Method,
+    allowed: &[Method],
+  ) -> HttpResponse {
+    let allow: Vec<&str> = allowed.iter().map(Method::as_str).collect();
+    let mut headers: HeaderMap = HeaderMap::new();
+    headers.insert("Allow", &allow.join(", "));
+
+    match method {
+      Method::OPTIONS => HttpResponse::new(StatusCode::OK, Some(headers), Body::Empty),
+      _ => {
+        headers.insert("Content-Type", "text/html");
+        HttpResponse::new(StatusCode::METHOD_NOT_ALLOWED, Some(headers), Body::Empty)
+      }
+    }
+  }
+}
+
+/// Gets a handler of the application by the name routes in the configuration use for
+/// it: `orders` (the shipping orders API), `order_events` (the stream of their
+/// changes), `static` (the files of the public directory) or `not_found`.
+///
+/// # Arguments
+///
+/// * `name`: Name of the handler.
+pub fn named_handler(name: &str) -> Option<HandlerFn> {
+  match name {
+    "orders" => Some(WebServiceHandler::handle),
+    "order_events" => Some(OrderEventsHandler::handle),
+    "static" => Some(StaticPageHandler::handle),
+    "not_found" => Some(PageNotFoundHandler::handle),
+    _ => None,
+  }
+}
+
+/// Gets a WebSocket handler of the application by the name routes in the configuration
+/// use for it: `echo`.
+///
+/// # Arguments
+///
+/// * `name`: Name of the handler.
+pub fn named_websocket_handler(name: &str) -> Option<WebSocketFn> {
+  match name {
+    "echo" => Some(EchoHandler::serve),
+    _ => None,
+  }
+}
+
+impl Router {
+  /// Creates the router with the routes of the application as served in the given
+  /// mode. Streams of order events and WebSocket connections would each hold a thread
+  /// for blocking calls, so the async mode answers them with "404 Not Found".
+  ///
+  /// # Arguments
+  ///
+  /// * `mode`: How the connections are served.
+  pub fn application(mode: Mode) -> Router {
+    let streams: bool = mode == Mode::Threads;
+    let order_events: HandlerFn = match streams {
+      true => OrderEventsHandler::handle,
+      false => Self::threads_only,
+    };
+
+    let router: Router = Router::new()
+      // Process requests to the API
+      .add(
+        Method::GET,
+        "/api/shipping/orders",
+        WebServiceHandler::handle,
+      )
+      .add(
+        Method::POST,
+        "/api/shipping/orders",
+        WebServiceHandler::handle,
+      )
+      // Stream the changes of the orders, before the path is taken as an order ID
+      .add(Method::GET, "/api/shipping/orders/stream", order_events)
+      .add(
+        Method::GET,
+        "/api/shipping/orders/{order_id}",
+        WebServiceHandler::handle,
+      )
+      .add(
+        Method::PUT,
+        "/api/shipping/orders/{order_id}",
+        WebServiceHandler::handle,
+      )
+      .add(
+        Method::PATCH,
+        "/api/shipping/orders/{order_id}",
+        WebServiceHandler::handle,
+      )
+      .add(
+        Method::DELETE,
+        "/api/shipping/orders/{order_id}",
+        WebServiceHandler::handle,
+      )
+      // Process requests to the page handler (/**)
+      .add(Method::GET, "/{*path}", StaticPageHandler::handle);
+
+    // Echo the messages of WebSocket clients
+    match streams {
+      true => router.websocket("/ws/echo", EchoHandler::serve),
+      false => router.add(Method::GET, "/ws/echo", Self::threads_only),
+    }
+  } // end fn application()
+
+  /// Answers a request for a route only served in the threads mode with "404 Not
+  /// Found", noting why.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request received in async mode.
+  fn threads_only(request: &HttpRequest) -> HttpResponse {
+    info!(
+      "{} is only served in the threads mode",
+      request.resource.path
+    );
+    PageNotFoundHandler::handle(request)
+  }
+}
+
+impl Default for Router {
+  /// Creates the router with the routes of the application served in the threads mode.
+  fn default() -> Self {
+    Router::application(Mode::Threads)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn request(text: &str) -> HttpRequest {
+    HttpRequest::parse(text.as_bytes()).unwrap().0
+  }
+
+  #[test]
+  fn test_pattern_params() {
+    let pattern = RoutePattern::parse("/api/shipping/orders/{order_id}");
+    let path = request("GET /api/shipping/orders/7/ HTTP/1.1\r\n\r\n").resource;
+
+    let params = pattern.matches(&path.segments()).unwrap();
+    assert_eq!(params.get("order_id").map(String::as_str), Some("7"));
+    assert_eq!(pattern.matches(&["api", "shipping", "orders"]), None);
+    assert_eq!(
+      pattern.matches(&["api", "shipping", "orders", "7", "x"]),
+      None
+    );
+    assert_eq!(pattern.matches(&["api", "billing", "orders", "7"]), None);
+  }
+
+  #[test]
+  fn test_pattern_rest() {
+    let pattern = RoutePattern::parse("/static/{*path}");
+
+    let params = pattern.matches(&["static", "css", "styles.css"]).unwrap();
+    assert_eq!(params["path"], "css/styles.css");
+    assert_eq!(pattern.matches(&["static"]).unwrap()["path"], "");
+    assert_eq!(pattern.matches(&[]), None);
+  }
+
+  #[test]
+  #[should_panic]
+  fn test_pattern_rest_not_last() {
+    RoutePattern::parse("/{*path}/edit");
+  }
+
+  #[test]
+  fn test_pattern_try_parse() {
+    assert!(RoutePattern::try_parse("/{*path}/edit").is_err());
+    assert_eq!(
+      RoutePattern::try_parse("/a/{b}"),
+      Ok(RoutePattern::parse("a/{b}/"))
+    );
+    assert!(named_handler("orders").is_some());
+    assert!(named_handler("shell").is_none());
+  }
+
+  #[test]
+  fn test_route_table_methods() {
+    let mut table: RouteTable<u8> = RouteTable::new();
+    table.add(Method::GET, "/orders", 1);
+    table.add(Method::POST, "/orders", 2);
+    table.add(Method::GET, "/orders/{id}", 3);
+
+    assert_eq!(
+      table.find(&Method::HEAD, &["orders"]),
+      RouteMatch::Found(1, HashMap::new())
+    );
+    assert_eq!(
+      table.find(&Method::POST, &["orders"]),
+      RouteMatch::Found(2, HashMap::new())
+    );
+    assert_eq!(
+      table.find(&Method::DELETE, &["orders", "1"]),
+      RouteMatch::MethodNotAllowed(vec![Method::GET, Method::HEAD, Method::OPTIONS])
+    );
+    assert_eq!(
+      table.find(&Method::GET, &["customers"]),
+      RouteMatch::NotFound
+    );
+  }
+
+  #[test]
+  fn test_router_responses() {
+    let router = Router::default();
+
+    let mut req = request("GET /api/shipping/orders/2 HTTP/1.1\r\n\r\n");
+    let response = router.route(&mut req);
+    assert_eq!(response.status_code(), &StatusCode::OK);
+    assert_eq!(req.path_param("order_id"), Some("2"));
+
+    let response =
+      router.route(&mut request("GET /api/shipping/orders/x HTTP/1.1\r\n\r\n"));
+    assert_eq!(response.status_code(), &StatusCode::BAD_REQUEST);
+
+    let response =
+      router.route(&mut request("GET /api/shipping/orders/99 HTTP/1.1\r\n\r\n"));
+    assert_eq!(response.status_code(), &StatusCode::NOT_FOUND);
+
+    let response =
+      router.route(&mut request("PUT /api/shipping/orders HTTP/1.1\r\n\r\n"));
+    assert_eq!(response.status_code(), &StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(response.header("Allow"), Some("GET, POST, HEAD, OPTIONS"));
+
+    let response = router.route(&mut request("POST /ws/echo HTTP/1.1\r\n\r\n"));
+    assert_eq!(response.status_code(), &StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(response.header("Allow"), Some("GET, HEAD, OPTIONS"));
+
+    let response = router.route(&mut request("OPTIONS / HTTP/1.1\r\n\r\n"));
+    assert_eq!(response.status_code(), &StatusCode::OK);
+
+    let router = Router::application(Mode::Async);
+    let response = router.route(&mut request(
+      "GET /api/shipping/orders/stream HTTP/1.1\r\n\r\n",
+    ));
+    assert_eq!(response.status_code(), &StatusCode::NOT_FOUND);
+    let response = router.route(&mut request("GET /ws/echo HTTP/1.1\r\n\r\n"));
+    assert_eq!(response.status_code(), &StatusCode::NOT_FOUND);
+
+    let router = Router::new().add(Method::GET, "/health", StaticPageHandler::handle);
+    let response = router.route(&mut request("GET /nope HTTP/1.1\r\n\r\n"));
+    assert_eq!(response.status_code(), &StatusCode::NOT_FOUND);
+  }
 }
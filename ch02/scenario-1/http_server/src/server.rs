@@ -1,46 +1,1011 @@
-use std::{net::{TcpListener, TcpStream}, io::Read};
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+use std::{process, thread};
 
-use http::http_request::HttpRequest;
+use http::body::Body;
+use http::http_request::{HttpRequest, Method, Version};
+use http::http_response::HttpResponse;
+use http::sse;
+use http::status_code::StatusCode;
+use http::websocket::{Role, Stream, WebSocket};
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
 
-use crate::router::Router;
+use crate::middleware::{self, Chain, Compression, Context};
+use crate::order_events;
+use crate::reader::{ReadError, RequestLimits, RequestReader};
+use crate::router::WebSocketFn;
+use crate::thread_pool::ThreadPool;
+#[cfg(feature = "tls")]
+use crate::tls;
+use crate::virtual_host::VirtualHosts;
+
+/// Represents the settings of persistent connections.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeepAlive {
+  /// Time to wait for the next request on an idle connection.
+  pub idle_timeout: Duration,
+  /// Maximum number of requests served on a single connection.
+  pub max_requests: usize,
+}
+
+impl Default for KeepAlive {
+  fn default() -> Self {
+    Self {
+      idle_timeout: Duration::from_secs(5),
+      max_requests: 100,
+    }
+  }
+}
+
+/// Represents the settings of the worker threads that serve connections.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Workers {
+  /// Number of worker threads.
+  pub count: usize,
+  /// Number of accepted connections that can wait for a free worker.
+  pub queue_depth: usize,
+}
+
+impl Default for Workers {
+  fn default() -> Self {
+    Self {
+      count: thread::available_parallelism().map_or(4, |n| n.get()),
+      queue_depth: 64,
+    }
+  }
+}
+
+/// Default maximum number of connections upgraded to WebSocket at once.
+pub const DEFAULT_MAX_WEBSOCKETS: usize = 256;
+
+/// Default maximum number of streams of server-sent events open at once.
+pub const DEFAULT_MAX_EVENT_STREAMS: usize = 256;
+
+/// Represents a limited number of connections served on threads of their own, out of
+/// the worker pool, e.g. the ones upgraded to WebSocket.
+struct Slots {
+  /// Maximum number of connections.
+  limit: usize,
+  /// Number of connections being served.
+  taken: Mutex<usize>,
+  /// Signaled when a connection ends.
+  released: Condvar,
+}
+
+impl Slots {
+  /// Creates a new [`Slots`] object.
+  ///
+  /// # Argument
+  ///
+  /// * `limit`: Maximum number of connections.
+  fn new(limit: usize) -> Arc<Self> {
+    Arc::new(Self {
+      limit,
+      taken: Mutex::new(0),
+      released: Condvar::new(),
+    })
+  }
+
+  /// Takes a slot for a connection, unless every one is taken. The slot is released
+  /// when dropped.
+  fn take(self: &Arc<Self>) -> Option<Slot> {
+    let mut taken = self.taken.lock().unwrap();
+    if *taken >= self.limit {
+      return None;
+    }
+    *taken += 1;
+    Some(Slot(Arc::clone(self)))
+  }
+
+  /// Waits until every connection ends.
+  fn wait_all(&self) {
+    let taken = self.taken.lock().unwrap();
+    drop(self.released.wait_while(taken, |taken| *taken > 0).unwrap());
+  }
+}
+
+/// Represents the slot of a connection served on a thread of its own.
+struct Slot(Arc<Slots>);
+
+impl Drop for Slot {
+  fn drop(&mut self) {
+    *self.0.taken.lock().unwrap() -= 1;
+    self.0.released.notify_all();
+  }
+}
+
+/// Represents a long-lived connection, to be served on a thread of its own.
+enum Handoff {
+  /// Connection upgraded to WebSocket.
+  WebSocket {
+    /// Request that opened the connection.
+    request: HttpRequest,
+    /// Handler of the connection.
+    handler: WebSocketFn,
+    /// Slot taken by the connection.
+    slot: Slot,
+  },
+  /// Connection streaming server-sent events until the stream ends.
+  EventStream {
+    /// Response whose body is the stream.
+    response: HttpResponse,
+    /// Slot taken by the connection.
+    slot: Slot,
+  },
+}
 
 /// Represents a server.
 pub struct Server<'a> {
-  /// Socket address to listen connections.
-  socket_address: &'a str,
+  /// Socket addresses to listen connections.
+  socket_addresses: Vec<&'a str>,
+  /// Size limits for the incoming requests.
+  limits: RequestLimits,
+  /// Settings of persistent connections.
+  keep_alive: KeepAlive,
+  /// Settings of the worker threads.
+  workers: Workers,
+  /// Maximum number of connections upgraded to WebSocket at once.
+  max_websockets: usize,
+  /// Maximum number of streams of server-sent events open at once.
+  max_event_streams: usize,
+  /// Sites served, each with its routes.
+  hosts: Arc<VirtualHosts>,
+  /// Middlewares run around the routing of every request.
+  middlewares: Arc<Chain>,
+  /// TLS settings, when the server speaks HTTPS.
+  #[cfg(feature = "tls")]
+  tls: Option<Arc<rustls::ServerConfig>>,
+  /// Socket address of the plaintext listener redirecting to HTTPS, if any.
+  #[cfg(feature = "tls")]
+  https_redirect: Option<String>,
 }
 
 impl<'a> Server<'a> {
   /// Creates a new [`Server`] object.
-  /// 
+  ///
   /// # Argument
-  /// 
+  ///
   /// * `socket_address`: Socket address to listen new connections.
   pub fn new(socket_address: &'a str) -> Self {
     Self {
-      socket_address: socket_address,
+      socket_addresses: vec![socket_address],
+      limits: RequestLimits::default(),
+      keep_alive: KeepAlive::default(),
+      workers: Workers::default(),
+      max_websockets: DEFAULT_MAX_WEBSOCKETS,
+      max_event_streams: DEFAULT_MAX_EVENT_STREAMS,
+      hosts: Arc::new(VirtualHosts::default()),
+      middlewares: Arc::new(middleware::default_chain(Compression::default())),
+      #[cfg(feature = "tls")]
+      tls: None,
+      #[cfg(feature = "tls")]
+      https_redirect: None,
+    }
+  }
+
+  /// Listens for connections on another socket address too.
+  ///
+  /// # Argument
+  ///
+  /// * `socket_address`: Additional socket address to listen new connections.
+  pub fn with_address(
+    mut self,
+    socket_address: &'a str,
+  ) -> Self {
+    self.socket_addresses.push(socket_address);
+    self
+  }
+
+  /// Sets the size limits for the incoming requests.
+  ///
+  /// # Argument
+  ///
+  /// * `limits`: Maximum sizes for the headers and the body of a request.
+  pub fn with_limits(
+    mut self,
+    limits: RequestLimits,
+  ) -> Self {
+    self.limits = limits;
+    self
+  }
+
+  /// Sets the settings of persistent connections.
+  ///
+  /// # Argument
+  ///
+  /// * `keep_alive`: Idle timeout and maximum number of requests per connection.
+  pub fn with_keep_alive(
+    mut self,
+    keep_alive: KeepAlive,
+  ) -> Self {
+    self.keep_alive = keep_alive;
+    self
+  }
+
+  /// Sets the settings of the worker threads.
+  ///
+  /// # Argument
+  ///
+  /// * `workers`: Number of worker threads and depth of their queue.
+  pub fn with_workers(
+    mut self,
+    workers: Workers,
+  ) -> Self {
+    self.workers = workers;
+    self
+  }
+
+  /// Sets the maximum number of connections upgraded to WebSocket at once. Each one is
+  /// served on a thread of its own, so it does not hold a worker; the upgrades past the
+  /// limit are answered with "503 Service Unavailable".
+  ///
+  /// # Argument
+  ///
+  /// * `max_websockets`: Maximum number of WebSocket connections.
+  pub fn with_max_websockets(
+    mut self,
+    max_websockets: usize,
+  ) -> Self {
+    self.max_websockets = max_websockets;
+    self
+  }
+
+  /// Sets the maximum number of streams of server-sent events open at once. Each one is
+  /// sent on a thread of its own, so it does not hold a worker; the streams past the
+  /// limit are answered with "503 Service Unavailable".
+  ///
+  /// # Argument
+  ///
+  /// * `max_event_streams`: Maximum number of event streams.
+  pub fn with_max_event_streams(
+    mut self,
+    max_event_streams: usize,
+  ) -> Self {
+    self.max_event_streams = max_event_streams;
+    self
+  }
+
+  /// Sets the sites served, chosen by the `Host` header of the requests.
+  ///
+  /// # Argument
+  ///
+  /// * `hosts`: Sites, each with its routes and public directory.
+  pub fn with_virtual_hosts(
+    mut self,
+    hosts: VirtualHosts,
+  ) -> Self {
+    self.hosts = Arc::new(hosts);
+    self
+  }
+
+  /// Sets the middlewares run around the routing of every request.
+  ///
+  /// # Argument
+  ///
+  /// * `middlewares`: Middlewares, from the outermost to the innermost.
+  pub fn with_middlewares(
+    mut self,
+    middlewares: Chain,
+  ) -> Self {
+    self.middlewares = Arc::new(middlewares);
+    self
+  }
+
+  /// Serves HTTPS instead of plaintext HTTP.
+  ///
+  /// # Argument
+  ///
+  /// * `config`: TLS settings, e.g. loaded with [`tls::load_config`].
+  #[cfg(feature = "tls")]
+  pub fn with_tls(
+    mut self,
+    config: Arc<rustls::ServerConfig>,
+  ) -> Self {
+    self.tls = Some(config);
+    self
+  }
+
+  /// Listens for plaintext connections on another address and redirects their requests
+  /// to HTTPS. Only used along with [`Server::with_tls`].
+  ///
+  /// # Argument
+  ///
+  /// * `socket_address`: Socket address of the plaintext listener.
+  #[cfg(feature = "tls")]
+  pub fn with_https_redirect(
+    mut self,
+    socket_address: &str,
+  ) -> Self {
+    self.https_redirect = Some(socket_address.to_string());
+    self
+  }
+
+  /// Runs the server until it receives SIGINT or SIGTERM, and then waits for the
+  /// requests in progress to finish. Every listener shares the same worker threads.
+  ///
+  /// Returns an error if the server cannot start, e.g. when an address is in use.
+  pub fn run(&self) -> io::Result<()> {
+    // Start the server on the socket addresses
+    let listeners: Vec<TcpListener> = self
+      .socket_addresses
+      .iter()
+      .map(|address| TcpListener::bind(address).map_err(|e| listen_error(address, e)))
+      .collect::<io::Result<_>>()?;
+
+    for address in &self.socket_addresses {
+      info!("Server running on {}", address);
+    }
+
+    #[cfg(feature = "tls")]
+    if let (Some(_), Some(address)) = (&self.tls, &self.https_redirect) {
+      let https_port: u16 = listeners[0].local_addr()?.port();
+      tls::run_redirect(address, https_port).map_err(|e| listen_error(address, e))?;
+    }
+
+    let shutdown: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+    Self::watch_signals(
+      listeners
+        .iter()
+        .map(TcpListener::local_addr)
+        .collect::<io::Result<_>>()?,
+      Arc::clone(&shutdown),
+    )?;
+
+    let websockets: Arc<Slots> = Slots::new(self.max_websockets);
+    let event_streams: Arc<Slots> = Slots::new(self.max_event_streams);
+    let handler: ConnectionHandler = ConnectionHandler {
+      limits: self.limits,
+      keep_alive: self.keep_alive,
+      shutdown: Arc::clone(&shutdown),
+      hosts: Arc::clone(&self.hosts),
+      middlewares: Arc::clone(&self.middlewares),
+      websockets: Arc::clone(&websockets),
+      event_streams: Arc::clone(&event_streams),
+      #[cfg(feature = "tls")]
+      tls: self.tls.clone(),
+    };
+    let pool: ThreadPool<TcpStream> = ThreadPool::new(
+      self.workers.count,
+      self.workers.queue_depth,
+      move |stream| handler.handle(stream),
+    );
+
+    thread::scope(|scope| {
+      for listener in &listeners {
+        scope.spawn(|| Self::accept_connections(listener, &pool, &shutdown));
+      }
+    });
+
+    info!("Shutting down, waiting for the requests in progress...");
+    order_events::close();
+    drop(pool);
+    websockets.wait_all();
+    event_streams.wait_all();
+    info!("Server stopped");
+    Ok(())
+  } // end fn run()
+
+  /// Listens and waits for new connections, queuing them for the workers, until the
+  /// server is shutting down.
+  ///
+  /// # Arguments
+  ///
+  /// * `listener`: Listener of the connections.
+  /// * `pool`: Worker threads serving the connections.
+  /// * `shutdown`: Flag set when the server must stop.
+  fn accept_connections(
+    listener: &TcpListener,
+    pool: &ThreadPool<TcpStream>,
+    shutdown: &AtomicBool,
+  ) {
+    for stream in listener.incoming() {
+      if shutdown.load(Ordering::SeqCst) {
+        break;
+      }
+      match stream {
+        Ok(stream) => {
+          // Every worker is busy and the queue is full
+          if let Err(mut stream) = pool.execute(stream) {
+            let mut response: HttpResponse =
+              HttpResponse::new(StatusCode::SERVICE_UNAVAILABLE, None, Body::Empty);
+            response.headers_mut().insert("Connection", "close");
+            let _ = response.send_response(&mut stream);
+          }
+        }
+        Err(e) => error!("Failed to accept connection: {}", e),
+      }
     }
   }
 
-  /// Runs the server
-  pub fn run(&self) {
-    // Start the server on the socket address
-    let connection_listener : TcpListener= TcpListener::bind(self.socket_address).unwrap();
+  /// Starts a thread that sets the shutdown flag on SIGINT or SIGTERM and wakes up the
+  /// listeners with a connection. A second signal exits immediately.
+  ///
+  /// # Arguments
+  ///
+  /// * `listener_addresses`: Local addresses of the listeners.
+  /// * `shutdown`: Flag to set when the server must stop.
+  fn watch_signals(
+    listener_addresses: Vec<SocketAddr>,
+    shutdown: Arc<AtomicBool>,
+  ) -> io::Result<()> {
+    let mut signals: Signals = Signals::new([SIGINT, SIGTERM])?;
 
-    println!("Server running on {}", self.socket_address);
+    thread::spawn(move || {
+      for signal in signals.forever() {
+        if shutdown.swap(true, Ordering::SeqCst) {
+          process::exit(128 + signal);
+        }
+        for address in &listener_addresses {
+          let _ = TcpStream::connect(address);
+        }
+      }
+    });
+    Ok(())
+  }
+}
 
-    // Listen and waits for new connections
-    for stream in connection_listener.incoming() {
-      let mut stream : TcpStream = stream.unwrap();
-      println!("Connection established with client.");
+/// Represents the settings shared by the workers to serve connections.
+#[derive(Clone)]
+struct ConnectionHandler {
+  /// Size limits for the incoming requests.
+  limits: RequestLimits,
+  /// Settings of persistent connections.
+  keep_alive: KeepAlive,
+  /// Flag set when the server is shutting down.
+  shutdown: Arc<AtomicBool>,
+  /// Sites served, each with its routes.
+  hosts: Arc<VirtualHosts>,
+  /// Middlewares run around the routing of every request.
+  middlewares: Arc<Chain>,
+  /// Slots of the connections upgraded to WebSocket.
+  websockets: Arc<Slots>,
+  /// Slots of the connections streaming server-sent events.
+  event_streams: Arc<Slots>,
+  /// TLS settings, when the server speaks HTTPS.
+  #[cfg(feature = "tls")]
+  tls: Option<Arc<rustls::ServerConfig>>,
+}
+
+impl ConnectionHandler {
+  /// Serves the requests received on a connection, in order, until the client or the
+  /// server closes it, or hands it over to a thread of its own once it is upgraded to
+  /// WebSocket or streams events. With TLS, the handshake is done first.
+  ///
+  /// # Argument
+  ///
+  /// * `stream`: Connection with the client.
+  fn handle(
+    &self,
+    mut stream: TcpStream,
+  ) {
+    if let Err(e) = stream.set_read_timeout(Some(self.keep_alive.idle_timeout)) {
+      error!("Failed to set the connection timeout: {}", e);
+      return;
+    }
+    let peer_address: Option<SocketAddr> = stream.peer_addr().ok();
+
+    #[cfg(feature = "tls")]
+    if let Some(config) = &self.tls {
+      match tls::accept(Arc::clone(config), stream) {
+        Ok(mut stream) => match self.serve(&mut stream, peer_address, true) {
+          Some(handoff) => self.detach(stream, handoff, tls::close),
+          None => tls::close(&mut stream),
+        },
+        Err(e) => warn!("Failed to complete the TLS handshake: {}", e),
+      }
+      return;
+    }
+
+    if let Some(handoff) = self.serve(&mut stream, peer_address, false) {
+      self.detach(stream, handoff, |_| {});
+    }
+  }
+
+  /// Serves a long-lived connection on a new thread, so the worker is free to serve
+  /// other connections: runs the WebSocket handler of an upgraded connection, or sends
+  /// the events of a stream. A panic only closes the connection.
+  ///
+  /// # Arguments
+  ///
+  /// * `stream`: Stream with the client, plaintext or TLS.
+  /// * `handoff`: Long-lived connection.
+  /// * `finish`: Function closing the stream once the connection is served.
+  fn detach<S: Stream + Send + 'static>(
+    &self,
+    mut stream: S,
+    handoff: Handoff,
+    finish: fn(&mut S),
+  ) {
+    let shutdown: Arc<AtomicBool> = Arc::clone(&self.shutdown);
+    let name: &str = match &handoff {
+      Handoff::WebSocket { .. } => "websocket",
+      Handoff::EventStream { .. } => "event-stream",
+    };
+
+    let spawned: std::io::Result<thread::JoinHandle<()>> = thread::Builder::new()
+      .name(name.to_string())
+      .spawn(move || {
+        let served: thread::Result<()> =
+          panic::catch_unwind(AssertUnwindSafe(|| match handoff {
+            Handoff::WebSocket {
+              request,
+              handler,
+              slot: _slot,
+            } => {
+              let socket_stream: &mut dyn Stream = &mut stream;
+              let mut socket: WebSocket<&mut dyn Stream> =
+                WebSocket::new(socket_stream, Role::Server).with_shutdown(shutdown);
+              handler(&request, &mut socket);
+            }
+            Handoff::EventStream {
+              response,
+              slot: _slot,
+            } => {
+              let _ = response.send_response(&mut stream);
+            }
+          }));
+        if served.is_err() {
+          error!("Panicked while serving a long-lived connection, closing it");
+        }
+        finish(&mut stream);
+      });
+    if let Err(e) = spawned {
+      error!(
+        "Failed to start a thread for a long-lived connection: {}",
+        e
+      );
+    }
+  } // end fn detach()
+
+  /// Answers a request for a long-lived connection when every slot is taken, and closes
+  /// the connection.
+  ///
+  /// # Arguments
+  ///
+  /// * `stream`: Stream with the client, plaintext or TLS.
+  /// * `kind`: Kind of the connection refused, for the log.
+  /// * `slots`: Slots of the connections of that kind.
+  fn refuse(
+    stream: &mut (impl Read + Write),
+    kind: &str,
+    slots: &Slots,
+  ) {
+    warn!("Refused a {}: {} connections are open", kind, slots.limit);
+    let mut response: HttpResponse =
+      HttpResponse::new(StatusCode::SERVICE_UNAVAILABLE, None, Body::Empty);
+    response.headers_mut().insert("Connection", "close");
+    let _ = response.send_response(stream);
+  }
+
+  /// Serves the requests received on a stream, in order, until the client or the server
+  /// closes it. Returns the connection upgraded to WebSocket or streaming events, if
+  /// any.
+  ///
+  /// # Argument
+  ///
+  /// * `stream`: Stream with the client, plaintext or TLS.
+  /// * `peer_address`: Socket address of the client.
+  /// * `secure`: Whether the stream is a TLS one.
+  fn serve(
+    &self,
+    stream: &mut (impl Read + Write),
+    peer_address: Option<SocketAddr>,
+    secure: bool,
+  ) -> Option<Handoff> {
+    let mut reader: RequestReader = RequestReader::new(self.limits);
+    let mut served: usize = 0;
+
+    loop {
       // Create the request from the byte stream received
-      let mut read_buffer = [0; 90];
-      stream.read(&mut read_buffer).unwrap();
+      let mut req: HttpRequest = match reader.read_request(stream) {
+        Ok(req) => req,
+        Err(ReadError::ConnectionClosed | ReadError::IdleTimeout) => break,
+        // Answer an invalid request with the respective error status and close
+        Err(e) => {
+          warn!("Failed to read request: {}", e);
+          if let Some(status_code) = e.status_code() {
+            let mut response: HttpResponse =
+              HttpResponse::new(status_code, None, Body::Empty);
+            response.headers_mut().insert("Connection", "close");
+            let _ = response.send_response(stream);
+          }
+          break;
+        }
+      };
+      served += 1;
+
+      // Route the request to the appropiate handler through the middlewares
+      let mut context: Context = Context::new(peer_address);
+      context.secure = secure;
+      let hosts: &VirtualHosts = &self.hosts;
+      let mut response: HttpResponse =
+        self
+          .middlewares
+          .handle(&mut req, &mut context, &|req| hosts.route(req));
+
+      // Hand the connection over to the WebSocket handler once the upgrade is accepted,
+      // unless too many connections are upgraded already
+      if response.status_code() == &StatusCode::SWITCHING_PROTOCOLS {
+        if let Some(handler) = self.hosts.websocket_handler(&req) {
+          let Some(slot) = self.websockets.take() else {
+            Self::refuse(stream, "WebSocket upgrade", &self.websockets);
+            break;
+          };
+          if response.send_response(stream).is_ok() {
+            return Some(Handoff::WebSocket {
+              request: req,
+              handler,
+              slot,
+            });
+          }
+        }
+        break;
+      }
+
+      // Send a stream of events, which lasts until the server ends it, from a thread of
+      // its own, unless too many streams are open already
+      if req.method != Method::HEAD && is_event_stream(&response) {
+        let Some(slot) = self.event_streams.take() else {
+          Self::refuse(stream, "event stream", &self.event_streams);
+          break;
+        };
+        response.headers_mut().insert("Connection", "close");
+        return Some(Handoff::EventStream { response, slot });
+      }
+
+      // Close the connection after this request when the server is shutting down or a
+      // middleware asks for it
+      let keep_alive: bool = wants_keep_alive(&req)
+        && served < self.keep_alive.max_requests
+        && !self.shutdown.load(Ordering::SeqCst)
+        && !response.headers().connection().iter().any(|o| o == "close");
+
+      if !keep_alive {
+        response.headers_mut().insert("Connection", "close");
+      } else if req.version == Version::V1_0 {
+        response.headers_mut().insert("Connection", "keep-alive");
+      }
+
+      // HTTP/1.0 clients do not understand the chunked transfer coding
+      if req.version == Version::V1_0 && response.buffer_body().is_err() {
+        break;
+      }
 
-      let req: HttpRequest = String::from_utf8(read_buffer.to_vec()).unwrap().into();
+      // Answer HEAD requests without the body
+      let sent: std::io::Result<()> = match req.method {
+        Method::HEAD => response.send_head(stream),
+        _ => response.send_response(stream),
+      };
 
-      // Route the request to the appropiate handler
-      Router::route(req, &mut stream);
+      if sent.is_err() || !keep_alive {
+        break;
+      }
     }
+    None
+  } // end fn serve()
+}
+
+/// Checks whether a response streams server-sent events, as a body with no known end.
+///
+/// # Argument
+///
+/// * `response`: HTTP response to send.
+fn is_event_stream(response: &HttpResponse) -> bool {
+  response.body().len().is_none()
+    && response
+      .headers()
+      .content_type()
+      .is_some_and(|content_type| content_type.starts_with(sse::CONTENT_TYPE))
+}
+
+/// Adds the address a server failed to listen on to the error, as the error alone
+/// does not name it.
+///
+/// # Arguments
+///
+/// * `address`: Socket address to listen on.
+/// * `error`: Error binding the address.
+pub fn listen_error(
+  address: &str,
+  error: io::Error,
+) -> io::Error {
+  io::Error::new(
+    error.kind(),
+    format!("cannot listen on {}: {}", address, error),
+  )
+}
+
+/// Checks whether the client allows the connection to stay open after the request.
+/// HTTP/1.1 connections are persistent unless closed explicitly, while HTTP/1.0 ones
+/// must ask for it.
+///
+/// # Argument
+///
+/// * `request`: HTTP request received.
+pub fn wants_keep_alive(request: &HttpRequest) -> bool {
+  let options: Vec<String> = request.headers.connection();
+
+  if options.iter().any(|o| o == "close") {
+    return false;
+  }
+  match request.version {
+    Version::V1_1 => true,
+    Version::V1_0 => options.iter().any(|o| o == "keep-alive"),
+    _ => false,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::{Read, Write};
+  use std::thread;
+
+  use super::*;
+
+  /// Creates a handler serving the default routes with the given settings.
+  fn handler(keep_alive: KeepAlive) -> ConnectionHandler {
+    ConnectionHandler {
+      limits: RequestLimits::default(),
+      keep_alive,
+      shutdown: Arc::new(AtomicBool::new(false)),
+      hosts: Arc::new(VirtualHosts::default()),
+      middlewares: Arc::new(Chain::new()),
+      websockets: Slots::new(DEFAULT_MAX_WEBSOCKETS),
+      event_streams: Slots::new(DEFAULT_MAX_EVENT_STREAMS),
+      #[cfg(feature = "tls")]
+      tls: None,
+    }
+  }
+
+  /// Serves a single connection with the given handler, in a new thread. Returns the
+  /// address to connect to and the thread, which ends with the connection.
+  fn serve_one(handler: ConnectionHandler) -> (SocketAddr, thread::JoinHandle<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+
+    let server = thread::spawn(move || {
+      let (stream, _) = listener.accept().unwrap();
+      handler.handle(stream);
+    });
+    (address, server)
+  }
+
+  /// Serves a single connection with the given settings and returns everything the
+  /// client receives after sending the given bytes.
+  fn exchange(
+    keep_alive: KeepAlive,
+    request: &[u8],
+  ) -> String {
+    let (address, server) = serve_one(handler(keep_alive));
+
+    let mut client = TcpStream::connect(address).unwrap();
+    client.write_all(request).unwrap();
+
+    let mut received = String::new();
+    client.read_to_string(&mut received).unwrap();
+    server.join().unwrap();
+    received
+  }
+
+  #[test]
+  fn test_address_in_use() {
+    let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = taken.local_addr().unwrap().to_string();
+
+    let error = Server::new(&address).run().unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+    assert!(error.to_string().contains(&address));
+  }
+
+  #[test]
+  fn test_pipelined_requests_in_order() {
+    let received = exchange(
+      KeepAlive::default(),
+      b"GET /health HTTP/1.1\r\n\r\nHEAD / HTTP/1.1\r\n\r\nGET /nope HTTP/1.1\r\nConnection: close\r\n\r\n",
+    );
+
+    // Bodies do not end with a line break, so look for the status lines anywhere
+    let statuses: Vec<&str> = received
+      .split("HTTP/1.1 ")
+      .skip(1)
+      .map(|r| &r[..3])
+      .collect();
+    assert_eq!(statuses, vec!["200", "200", "404"]);
+    assert_eq!(received.matches("Connection:close").count(), 1);
+  }
+
+  #[test]
+  fn test_max_requests_per_connection() {
+    let keep_alive = KeepAlive {
+      idle_timeout: Duration::from_secs(5),
+      max_requests: 2,
+    };
+    let received = exchange(
+      keep_alive,
+      b"HEAD / HTTP/1.1\r\n\r\nHEAD / HTTP/1.1\r\n\r\nHEAD / HTTP/1.1\r\n\r\n",
+    );
+
+    assert_eq!(received.matches("HTTP/1.1 200 OK").count(), 2);
+    assert_eq!(received.matches("Connection:close").count(), 1);
+  }
+
+  #[test]
+  fn test_websocket_echo() {
+    use http::websocket::{self, Message};
+
+    let (address, server) = serve_one(handler(KeepAlive::default()));
+
+    let client = TcpStream::connect(address).unwrap();
+    let mut socket = WebSocket::connect(client, "localhost", "/ws/echo").unwrap();
+    socket.send(Message::Text("hello".to_string())).unwrap();
+    socket.send(Message::Binary(vec![0, 1, 2])).unwrap();
+    assert_eq!(
+      socket.read_message().unwrap(),
+      Message::Text("hello".to_string())
+    );
+    assert_eq!(
+      socket.read_message().unwrap(),
+      Message::Binary(vec![0, 1, 2])
+    );
+    socket.close(websocket::CLOSE_NORMAL, "").unwrap();
+    server.join().unwrap();
+
+    // The route refuses plain requests
+    let received = exchange(
+      KeepAlive::default(),
+      b"GET /ws/echo HTTP/1.1\r\nConnection: close\r\n\r\n",
+    );
+    assert!(received.starts_with("HTTP/1.1 426 Upgrade Required"));
+  }
+
+  #[test]
+  fn test_websocket_limit() {
+    use http::websocket::{self, Message};
+
+    let mut limited = handler(KeepAlive::default());
+    limited.websockets = Slots::new(1);
+    let slot = limited.websockets.take().unwrap();
+    let (address, server) = serve_one(limited.clone());
+
+    // Every slot is taken
+    let mut client = TcpStream::connect(address).unwrap();
+    client
+      .write_all(
+        b"GET /ws/echo HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\n\
+          Connection: Upgrade\r\nSec-WebSocket-Version: 13\r\n\
+          Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+      )
+      .unwrap();
+    let mut received = String::new();
+    client.read_to_string(&mut received).unwrap();
+    server.join().unwrap();
+    assert!(received.starts_with("HTTP/1.1 503 Service Unavailable"));
+
+    // The upgraded connection does not hold the thread serving it
+    drop(slot);
+    let (address, server) = serve_one(limited.clone());
+    let client = TcpStream::connect(address).unwrap();
+    let mut socket = WebSocket::connect(client, "localhost", "/ws/echo").unwrap();
+    server.join().unwrap();
+    assert!(limited.websockets.take().is_none());
+    socket.send(Message::Text("hello".to_string())).unwrap();
+    assert_eq!(
+      socket.read_message().unwrap(),
+      Message::Text("hello".to_string())
+    );
+    socket.close(websocket::CLOSE_NORMAL, "").unwrap();
+    limited.websockets.wait_all();
+  }
+
+  #[test]
+  fn test_event_stream_limit() {
+    let mut limited = handler(KeepAlive::default());
+    limited.event_streams = Slots::new(1);
+    let slot = limited.event_streams.take().unwrap();
+
+    // Every slot is taken
+    let (address, server) = serve_one(limited.clone());
+    let mut client = TcpStream::connect(address).unwrap();
+    client
+      .write_all(b"GET /api/shipping/orders/stream HTTP/1.1\r\nHost: localhost\r\n\r\n")
+      .unwrap();
+    let mut received = String::new();
+    client.read_to_string(&mut received).unwrap();
+    server.join().unwrap();
+    assert!(received.starts_with("HTTP/1.1 503 Service Unavailable"));
+
+    // The stream does not hold the thread serving the connection
+    drop(slot);
+    let (address, server) = serve_one(limited.clone());
+    let mut client = TcpStream::connect(address).unwrap();
+    client
+      .write_all(b"GET /api/shipping/orders/stream HTTP/1.1\r\nHost: localhost\r\n\r\n")
+      .unwrap();
+    server.join().unwrap();
+    assert!(limited.event_streams.take().is_none());
+    let mut head = [0; 15];
+    client.read_exact(&mut head).unwrap();
+    assert_eq!(&head, b"HTTP/1.1 200 OK");
+  }
+
+  #[test]
+  fn test_http_1_0_closes_by_default() {
+    let received = exchange(
+      KeepAlive::default(),
+      b"HEAD / HTTP/1.0\r\n\r\nHEAD / HTTP/1.0\r\n\r\n",
+    );
+
+    assert_eq!(received.matches("HTTP/1.1 200 OK").count(), 1);
+    assert!(received.contains("Connection:close"));
+  }
+
+  #[test]
+  fn test_idle_timeout_closes_connection() {
+    let keep_alive = KeepAlive {
+      idle_timeout: Duration::from_millis(100),
+      max_requests: 100,
+    };
+    let received = exchange(keep_alive, b"HEAD / HTTP/1.1\r\n\r\n");
+
+    assert_eq!(received.matches("HTTP/1.1 200 OK").count(), 1);
+    assert!(!received.contains("Connection:close"));
+  }
+
+  #[cfg(feature = "tls")]
+  #[test]
+  fn test_https_round_trip() {
+    let (cert_path, key_path) = tls::self_signed_files();
+    let config = tls::load_config(&cert_path, &key_path).unwrap();
+    let mut https_handler = handler(KeepAlive::default());
+    https_handler.tls = Some(config);
+    let (address, server) = serve_one(https_handler);
+
+    // The client trusts only the self-signed certificate
+    let mut roots = rustls::RootCertStore::empty();
+    let mut pem = std::io::BufReader::new(std::fs::File::open(&cert_path).unwrap());
+    for cert in rustls_pemfile::certs(&mut pem) {
+      roots.add(cert.unwrap()).unwrap();
+    }
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut client_config = rustls::ClientConfig::builder_with_provider(provider)
+      .with_safe_default_protocol_versions()
+      .unwrap()
+      .with_root_certificates(roots)
+      .with_no_client_auth();
+    client_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    let connection = rustls::ClientConnection::new(
+      Arc::new(client_config),
+      "localhost".try_into().unwrap(),
+    )
+    .unwrap();
+    let mut client =
+      rustls::StreamOwned::new(connection, TcpStream::connect(address).unwrap());
+
+    client
+      .write_all(b"HEAD / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+      .unwrap();
+    let mut received = String::new();
+    client.read_to_string(&mut received).unwrap();
+    server.join().unwrap();
+
+    assert_eq!(client.conn.alpn_protocol(), Some(&b"http/1.1"[..]));
+    assert!(received.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(received.contains("Connection:close"));
+  }
+
+  #[test]
+  fn test_partial_request_times_out() {
+    let keep_alive = KeepAlive {
+      idle_timeout: Duration::from_millis(100),
+      max_requests: 100,
+    };
+    let received = exchange(keep_alive, b"GET / HTTP/1.1\r\nHost:");
+
+    assert!(received.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
   }
 }
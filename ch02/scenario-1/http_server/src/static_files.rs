@@ -0,0 +1,556 @@
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::{fs, io};
+
+use http::date::DateTime;
+use http::header_map::HeaderMap;
+
+use crate::config;
+
+/// Name of the file served for a directory.
+const INDEX_FILE: &str = "index.html";
+
+/// MIME type of the files with an unknown extension.
+const DEFAULT_MIME_TYPE: &str = "application/octet-stream";
+
+/// MIME type of each known file extension.
+const MIME_TYPES: [(&str, &str); 42] = [
+  ("aac", "audio/aac"),
+  ("avif", "image/avif"),
+  ("bmp", "image/bmp"),
+  ("css", "text/css"),
+  ("csv", "text/csv"),
+  ("gif", "image/gif"),
+  ("gz", "application/gzip"),
+  ("htm", "text/html"),
+  ("html", "text/html"),
+  ("ico", "image/vnd.microsoft.icon"),
+  ("ics", "text/calendar"),
+  ("jpeg", "image/jpeg"),
+  ("jpg", "image/jpeg"),
+  ("js", "text/javascript"),
+  ("json", "application/json"),
+  ("jsonld", "application/ld+json"),
+  ("map", "application/json"),
+  ("md", "text/markdown"),
+  ("mjs", "text/javascript"),
+  ("mp3", "audio/mpeg"),
+  ("mp4", "video/mp4"),
+  ("mpeg", "video/mpeg"),
+  ("oga", "audio/ogg"),
+  ("ogv", "video/ogg"),
+  ("otf", "font/otf"),
+  ("pdf", "application/pdf"),
+  ("png", "image/png"),
+  ("svg", "image/svg+xml"),
+  ("tar", "application/x-tar"),
+  ("tif", "image/tiff"),
+  ("tiff", "image/tiff"),
+  ("ttf", "font/ttf"),
+  ("txt", "text/plain"),
+  ("wasm", "application/wasm"),
+  ("wav", "audio/wav"),
+  ("weba", "audio/webm"),
+  ("webm", "video/webm"),
+  ("webp", "image/webp"),
+  ("woff", "font/woff"),
+  ("woff2", "font/woff2"),
+  ("xml", "application/xml"),
+  ("zip", "application/zip"),
+];
+
+/// `Cache-Control` value of the paths without a more specific policy.
+pub const DEFAULT_CACHE_CONTROL: &str = "no-cache";
+
+/// Represents the reasons a path cannot be served from the public directory.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ResolveError {
+  /// There is no such file.
+  NotFound,
+  /// The path leads outside the public directory.
+  Forbidden,
+}
+
+thread_local! {
+  /// Public directory of the virtual host whose request this thread is serving, if any.
+  static HOST_PUBLIC_ROOT: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+}
+
+/// Gets the public directory of the virtual host being served, or else the server
+/// public directory, as configured at startup.
+pub fn public_root() -> PathBuf {
+  HOST_PUBLIC_ROOT
+    .with(|root| root.borrow().clone())
+    .unwrap_or_else(|| config::paths().public)
+}
+
+/// Runs a function with the given public directory in place of the configured one, as
+/// needed while serving a request for a virtual host with its own directory.
+///
+/// # Arguments
+///
+/// * `root`: Public directory of the virtual host.
+/// * `serve`: Function serving the request.
+pub fn with_public_root<R>(
+  root: &Path,
+  serve: impl FnOnce() -> R,
+) -> R {
+  /// Restores the previous directory, even when the handler panics.
+  struct Restore(Option<PathBuf>);
+
+  impl Drop for Restore {
+    fn drop(&mut self) {
+      HOST_PUBLIC_ROOT.with(|root| *root.borrow_mut() = self.0.take());
+    }
+  }
+
+  let _restore: Restore =
+    Restore(HOST_PUBLIC_ROOT.with(|current| current.replace(Some(root.to_path_buf()))));
+  serve()
+}
+
+/// Finds the file to serve for the given path segments.
+///
+/// Both the root and the requested path are canonicalised, so `..` segments and
+/// symbolic links are followed before checking that the file is inside the root. A
+/// directory is served by its `index.html` file.
+///
+/// # Arguments
+///
+/// * `root`: Public directory.
+/// * `segments`: Decoded segments of the requested path.
+pub fn resolve(
+  root: &Path,
+  segments: &[&str],
+) -> Result<PathBuf, ResolveError> {
+  let root: PathBuf = root.canonicalize().map_err(|_| ResolveError::NotFound)?;
+
+  // A decoded segment must not start a new path (e.g. "%2Fetc")
+  if segments.iter().any(|s| s.contains(['/', '\\', '\0'])) {
+    return Err(ResolveError::Forbidden);
+  }
+  // Reject going above the root even if the target does not exist
+  let mut depth: usize = 0;
+  for segment in segments {
+    match *segment {
+      "." => {}
+      ".." => depth = depth.checked_sub(1).ok_or(ResolveError::Forbidden)?,
+      _ => depth += 1,
+    }
+  }
+
+  let mut path: PathBuf =
+    confine(&root, root.join(segments.iter().collect::<PathBuf>()))?;
+  if path.is_dir() {
+    path = confine(&root, path.join(INDEX_FILE))?;
+  }
+
+  if path.is_file() {
+    Ok(path)
+  } else {
+    Err(ResolveError::NotFound)
+  }
+}
+
+/// Gets the precompressed sibling of a file (the file name followed by `.gz`), if it
+/// exists inside the root.
+///
+/// # Arguments
+///
+/// * `root`: Public directory.
+/// * `path`: Canonical path of the file.
+pub fn precompressed(
+  root: &Path,
+  path: &Path,
+) -> Option<PathBuf> {
+  let root: PathBuf = root.canonicalize().ok()?;
+  let mut name: std::ffi::OsString = path.file_name()?.to_os_string();
+  name.push(".gz");
+
+  confine(&root, path.with_file_name(name))
+    .ok()
+    .filter(|sibling| sibling.is_file())
+}
+
+/// Canonicalises a path and checks that it is inside the root.
+///
+/// # Arguments
+///
+/// * `root`: Canonical public directory.
+/// * `path`: Path to check.
+fn confine(
+  root: &Path,
+  path: PathBuf,
+) -> Result<PathBuf, ResolveError> {
+  let path: PathBuf = path.canonicalize().map_err(|e| match e.kind() {
+    io::ErrorKind::PermissionDenied => ResolveError::Forbidden,
+    _ => ResolveError::NotFound,
+  })?;
+
+  if path.starts_with(root) {
+    Ok(path)
+  } else {
+    Err(ResolveError::Forbidden)
+  }
+}
+
+/// Gets the MIME type of a file from its extension. Text types include the UTF-8
+/// charset.
+///
+/// # Arguments
+///
+/// * `path`: Path of the file.
+pub fn mime_type(path: &Path) -> String {
+  let extension: String = path
+    .extension()
+    .and_then(|e| e.to_str())
+    .unwrap_or_default()
+    .to_ascii_lowercase();
+
+  let mime_type: &str = MIME_TYPES
+    .iter()
+    .find(|(e, _)| *e == extension)
+    .map_or(DEFAULT_MIME_TYPE, |(_, m)| *m);
+
+  if mime_type.starts_with("text/") {
+    format!("{};charset=UTF-8", mime_type)
+  } else {
+    mime_type.to_string()
+  }
+}
+
+/// Represents the validators of a file, used by clients to revalidate their copies.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Validators {
+  /// Strong entity tag, derived from the size and modification time of the file.
+  pub etag: String,
+  /// Modification time of the file.
+  pub last_modified: DateTime,
+}
+
+impl Validators {
+  /// Creates the validators of a file from its metadata.
+  ///
+  /// # Arguments
+  ///
+  /// * `metadata`: Metadata of the file.
+  pub fn from_metadata(metadata: &fs::Metadata) -> Validators {
+    let modified: std::time::SystemTime =
+      metadata.modified().unwrap_or(std::time::UNIX_EPOCH);
+    let nanos: u128 = modified
+      .duration_since(std::time::UNIX_EPOCH)
+      .map_or(0, |elapsed| elapsed.as_nanos());
+
+    Validators {
+      etag: format!("\"{:x}-{:x}\"", metadata.len(), nanos),
+      last_modified: DateTime::from_system_time(modified),
+    }
+  }
+
+  /// Checks whether the copy the client holds is still valid according to the
+  /// `If-None-Match` header or, if it is missing, the `If-Modified-Since` header.
+  ///
+  /// # Arguments
+  ///
+  /// * `headers`: Headers of a GET or HEAD request.
+  pub fn not_modified(
+    &self,
+    headers: &HeaderMap,
+  ) -> bool {
+    let if_none_match: Vec<&str> = headers.get_all("If-None-Match");
+    if !if_none_match.is_empty() {
+      // Entity tags are compared with the weak comparison
+      let etag: &str = self.etag.trim_start_matches("W/");
+      return if_none_match
+        .iter()
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag);
+    }
+
+    match headers
+      .get("If-Modified-Since")
+      .and_then(DateTime::parse_http_date)
+    {
+      Some(since) => self.last_modified.to_unix() <= since.to_unix(),
+      None => false,
+    }
+  }
+
+  /// Checks whether the `Range` header of a request can be honoured according to its
+  /// `If-Range` header, which holds either an entity tag or a date.
+  ///
+  /// # Arguments
+  ///
+  /// * `headers`: Headers of a GET request.
+  pub fn range_applies(
+    &self,
+    headers: &HeaderMap,
+  ) -> bool {
+    let Some(if_range) = headers.get("If-Range").map(str::trim) else {
+      return true;
+    };
+
+    // Entity tags are compared with the strong comparison, so weak tags never match
+    if if_range.starts_with('"') || if_range.starts_with("W/") {
+      return !self.etag.starts_with("W/") && if_range == self.etag;
+    }
+    DateTime::parse_http_date(if_range) == Some(self.last_modified)
+  }
+}
+
+/// Represents the `Cache-Control` values sent for the paths under some prefixes.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CachePolicy {
+  /// Path prefix and `Cache-Control` value of each rule.
+  rules: Vec<(String, String)>,
+}
+
+impl CachePolicy {
+  /// Parses a policy written as `prefix=value` rules separated by semicolons, e.g.
+  /// `/css=public, max-age=86400;/=no-cache`.
+  ///
+  /// # Arguments
+  ///
+  /// * `text`: Rules of the policy.
+  pub fn parse(text: &str) -> CachePolicy {
+    let rules: Vec<(String, String)> = text
+      .split(';')
+      .filter_map(|rule| rule.split_once('='))
+      .map(|(prefix, value)| {
+        let prefix: &str = prefix.trim().trim_end_matches('/');
+        (format!("{}/", prefix), value.trim().to_string())
+      })
+      .collect();
+
+    CachePolicy { rules }
+  }
+
+  /// Gets the `Cache-Control` value of a path: the one of the longest matching prefix,
+  /// where prefixes only match whole segments.
+  ///
+  /// # Arguments
+  ///
+  /// * `path`: Decoded path of the request.
+  pub fn for_path(
+    &self,
+    path: &str,
+  ) -> &str {
+    let path: String = format!("{}/", path.trim_end_matches('/'));
+
+    self
+      .rules
+      .iter()
+      .filter(|(prefix, _)| path.starts_with(prefix.as_str()))
+      .max_by_key(|(prefix, _)| prefix.len())
+      .map_or(DEFAULT_CACHE_CONTROL, |(_, value)| value.as_str())
+  }
+}
+
+impl Default for CachePolicy {
+  fn default() -> Self {
+    CachePolicy::parse(&format!("/={}", DEFAULT_CACHE_CONTROL))
+  }
+}
+
+/// Cache policy of the running server, installed at startup.
+static CACHE_POLICY: RwLock<Option<CachePolicy>> = RwLock::new(None);
+
+/// Installs the cache policy of the running server, read by the file handler.
+///
+/// # Arguments
+///
+/// * `policy`: `Cache-Control` values of the served paths.
+pub fn install_cache_policy(policy: CachePolicy) {
+  *CACHE_POLICY.write().unwrap() = Some(policy);
+}
+
+/// Gets the `Cache-Control` value of a path under the installed policy, or under the
+/// default one if none was installed.
+///
+/// # Arguments
+///
+/// * `path`: Decoded path of the request.
+pub fn cache_control(path: &str) -> String {
+  match CACHE_POLICY.read().unwrap().as_ref() {
+    Some(policy) => policy.for_path(path).to_string(),
+    None => CachePolicy::default().for_path(path).to_string(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{env, fs};
+
+  use super::*;
+
+  /// Creates a public directory with nested files, and a secret file beside it.
+  fn public_dir(name: &str) -> PathBuf {
+    let base = env::temp_dir().join(format!("static-{}-{}", name, std::process::id()));
+    let root = base.join("public");
+    fs::create_dir_all(root.join("css")).unwrap();
+    fs::create_dir_all(root.join("docs")).unwrap();
+    fs::write(root.join("index.html"), "home").unwrap();
+    fs::write(root.join("README"), "readme").unwrap();
+    fs::write(root.join("css").join("site.css"), "body {}").unwrap();
+    fs::write(root.join("docs").join("index.html"), "docs").unwrap();
+    fs::write(base.join("secret.txt"), "secret").unwrap();
+    root
+  }
+
+  #[test]
+  fn test_resolve_inside_root() {
+    let root = public_dir("inside");
+    let canonical = root.canonicalize().unwrap();
+
+    assert_eq!(resolve(&root, &[]), Ok(canonical.join("index.html")));
+    assert_eq!(
+      resolve(&root, &["css", "site.css"]),
+      Ok(canonical.join("css").join("site.css"))
+    );
+    assert_eq!(
+      resolve(&root, &["docs"]),
+      Ok(canonical.join("docs").join("index.html"))
+    );
+    assert_eq!(resolve(&root, &["README"]), Ok(canonical.join("README")));
+    assert_eq!(resolve(&root, &["css"]), Err(ResolveError::NotFound));
+    assert_eq!(resolve(&root, &["nope.html"]), Err(ResolveError::NotFound));
+
+    fs::remove_dir_all(root.parent().unwrap()).unwrap();
+  }
+
+  #[test]
+  fn test_precompressed_sibling() {
+    let root = public_dir("precompressed");
+    let canonical = root.canonicalize().unwrap();
+    fs::write(root.join("css").join("site.css.gz"), "gzipped").unwrap();
+
+    assert_eq!(
+      precompressed(&root, &canonical.join("css").join("site.css")),
+      Some(canonical.join("css").join("site.css.gz"))
+    );
+    assert_eq!(precompressed(&root, &canonical.join("README")), None);
+
+    // A sibling linking outside the root is not served
+    #[cfg(unix)]
+    {
+      std::os::unix::fs::symlink(
+        root.parent().unwrap().join("secret.txt"),
+        root.join("README.gz"),
+      )
+      .unwrap();
+      assert_eq!(precompressed(&root, &canonical.join("README")), None);
+    }
+
+    fs::remove_dir_all(root.parent().unwrap()).unwrap();
+  }
+
+  #[test]
+  fn test_resolve_outside_root() {
+    let root = public_dir("outside");
+
+    assert_eq!(
+      resolve(&root, &["..", "secret.txt"]),
+      Err(ResolveError::Forbidden)
+    );
+    assert_eq!(
+      resolve(&root, &["css", "..", "..", "secret.txt"]),
+      Err(ResolveError::Forbidden)
+    );
+    assert_eq!(
+      resolve(&root, &["/etc", "passwd"]),
+      Err(ResolveError::Forbidden)
+    );
+    assert_eq!(
+      resolve(&root, &["..", "nope"]),
+      Err(ResolveError::Forbidden)
+    );
+    // Going up and back into the root is fine
+    assert!(resolve(&root, &["css", "..", "README"]).is_ok());
+
+    #[cfg(unix)]
+    {
+      std::os::unix::fs::symlink(
+        root.parent().unwrap().join("secret.txt"),
+        root.join("link"),
+      )
+      .unwrap();
+      assert_eq!(resolve(&root, &["link"]), Err(ResolveError::Forbidden));
+    }
+
+    fs::remove_dir_all(root.parent().unwrap()).unwrap();
+  }
+
+  #[test]
+  fn test_mime_types() {
+    assert_eq!(
+      mime_type(Path::new("a/index.HTML")),
+      "text/html;charset=UTF-8"
+    );
+    assert_eq!(mime_type(Path::new("logo.svg")), "image/svg+xml");
+    assert_eq!(mime_type(Path::new("app.wasm")), "application/wasm");
+    assert_eq!(mime_type(Path::new("README")), "application/octet-stream");
+    assert_eq!(mime_type(Path::new("archive.tar.gz")), "application/gzip");
+  }
+
+  #[test]
+  fn test_conditional_headers() {
+    let validators = Validators {
+      etag: "\"5-abc\"".to_string(),
+      last_modified: DateTime::parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT").unwrap(),
+    };
+    let check = |headers: &[(&str, &str)]| {
+      validators.not_modified(&headers.iter().copied().collect::<HeaderMap>())
+    };
+
+    assert!(check(&[("If-None-Match", "\"x\", W/\"5-abc\"")]));
+    assert!(check(&[("If-None-Match", "*")]));
+    assert!(!check(&[("If-None-Match", "\"x\"")]));
+    assert!(check(&[(
+      "If-Modified-Since",
+      "Sun, 06 Nov 1994 08:49:37 GMT"
+    )]));
+    assert!(!check(&[(
+      "If-Modified-Since",
+      "Sat, 05 Nov 1994 08:49:37 GMT"
+    )]));
+    assert!(!check(&[("If-Modified-Since", "yesterday")]));
+    // If-None-Match takes precedence over If-Modified-Since
+    assert!(!check(&[
+      ("If-None-Match", "\"x\""),
+      ("If-Modified-Since", "Sun, 06 Nov 1994 08:49:37 GMT"),
+    ]));
+  }
+
+  #[test]
+  fn test_if_range() {
+    let validators = Validators {
+      etag: "\"5-abc\"".to_string(),
+      last_modified: DateTime::parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT").unwrap(),
+    };
+    let check = |headers: &[(&str, &str)]| {
+      validators.range_applies(&headers.iter().copied().collect::<HeaderMap>())
+    };
+
+    assert!(check(&[]));
+    assert!(check(&[("If-Range", "\"5-abc\"")]));
+    assert!(!check(&[("If-Range", "W/\"5-abc\"")]));
+    assert!(!check(&[("If-Range", "\"x\"")]));
+    assert!(check(&[("If-Range", "Sun, 06 Nov 1994 08:49:37 GMT")]));
+    assert!(!check(&[("If-Range", "Sat, 05 Nov 1994 08:49:37 GMT")]));
+  }
+
+  #[test]
+  fn test_cache_policy() {
+    let policy =
+      CachePolicy::parse("/=no-cache; /css/=public, max-age=86400;/css/dev=no-store");
+
+    assert_eq!(policy.for_path("/index.html"), "no-cache");
+    assert_eq!(policy.for_path("/css/site.css"), "public, max-age=86400");
+    assert_eq!(policy.for_path("/css"), "public, max-age=86400");
+    assert_eq!(policy.for_path("/css/dev/a.css"), "no-store");
+    assert_eq!(policy.for_path("/cssx"), "no-cache");
+    assert_eq!(CachePolicy::parse("").for_path("/a"), "no-cache");
+  }
+}
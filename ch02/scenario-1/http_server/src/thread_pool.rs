@@ -0,0 +1,152 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// Represents a fixed set of worker threads that process items from a bounded queue.
+pub struct ThreadPool<T: Send + 'static> {
+  /// Threads processing the items.
+  workers: Vec<JoinHandle<()>>,
+  /// Sending side of the queue, dropped to stop the workers.
+  sender: Option<SyncSender<T>>,
+}
+
+impl<T: Send + 'static> ThreadPool<T> {
+  /// Creates a new [`ThreadPool`] object and starts its workers.
+  ///
+  /// # Arguments
+  ///
+  /// * `size`: Number of worker threads (at least one is started).
+  /// * `queue_depth`: Number of items that can wait for a free worker.
+  /// * `process`: Function each worker applies to the items it takes.
+  pub fn new(
+    size: usize,
+    queue_depth: usize,
+    process: impl Fn(T) + Send + Sync + 'static,
+  ) -> Self {
+    let (sender, receiver) = mpsc::sync_channel::<T>(queue_depth);
+    let receiver: Arc<Mutex<Receiver<T>>> = Arc::new(Mutex::new(receiver));
+    let process = Arc::new(process);
+
+    let workers: Vec<JoinHandle<()>> = (0..size.max(1))
+      .map(|_| {
+        let receiver = Arc::clone(&receiver);
+        let process = Arc::clone(&process);
+        thread::spawn(move || loop {
+          // Release the lock before processing so other workers can take items
+          let item: Result<T, mpsc::RecvError> = receiver.lock().unwrap().recv();
+          match item {
+            // A panic is contained so the worker keeps serving the next items
+            Ok(item) => {
+              let processed = panic::catch_unwind(AssertUnwindSafe(|| process(item)));
+              if processed.is_err() {
+                error!("Worker recovered from a panic while processing an item");
+              }
+            }
+            // The pool was dropped and the queue is empty
+            Err(_) => break,
+          }
+        })
+      })
+      .collect();
+
+    Self {
+      workers,
+      sender: Some(sender),
+    }
+  }
+
+  /// Queues an item for the next free worker. If the queue is full, the item is given
+  /// back.
+  ///
+  /// # Arguments
+  ///
+  /// * `item`: Item to process.
+  pub fn execute(
+    &self,
+    item: T,
+  ) -> Result<(), T> {
+    match self.sender.as_ref() {
+      Some(sender) => sender.try_send(item).map_err(|e| match e {
+        TrySendError::Full(item) | TrySendError::Disconnected(item) => item,
+      }),
+      None => Err(item),
+    }
+  }
+}
+
+impl<T: Send + 'static> Drop for ThreadPool<T> {
+  /// Stops accepting items and waits until the workers process every queued item.
+  fn drop(&mut self) {
+    drop(self.sender.take());
+    for worker in self.workers.drain(..) {
+      let _ = worker.join();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::atomic::{AtomicUsize, Ordering};
+  use std::sync::mpsc::channel;
+  use std::time::Duration;
+
+  use super::*;
+
+  #[test]
+  fn test_drop_drains_queue() {
+    let processed = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&processed);
+    let pool = ThreadPool::new(2, 16, move |n: usize| {
+      thread::sleep(Duration::from_millis(5));
+      counter.fetch_add(n, Ordering::SeqCst);
+    });
+
+    for _ in 0..10 {
+      pool.execute(1).unwrap();
+    }
+    drop(pool);
+
+    assert_eq!(processed.load(Ordering::SeqCst), 10);
+  }
+
+  #[test]
+  fn test_worker_survives_panic() {
+    let processed = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&processed);
+    let pool = ThreadPool::new(1, 16, move |n: usize| {
+      if n == 0 {
+        panic!("failed to process the item");
+      }
+      counter.fetch_add(n, Ordering::SeqCst);
+    });
+
+    for n in [0, 1, 0, 1] {
+      pool.execute(n).unwrap();
+    }
+    drop(pool);
+
+    assert_eq!(processed.load(Ordering::SeqCst), 2);
+  }
+
+  #[test]
+  fn test_full_queue_gives_item_back() {
+    let (started, has_started) = channel::<()>();
+    let (release, wait) = channel::<()>();
+    let started = Mutex::new(started);
+    let wait = Mutex::new(wait);
+    let pool = ThreadPool::new(1, 1, move |_: u8| {
+      let _ = started.lock().unwrap().send(());
+      let _ = wait.lock().unwrap().recv();
+    });
+
+    // The first item keeps the worker busy and the second one fills the queue
+    pool.execute(1).unwrap();
+    has_started.recv().unwrap();
+    pool.execute(2).unwrap();
+    assert_eq!(pool.execute(3), Err(3));
+
+    release.send(()).unwrap();
+    release.send(()).unwrap();
+  }
+}
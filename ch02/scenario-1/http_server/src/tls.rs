@@ -0,0 +1,273 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use http::body::Body;
+use http::header_map::HeaderMap;
+use http::http_request::{HttpRequest, Method};
+use http::http_response::HttpResponse;
+use http::status_code::StatusCode;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+
+use crate::reader::{RequestLimits, RequestReader};
+use crate::thread_pool::ThreadPool;
+
+/// Protocol advertised with ALPN during the handshake.
+const ALPN_HTTP_1_1: &[u8] = b"http/1.1";
+/// Time allowed to a client of the redirect listener to send its request.
+const REDIRECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Number of threads answering on the redirect listener.
+const REDIRECT_WORKERS: usize = 2;
+
+/// Represents a TLS stream over a TCP connection, as served by the workers.
+pub type TlsStream = StreamOwned<ServerConnection, TcpStream>;
+
+/// Represents the errors found while loading the TLS settings.
+#[derive(Debug)]
+pub enum TlsError {
+  /// A PEM file could not be read.
+  Io(io::Error),
+  /// The certificate file holds no certificate.
+  NoCertificate,
+  /// The key file holds no private key.
+  NoPrivateKey,
+  /// The certificate and the key were rejected.
+  Rustls(rustls::Error),
+}
+
+impl fmt::Display for TlsError {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    match self {
+      TlsError::Io(e) => write!(f, "{}", e),
+      TlsError::NoCertificate => write!(f, "no certificate found in the PEM file"),
+      TlsError::NoPrivateKey => write!(f, "no private key found in the PEM file"),
+      TlsError::Rustls(e) => write!(f, "{}", e),
+    }
+  }
+}
+
+impl std::error::Error for TlsError {}
+
+impl From<io::Error> for TlsError {
+  fn from(value: io::Error) -> Self {
+    TlsError::Io(value)
+  }
+}
+
+impl From<rustls::Error> for TlsError {
+  fn from(value: rustls::Error) -> Self {
+    TlsError::Rustls(value)
+  }
+}
+
+/// Loads the server settings from a certificate chain and a private key in PEM files.
+/// Only HTTP/1.1 is advertised with ALPN.
+///
+/// # Arguments
+///
+/// * `cert_path`: Path of the PEM file with the certificate chain, leaf first.
+/// * `key_path`: Path of the PEM file with the private key (PKCS #1, PKCS #8 or SEC1).
+pub fn load_config(
+  cert_path: &Path,
+  key_path: &Path,
+) -> Result<Arc<ServerConfig>, TlsError> {
+  let mut cert_reader: BufReader<File> = BufReader::new(File::open(cert_path)?);
+  let certs: Vec<CertificateDer<'static>> =
+    rustls_pemfile::certs(&mut cert_reader).collect::<Result<_, _>>()?;
+  if certs.is_empty() {
+    return Err(TlsError::NoCertificate);
+  }
+
+  let mut key_reader: BufReader<File> = BufReader::new(File::open(key_path)?);
+  let key: PrivateKeyDer<'static> =
+    rustls_pemfile::private_key(&mut key_reader)?.ok_or(TlsError::NoPrivateKey)?;
+
+  let provider = Arc::new(rustls::crypto::ring::default_provider());
+  let mut config: ServerConfig = ServerConfig::builder_with_provider(provider)
+    .with_safe_default_protocol_versions()?
+    .with_no_client_auth()
+    .with_single_cert(certs, key)?;
+  config.alpn_protocols = vec![ALPN_HTTP_1_1.to_vec()];
+
+  Ok(Arc::new(config))
+} // end fn load_config()
+
+/// Performs the TLS handshake on an accepted connection.
+///
+/// # Arguments
+///
+/// * `config`: Settings of the server.
+/// * `stream`: Connection with the client.
+pub fn accept(
+  config: Arc<ServerConfig>,
+  mut stream: TcpStream,
+) -> io::Result<TlsStream> {
+  let mut connection: ServerConnection =
+    ServerConnection::new(config).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+  while connection.is_handshaking() {
+    connection.complete_io(&mut stream)?;
+  }
+
+  Ok(StreamOwned::new(connection, stream))
+}
+
+/// Tells the client the connection is closing, so it can tell a complete response from
+/// a truncated one.
+///
+/// # Arguments
+///
+/// * `stream`: TLS stream with the client.
+pub fn close(stream: &mut TlsStream) {
+  stream.conn.send_close_notify();
+  let _ = stream.flush();
+}
+
+/// Creates the response redirecting a plaintext request to the same resource over
+/// HTTPS: "301 Moved Permanently" for GET and HEAD, and "308 Permanent Redirect", which
+/// keeps the method and body, otherwise.
+///
+/// # Arguments
+///
+/// * `request`: HTTP request received on the plaintext listener.
+/// * `https_port`: Port of the HTTPS listener.
+pub fn redirect_response(
+  request: &HttpRequest,
+  https_port: u16,
+) -> HttpResponse {
+  let host: &str = request.headers.host().unwrap_or("localhost");
+  // Drop the port of the plaintext listener, keeping IPv6 addresses whole
+  let host: &str = match host.rfind(':') {
+    Some(i) if !host[i..].contains(']') => &host[..i],
+    _ => host,
+  };
+  let location: String = match https_port {
+    443 => format!("https://{}{}", host, request.resource.target),
+    port => format!("https://{}:{}{}", host, port, request.resource.target),
+  };
+
+  let status_code: StatusCode = match request.method {
+    Method::GET | Method::HEAD => StatusCode::MOVED_PERMANENTLY,
+    _ => StatusCode::PERMANENT_REDIRECT,
+  };
+  let mut headers: HeaderMap = HeaderMap::new();
+  headers.insert("Location", &location);
+  headers.insert("Connection", "close");
+  HttpResponse::new(status_code, Some(headers), Body::Empty)
+} // end fn redirect_response()
+
+/// Starts a thread listening for plaintext connections and redirecting every request
+/// to the HTTPS listener. Each connection gets a single response.
+///
+/// # Arguments
+///
+/// * `address`: Socket address of the plaintext listener.
+/// * `https_port`: Port of the HTTPS listener.
+pub fn run_redirect(
+  address: &str,
+  https_port: u16,
+) -> io::Result<()> {
+  let listener: TcpListener = TcpListener::bind(address)?;
+  info!("Redirecting {} to HTTPS", listener.local_addr()?);
+
+  thread::spawn(move || {
+    let pool: ThreadPool<TcpStream> =
+      ThreadPool::new(REDIRECT_WORKERS, 64, move |mut stream: TcpStream| {
+        if stream.set_read_timeout(Some(REDIRECT_TIMEOUT)).is_err() {
+          return;
+        }
+        let mut reader: RequestReader = RequestReader::new(RequestLimits::default());
+        if let Ok(request) = reader.read_request(&mut stream) {
+          let response: HttpResponse = redirect_response(&request, https_port);
+          let _ = match request.method {
+            Method::HEAD => response.send_head(&mut stream),
+            _ => response.send_response(&mut stream),
+          };
+        }
+      });
+
+    for stream in listener.incoming().flatten() {
+      // Drop the connection when every worker is busy
+      let _ = pool.execute(stream);
+    }
+  });
+  Ok(())
+} // end fn run_redirect()
+
+/// Writes a self-signed certificate for `localhost` and its key to PEM files in a new
+/// temporary directory, and returns their paths.
+#[cfg(test)]
+pub fn self_signed_files() -> (std::path::PathBuf, std::path::PathBuf) {
+  use std::sync::atomic::{AtomicUsize, Ordering};
+  static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+  let dir = std::env::temp_dir().join(format!(
+    "http_server_tls_{}_{}",
+    std::process::id(),
+    COUNTER.fetch_add(1, Ordering::SeqCst)
+  ));
+  std::fs::create_dir_all(&dir).unwrap();
+
+  let certified =
+    rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+  let (cert_path, key_path) = (dir.join("cert.pem"), dir.join("key.pem"));
+  std::fs::write(&cert_path, certified.cert.pem()).unwrap();
+  std::fs::write(&key_path, certified.key_pair.serialize_pem()).unwrap();
+  (cert_path, key_path)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_load_config() {
+    let (cert_path, key_path) = self_signed_files();
+
+    let config = load_config(&cert_path, &key_path).unwrap();
+    assert_eq!(config.alpn_protocols, vec![b"http/1.1".to_vec()]);
+
+    // The files swapped: no certificate in the key file and no key in the certificate
+    assert!(matches!(
+      load_config(&key_path, &key_path),
+      Err(TlsError::NoCertificate)
+    ));
+    assert!(matches!(
+      load_config(&cert_path, &cert_path),
+      Err(TlsError::NoPrivateKey)
+    ));
+    assert!(matches!(
+      load_config(&cert_path.with_extension("missing"), &key_path),
+      Err(TlsError::Io(_))
+    ));
+  }
+
+  #[test]
+  fn test_redirect_response() {
+    let (request, _) =
+      HttpRequest::parse(b"GET /index.html?x=1 HTTP/1.1\r\nHost: localhost:3080\r\n\r\n")
+        .unwrap();
+    let response = redirect_response(&request, 3443);
+    assert_eq!(response.status_code(), &StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(
+      response.header("Location"),
+      Some("https://localhost:3443/index.html?x=1")
+    );
+
+    let (request, _) = HttpRequest::parse(
+      b"POST /api HTTP/1.1\r\nHost: [::1]:80\r\nContent-Length: 0\r\n\r\n",
+    )
+    .unwrap();
+    let response = redirect_response(&request, 443);
+    assert_eq!(response.status_code(), &StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(response.header("Location"), Some("https://[::1]/api"));
+  }
+}
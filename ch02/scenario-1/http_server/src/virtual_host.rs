@@ -0,0 +1,509 @@
+use std::path::PathBuf;
+
+use http::body::Body;
+use http::http_request::{HttpRequest, Method};
+use http::http_response::HttpResponse;
+use http::status_code::StatusCode;
+use serde::Deserialize;
+
+use crate::config::HostSettings;
+use crate::router::{self, HandlerFn, RoutePattern, Router, WebSocketFn};
+use crate::static_files;
+
+/// Represents how requests for a host no virtual host is named after are answered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnknownHost {
+  /// Served by the default virtual host.
+  #[default]
+  Default,
+  /// Answered with "421 Misdirected Request".
+  Misdirected,
+  /// Answered with "404 Not Found".
+  NotFound,
+}
+
+/// Represents a site served by the server, chosen by the `Host` header of the
+/// requests.
+#[derive(Debug, Clone)]
+pub struct VirtualHost {
+  /// Lowercase host names, exact (`example.com`) or wildcards (`*.example.com`).
+  names: Vec<String>,
+  /// Directory of the static files, if not the server one.
+  public_root: Option<PathBuf>,
+  /// Routes of the site.
+  router: Router,
+}
+
+impl VirtualHost {
+  /// Creates a [`VirtualHost`] object.
+  ///
+  /// # Arguments
+  ///
+  /// * `names`: Host names, exact (`example.com`) or wildcards (`*.example.com`).
+  /// * `public_root`: Directory of the static files, if not the server one.
+  /// * `router`: Routes of the site.
+  pub fn new(
+    names: &[&str],
+    public_root: Option<PathBuf>,
+    router: Router,
+  ) -> Self {
+    Self {
+      names: names.iter().map(|name| name.to_ascii_lowercase()).collect(),
+      public_root,
+      router,
+    }
+  }
+
+  /// Routes the given request with the routes and the public directory of the site.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request to route.
+  fn route(
+    &self,
+    request: &mut HttpRequest,
+  ) -> HttpResponse {
+    match &self.public_root {
+      Some(root) => static_files::with_public_root(root, || self.router.route(request)),
+      None => self.router.route(request),
+    }
+  }
+}
+
+/// Represents the sites served by the server, and the policy for requests naming none
+/// of them.
+#[derive(Debug, Clone)]
+pub struct VirtualHosts {
+  /// Sites, in the configured order.
+  hosts: Vec<VirtualHost>,
+  /// Index of the site serving the requests for unknown hosts.
+  default: usize,
+  /// How requests for unknown hosts are answered.
+  unknown_host: UnknownHost,
+}
+
+impl VirtualHosts {
+  /// Creates a [`VirtualHosts`] object.
+  ///
+  /// # Arguments
+  ///
+  /// * `hosts`: Sites, at least one.
+  /// * `default`: Index of the site serving the requests for unknown hosts.
+  /// * `unknown_host`: How requests for unknown hosts are answered.
+  ///
+  /// # Panics
+  ///
+  /// Panics if there are no sites or the default one does not exist.
+  pub fn new(
+    hosts: Vec<VirtualHost>,
+    default: usize,
+    unknown_host: UnknownHost,
+  ) -> Self {
+    assert!(default < hosts.len(), "the default virtual host must exist");
+    Self {
+      hosts,
+      default,
+      unknown_host,
+    }
+  }
+
+  /// Builds the sites from the `[[hosts]]` sections of the configuration. The default
+  /// site is the one with `default = true`, or else the first one.
+  ///
+  /// # Arguments
+  ///
+  /// * `settings`: Settings of every site.
+  /// * `unknown_host`: How requests for unknown hosts are answered.
+  pub fn from_settings(
+    settings: &[HostSettings],
+    unknown_host: UnknownHost,
+  ) -> Result<Self, Vec<String>> {
+    let mut problems: Vec<String> = Vec::new();
+    let mut hosts: Vec<VirtualHost> = Vec::new();
+    let mut seen: Vec<String> = Vec::new();
+
+    for (i, host) in settings.iter().enumerate() {
+      if host.names.is_empty() {
+        problems.push(format!("hosts[{}] has no names", i));
+      }
+      for name in &host.names {
+        let name: String = name.to_ascii_lowercase();
+        if !is_valid_name(&name) {
+          problems.push(format!("hosts[{}] name '{}' is invalid", i, name));
+        } else if seen.contains(&name) {
+          problems.push(format!("host name '{}' is given twice", name));
+        }
+        seen.push(name);
+      }
+      if let Some(public) = &host.public {
+        if !public.is_dir() {
+          problems.push(format!(
+            "hosts[{}] public directory {} does not exist",
+            i,
+            public.display()
+          ));
+        }
+      }
+
+      let router: Router = match &host.routes {
+        None => Router::default(),
+        Some(routes) => {
+          let mut router: Router = Router::new();
+          for route in routes {
+            let method: Method = Method::from(route.method.as_str());
+            if method == Method::UNINITIALIZED {
+              problems.push(format!(
+                "hosts[{}] route method '{}' is invalid",
+                i, route.method
+              ));
+            }
+            if let Err(e) = RoutePattern::try_parse(&route.path) {
+              problems.push(format!("hosts[{}] {}", i, e));
+              continue;
+            }
+            if let Some(handler) = router::named_websocket_handler(&route.handler) {
+              router = router.websocket(&route.path, handler);
+              continue;
+            }
+            let Some(handler) = router::named_handler(&route.handler) else {
+              problems.push(format!(
+                "hosts[{}] route handler '{}' is unknown",
+                i, route.handler
+              ));
+              continue;
+            };
+            router = router.add(method, &route.path, handler as HandlerFn);
+          }
+          router
+        }
+      };
+
+      let names: Vec<&str> = host.names.iter().map(String::as_str).collect();
+      hosts.push(VirtualHost::new(&names, host.public.clone(), router));
+    }
+
+    if settings.iter().filter(|host| host.default).count() > 1 {
+      problems.push("only one host can be the default one".to_string());
+    }
+    if settings.is_empty() {
+      problems.push("at least one host is required".to_string());
+    }
+
+    match problems.is_empty() {
+      true => {
+        let default: usize = settings.iter().position(|host| host.default).unwrap_or(0);
+        Ok(VirtualHosts::new(hosts, default, unknown_host))
+      }
+      false => Err(problems),
+    }
+  } // end fn from_settings()
+
+  /// Finds the site named after the host of the given request. Exact names take
+  /// precedence over wildcards, and longer wildcards over shorter ones.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request.
+  fn find(
+    &self,
+    request: &HttpRequest,
+  ) -> Option<&VirtualHost> {
+    let host: String = request_host(request)?;
+
+    if let Some(exact) = self.hosts.iter().find(|h| h.names.contains(&host)) {
+      return Some(exact);
+    }
+    self
+      .hosts
+      .iter()
+      .flat_map(|h| h.names.iter().map(move |name| (h, name)))
+      .filter_map(|(h, name)| {
+        let suffix: &str = name.strip_prefix('*')?;
+        (host.len() > suffix.len() && host.ends_with(suffix)).then_some((h, suffix.len()))
+      })
+      .max_by_key(|(_, length)| *length)
+      .map(|(h, _)| h)
+  }
+
+  /// Routes the given request to the site named after its host and returns the
+  /// response. Requests for unknown hosts, or without a host, follow the configured
+  /// policy.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request to route.
+  pub fn route(
+    &self,
+    request: &mut HttpRequest,
+  ) -> HttpResponse {
+    match self.find(request) {
+      Some(host) => host.route(request),
+      None => match self.unknown_host {
+        UnknownHost::Default => self.hosts[self.default].route(request),
+        UnknownHost::Misdirected => {
+          HttpResponse::new(StatusCode::MISDIRECTED_REQUEST, None, Body::Empty)
+        }
+        UnknownHost::NotFound => {
+          HttpResponse::new(StatusCode::NOT_FOUND, None, Body::Empty)
+        }
+      },
+    }
+  }
+
+  /// Finds the handler of the WebSocket route matching the given request, in the site
+  /// it was routed to.
+  ///
+  /// # Arguments
+  ///
+  /// * `request`: HTTP request upgraded.
+  pub fn websocket_handler(
+    &self,
+    request: &HttpRequest,
+  ) -> Option<WebSocketFn> {
+    let host: &VirtualHost = match (self.find(request), self.unknown_host) {
+      (Some(host), _) => host,
+      (None, UnknownHost::Default) => &self.hosts[self.default],
+      (None, _) => return None,
+    };
+    host.router.websocket_handler(request)
+  }
+}
+
+impl Default for VirtualHosts {
+  /// Creates a single site with the routes of the application, serving every host.
+  fn default() -> Self {
+    VirtualHosts::new(
+      vec![VirtualHost::new(&[], None, Router::default())],
+      0,
+      UnknownHost::Default,
+    )
+  }
+}
+
+/// Gets the host a request is meant for, lowercase and without the port or a trailing
+/// dot. The authority of an absolute-form target takes precedence over the `Host`
+/// header.
+///
+/// # Arguments
+///
+/// * `request`: HTTP request.
+fn request_host(request: &HttpRequest) -> Option<String> {
+  let authority: &str = match request.resource.target.split_once("://") {
+    Some((_, rest)) => {
+      let end: usize = rest.find(['/', '?']).unwrap_or(rest.len());
+      let authority: &str = &rest[..end];
+      authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host)
+    }
+    None => request.headers.host()?,
+  };
+
+  // Drop the port, keeping IPv6 addresses whole
+  let host: &str = match authority.rfind(':') {
+    Some(i) if !authority[i..].contains(']') => &authority[..i],
+    _ => authority,
+  };
+  let host: &str = host.trim().trim_end_matches('.');
+  match host.is_empty() {
+    true => None,
+    false => Some(host.to_ascii_lowercase()),
+  }
+}
+
+/// Checks a lowercase host name of the configuration: a domain name or an IP address,
+/// optionally preceded by `*.`.
+///
+/// # Arguments
+///
+/// * `name`: Host name.
+fn is_valid_name(name: &str) -> bool {
+  let name: &str = name.strip_prefix("*.").unwrap_or(name);
+  if let Some(address) = name.strip_prefix('[').and_then(|n| n.strip_suffix(']')) {
+    return address.parse::<std::net::Ipv6Addr>().is_ok();
+  }
+  !name.is_empty()
+    && name.split('.').all(|label| {
+      !label.is_empty()
+        && label
+          .bytes()
+          .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+    })
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{env, fs};
+
+  use super::*;
+  use crate::config::RouteSettings;
+
+  fn request(text: &str) -> HttpRequest {
+    HttpRequest::parse(text.as_bytes()).unwrap().0
+  }
+
+  fn settings(
+    names: &[&str],
+    routes: Option<Vec<RouteSettings>>,
+  ) -> HostSettings {
+    HostSettings {
+      names: names.iter().map(|name| name.to_string()).collect(),
+      public: None,
+      default: false,
+      routes,
+    }
+  }
+
+  fn route(
+    method: &str,
+    path: &str,
+    handler: &str,
+  ) -> RouteSettings {
+    RouteSettings {
+      method: method.to_string(),
+      path: path.to_string(),
+      handler: handler.to_string(),
+    }
+  }
+
+  #[test]
+  fn test_request_host() {
+    let host = |text: &str| request_host(&request(text));
+    assert_eq!(
+      host("GET / HTTP/1.1\r\nHost: Example.COM:8080\r\n\r\n"),
+      Some("example.com".to_string())
+    );
+    assert_eq!(
+      host("GET / HTTP/1.1\r\nHost: example.com.\r\n\r\n"),
+      Some("example.com".to_string())
+    );
+    assert_eq!(
+      host("GET / HTTP/1.1\r\nHost: [::1]:3000\r\n\r\n"),
+      Some("[::1]".to_string())
+    );
+    // The authority of an absolute-form target wins over the header
+    assert_eq!(
+      host("GET http://api.example.com/x HTTP/1.1\r\nHost: other\r\n\r\n"),
+      Some("api.example.com".to_string())
+    );
+    assert_eq!(host("GET / HTTP/1.0\r\n\r\n"), None);
+  }
+
+  #[test]
+  fn test_host_matching() {
+    let hosts = VirtualHosts::from_settings(
+      &[
+        settings(&["example.com", "www.example.com"], None),
+        settings(&["*.example.com"], Some(vec![])),
+        settings(&["*.api.example.com", "api.example.com"], Some(vec![])),
+      ],
+      UnknownHost::Misdirected,
+    )
+    .unwrap();
+    let find = |host: &str| {
+      let request = request(&format!("GET / HTTP/1.1\r\nHost: {}\r\n\r\n", host));
+      hosts.find(&request).map(|h| h.names[0].clone())
+    };
+
+    assert_eq!(find("WWW.example.com").as_deref(), Some("example.com"));
+    assert_eq!(find("blog.example.com").as_deref(), Some("*.example.com"));
+    assert_eq!(
+      find("api.example.com").as_deref(),
+      Some("*.api.example.com")
+    );
+    assert_eq!(
+      find("v1.api.example.com").as_deref(),
+      Some("*.api.example.com")
+    );
+    assert_eq!(find("example.org"), None);
+  }
+
+  #[test]
+  fn test_unknown_host_policy() {
+    let unknown = "GET /api/shipping/orders HTTP/1.1\r\nHost: example.org\r\n\r\n";
+    let hosts_with = |policy: UnknownHost| {
+      VirtualHosts::from_settings(
+        &[
+          settings(&["example.com"], None),
+          settings(&["empty.example.com"], Some(vec![])),
+        ],
+        policy,
+      )
+      .unwrap()
+    };
+
+    let response = hosts_with(UnknownHost::Misdirected).route(&mut request(unknown));
+    assert_eq!(response.status_code(), &StatusCode::MISDIRECTED_REQUEST);
+    let response = hosts_with(UnknownHost::NotFound).route(&mut request(unknown));
+    assert_eq!(response.status_code(), &StatusCode::NOT_FOUND);
+
+    // The first site is the default one, unless another one says otherwise
+    let response = hosts_with(UnknownHost::Default).route(&mut request(unknown));
+    assert_eq!(response.status_code(), &StatusCode::OK);
+    let mut empty_default = settings(&["empty.example.com"], Some(vec![]));
+    empty_default.default = true;
+    let hosts = VirtualHosts::from_settings(
+      &[settings(&["example.com"], None), empty_default],
+      UnknownHost::Default,
+    )
+    .unwrap();
+    let response = hosts.route(&mut request("GET / HTTP/1.1\r\n\r\n"));
+    assert_eq!(response.status_code(), &StatusCode::NOT_FOUND);
+  }
+
+  #[test]
+  fn test_per_host_routes_and_root() {
+    let root = env::temp_dir().join(format!("vhost-{}", std::process::id()));
+    fs::create_dir_all(&root).unwrap();
+    fs::write(root.join("index.html"), "blog home").unwrap();
+
+    let mut blog = settings(
+      &["blog.example.com"],
+      Some(vec![route("GET", "/{*path}", "static")]),
+    );
+    blog.public = Some(root.clone());
+    let hosts = VirtualHosts::from_settings(
+      &[settings(&["example.com"], None), blog],
+      UnknownHost::NotFound,
+    )
+    .unwrap();
+
+    let mut blog_home = request("GET / HTTP/1.1\r\nHost: blog.example.com\r\n\r\n");
+    let response = hosts.route(&mut blog_home);
+    assert_eq!(response.status_code(), &StatusCode::OK);
+    assert_eq!(response.body().len(), Some("blog home".len() as u64));
+
+    // The orders API is not routed on the blog, and the root is restored afterwards
+    let mut orders =
+      request("GET /api/shipping/orders HTTP/1.1\r\nHost: blog.example.com\r\n\r\n");
+    assert_eq!(
+      hosts.route(&mut orders).status_code(),
+      &StatusCode::NOT_FOUND
+    );
+    assert_eq!(static_files::public_root(), crate::config::paths().public);
+  }
+
+  #[test]
+  fn test_invalid_settings() {
+    let mut first = settings(&["example.com", "bad_name"], None);
+    first.default = true;
+    first.public = Some(PathBuf::from("/nonexistent"));
+    let mut second = settings(
+      &["EXAMPLE.com"],
+      Some(vec![
+        route("", "/", "static"),
+        route("GET", "/{*path}/x", "static"),
+        route("GET", "/", "shell"),
+      ]),
+    );
+    second.default = true;
+
+    let problems =
+      VirtualHosts::from_settings(&[first, second], UnknownHost::Default).unwrap_err();
+    assert_eq!(problems.len(), 7, "{:?}", problems);
+    assert!(problems[0].contains("bad_name"));
+    assert!(problems[2].contains("given twice"));
+    assert!(problems[6].contains("default"));
+  }
+}
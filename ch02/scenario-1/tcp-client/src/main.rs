@@ -1,23 +1,222 @@
-use std::io::{Read, Write};
-use std::net::TcpStream;
-use std::str;
+use std::io::{self, Write};
+use std::process::ExitCode;
+use std::time::Duration;
+use std::{env, fs};
 
-fn main() {
-  // Create the TCP client
-  let server_address: &str = "127.0.0.1:3000";
-  let mut stream: TcpStream = TcpStream::connect(&server_address).unwrap();
+use http::{
+  client::{Client, ClientError},
+  http_request::Method,
+  http_response::HttpResponse,
+};
 
-  // Send a message to the server
-  stream.write("Hello you server".as_bytes()).unwrap();
+/// Usage text printed by `--help` and on invalid arguments.
+const USAGE: &str = "Usage: tcp-client [options] <url>
 
-  // Read the response from the server
-  let mut buffer: [u8; 20] = [0; 20];
-  let bytes_count: usize = stream.read(&mut buffer).unwrap();
+Options:
+  -X, --request <method>   HTTP method (default: GET, or POST with a body)
+  -H, --header <name: value>
+                           Add a request header (repeatable)
+  -d, --data <body>        Request body; @file reads it from a file
+  -i, --include            Print the status line and headers before the body
+  -I, --head               Send a HEAD request and print the headers
+  -L, --location           Follow redirects
+      --max-redirs <n>     Maximum number of redirects to follow (default: 10)
+  -m, --max-time <secs>    Timeout to connect and for each read or write
+  -v, --verbose            Print the request line and headers to stderr
+  -h, --help               Show this help";
 
-  println!("{}", bytes_count);
+/// Represents the options given on the command line.
+struct Options {
+  /// HTTP method of the request.
+  method: Option<Method>,
+  /// Headers of the request.
+  headers: Vec<(String, String)>,
+  /// Body of the request.
+  body: Option<String>,
+  /// Whether to print the status line and headers.
+  include: bool,
+  /// Whether to send a HEAD request.
+  head: bool,
+  /// Whether to follow redirects.
+  follow: bool,
+  /// Maximum number of redirects to follow.
+  max_redirects: usize,
+  /// Timeout for each operation.
+  timeout: Option<Duration>,
+  /// Whether to print the request to stderr.
+  verbose: bool,
+  /// URL of the request.
+  url: String,
+}
+
+/// Parses the command line arguments, without the program name.
+///
+/// # Arguments
+///
+/// * `args`: Command line arguments.
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+  let mut options = Options {
+    method: None,
+    headers: Vec::new(),
+    body: None,
+    include: false,
+    head: false,
+    follow: false,
+    max_redirects: 10,
+    timeout: None,
+    verbose: false,
+    url: "".to_string(),
+  };
+  let mut args = args.into_iter();
+
+  while let Some(arg) = args.next() {
+    let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name));
+    match arg.as_str() {
+      "-X" | "--request" => options.method = Some(Method::from(value(&arg)?.as_str())),
+      "-H" | "--header" => {
+        let header: String = value(&arg)?;
+        let (name, value) = header
+          .split_once(':')
+          .ok_or(format!("invalid header: {}", header))?;
+        options
+          .headers
+          .push((name.trim().to_string(), value.trim().to_string()));
+      }
+      "-d" | "--data" => {
+        let data: String = value(&arg)?;
+        options.body = Some(match data.strip_prefix('@') {
+          Some(path) => {
+            fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?
+          }
+          None => data,
+        });
+      }
+      "-i" | "--include" => options.include = true,
+      "-I" | "--head" => options.head = true,
+      "-L" | "--location" => options.follow = true,
+      "--max-redirs" => {
+        options.max_redirects = value(&arg)?
+          .parse()
+          .map_err(|_| "invalid --max-redirs".to_string())?
+      }
+      "-m" | "--max-time" => {
+        let seconds: f64 = value(&arg)?
+          .parse()
+          .map_err(|_| "invalid --max-time".to_string())?;
+        if !seconds.is_finite() || seconds <= 0.0 {
+          return Err("invalid --max-time".to_string());
+        }
+        options.timeout = Some(Duration::from_secs_f64(seconds));
+      }
+      "-v" | "--verbose" => options.verbose = true,
+      "-h" | "--help" => return Err("".to_string()),
+      _ if arg.starts_with('-') => return Err(format!("unknown option: {}", arg)),
+      _ if options.url.is_empty() => options.url = arg,
+      _ => return Err(format!("unexpected argument: {}", arg)),
+    }
+  }
+
+  if options.url.is_empty() {
+    return Err("no URL given".to_string());
+  }
+  // Accept "localhost:3000/path" like curl does
+  if !options.url.contains("://") {
+    options.url = format!("http://{}", options.url);
+  }
+  Ok(options)
+} // end fn parse_args()
+
+/// Sends the request described by the options.
+///
+/// # Arguments
+///
+/// * `options`: Options given on the command line.
+fn run(options: &Options) -> Result<HttpResponse, ClientError> {
+  let mut client: Client = Client::new().with_max_redirects(match options.follow {
+    true => options.max_redirects,
+    false => 0,
+  });
+  if let Some(timeout) = options.timeout {
+    client = client.with_timeout(timeout);
+  }
+
+  let method: Method = match (&options.method, options.head, &options.body) {
+    (Some(method), _, _) => method.clone(),
+    (None, true, _) => Method::HEAD,
+    (None, false, Some(_)) => Method::POST,
+    (None, false, None) => Method::GET,
+  };
+  if options.verbose {
+    eprintln!("> {} {}", method, options.url);
+  }
+
+  let mut request = client.request(method, &options.url);
+  for (name, value) in &options.headers {
+    if options.verbose {
+      eprintln!("> {}: {}", name, value);
+    }
+    request = request.with_header(name, value);
+  }
+  if let Some(body) = &options.body {
+    request = request.with_body(body.as_str());
+  }
+  request.send()
+}
+
+fn main() -> ExitCode {
+  let options: Options = match parse_args(env::args().skip(1)) {
+    Ok(options) => options,
+    Err(message) if message.is_empty() => {
+      println!("{}", USAGE);
+      return ExitCode::SUCCESS;
+    }
+    Err(message) => {
+      eprintln!("tcp-client: {}\n\n{}", message, USAGE);
+      return ExitCode::from(2);
+    }
+  };
+
+  let response: HttpResponse = match run(&options) {
+    Ok(response) => response,
+    Err(e) => {
+      eprintln!("tcp-client: {}", e);
+      return ExitCode::FAILURE;
+    }
+  };
+
+  match print_response(&options, response) {
+    Ok(()) => ExitCode::SUCCESS,
+    Err(e) => {
+      eprintln!("tcp-client: {}", e);
+      ExitCode::FAILURE
+    }
+  }
+} // end fn main()
+
+/// Prints the body of a response to stdout, preceded by the status line and headers if
+/// requested. With `--verbose` alone, the status line and headers go to stderr.
+///
+/// # Arguments
+///
+/// * `options`: Options given on the command line.
+/// * `response`: Response to print.
+fn print_response(
+  options: &Options,
+  mut response: HttpResponse,
+) -> io::Result<()> {
+  let mut head: Vec<String> = vec![format!("HTTP/1.1 {}", response.status_code())];
+  for (name, value) in response.headers().iter() {
+    head.push(format!("{}: {}", name, value));
+  }
 
-  println!(
-    "Received a response from the server: {:?}",
-    str::from_utf8(&buffer).unwrap().trim()
-  );
+  let mut stdout = io::stdout().lock();
+  if options.include || options.head {
+    stdout.write_all(format!("{}\r\n\r\n", head.join("\r\n")).as_bytes())?;
+  } else if options.verbose {
+    for line in head {
+      eprintln!("< {}", line);
+    }
+  }
+  response.take_body().write_to(&mut stdout)?;
+  stdout.flush()
 }
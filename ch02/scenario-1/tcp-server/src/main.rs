@@ -1,23 +1,86 @@
-use std::io::{Read, Write};
+use http::http_request::{HttpRequest, ParseError};
+use http::http_response::HttpResponse;
+use http::websocket::{self, Message, Role, WebSocket, WebSocketError};
+use std::io::Read;
 use std::net::TcpListener;
 use std::net::TcpStream;
+use std::thread;
+
+/// Maximum number of bytes of the handshake request.
+const MAX_REQUEST_SIZE: usize = 8192;
 
 fn main() {
   // Create the TCP Server
   let socket_address: &str = "127.0.0.1:3000";
-  let connection_listener: TcpListener = TcpListener::bind(&socket_address).unwrap();
-  println!("Running TCP Server on {}", &socket_address);
+  let connection_listener: TcpListener = TcpListener::bind(socket_address).unwrap();
+  println!("Running WebSocket echo server on ws://{}", &socket_address);
 
   // Wait for TCP client connections
   for stream in connection_listener.incoming() {
     // Stream of bytes comming from the client
-    let mut stream: TcpStream = stream.unwrap();
+    let stream: TcpStream = match stream {
+      Ok(stream) => stream,
+      Err(e) => {
+        println!("Failed to accept a connection: {}", e);
+        continue;
+      }
+    };
     println!("Connection established with a client.");
 
-    // Read data from the stream comming from the client
-    let mut buffer: [u8; 1024] = [0; 1024];
-    stream.read(&mut buffer).unwrap();
-    // Send data to the stream and to the client
-    stream.write(&mut buffer).unwrap();
+    thread::spawn(move || {
+      if let Err(e) = echo(stream) {
+        println!("Connection closed: {}", e);
+      }
+    });
+  }
+}
+
+/// Upgrades the connection to a WebSocket, then sends every text and binary message
+/// back to the client until it closes the connection.
+///
+/// # Arguments
+///
+/// * `stream`: Stream connected to the client.
+fn echo(mut stream: TcpStream) -> Result<(), WebSocketError> {
+  let request: HttpRequest = read_request(&mut stream)?;
+  let response: HttpResponse = match websocket::handshake(&request) {
+    Ok(response) => response,
+    Err(e) => {
+      e.response().send_response(&mut stream)?;
+      return Err(WebSocketError::Handshake(format!("{:?}", e)));
+    }
+  };
+  response.send_response(&mut stream)?;
+
+  let mut socket: WebSocket<TcpStream> = WebSocket::new(stream, Role::Server);
+  loop {
+    match socket.read_message()? {
+      message @ (Message::Text(_) | Message::Binary(_)) => socket.send(message)?,
+      Message::Close(_) => return Ok(()),
+      // Pings are answered by the socket itself
+      Message::Ping(_) | Message::Pong(_) => {}
+    }
+  }
+} // end fn echo()
+
+/// Reads the handshake request sent by the client.
+///
+/// # Arguments
+///
+/// * `stream`: Stream connected to the client.
+fn read_request(stream: &mut TcpStream) -> Result<HttpRequest, WebSocketError> {
+  let mut buffer: Vec<u8> = Vec::new();
+  loop {
+    match HttpRequest::parse(&buffer) {
+      Ok((request, _)) => return Ok(request),
+      Err(ParseError::Incomplete { .. }) if buffer.len() <= MAX_REQUEST_SIZE => {}
+      Err(e) => return Err(WebSocketError::Handshake(format!("{:?}", e))),
+    }
+
+    let mut chunk: [u8; 1024] = [0; 1024];
+    match stream.read(&mut chunk)? {
+      0 => return Err(WebSocketError::Closed),
+      bytes_count => buffer.extend_from_slice(&chunk[..bytes_count]),
+    }
   }
 }